@@ -0,0 +1,466 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::{
+    assets::{AbilityData, AssetIndex, TargetType},
+    chat::ChatLog,
+    components::*,
+    effects,
+    time::GameTime,
+    ui, AttackCommand, MovementCommand,
+};
+
+/// Time after any cast before the next ability can be cast, in milliseconds
+pub const GLOBAL_COOLDOWN: u32 = 1000;
+const CAST_BAR_COLOR: Color = Color::RGB(220, 170, 40);
+
+/// Starts, advances and interrupts casts. Casting reads the attack command of the tick,
+/// walking or being stunned interrupts a cast with a cast time.
+pub struct AbilitySystem;
+
+impl<'a> System<'a> for AbilitySystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Abilities>,
+        WriteStorage<'a, Status>,
+        ReadStorage<'a, Effects>,
+        Read<'a, AssetIndex>,
+        Read<'a, GameTime>,
+        Read<'a, Target>,
+        ReadExpect<'a, Option<AttackCommand>>,
+        ReadExpect<'a, Option<MovementCommand>>,
+        Write<'a, CastEvents>,
+        Write<'a, CastRequests>,
+        Write<'a, ChatLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            players,
+            positions,
+            mut abilities,
+            mut statuses,
+            effects,
+            index,
+            time,
+            target,
+            attack,
+            movement,
+            mut events,
+            mut requests,
+            mut log,
+        ) = data;
+        events.0.clear();
+        requests.0.clear();
+        let moving = matches!(*movement, Some(MovementCommand::Move(_)));
+        let delta = time.delta_ms();
+
+        for (caster, player, book) in (&entities, &players, &mut abilities).join() {
+            for remaining in book.cooldowns.values_mut() {
+                *remaining = remaining.saturating_sub(delta);
+            }
+            book.cooldowns.retain(|_, remaining| *remaining > 0);
+            book.global_cooldown = book.global_cooldown.saturating_sub(delta);
+            // Only the player's own casts come from this client
+            if !player.is_local() {
+                continue;
+            }
+
+            let stunned = effects
+                .get(caster)
+                .is_some_and(|effects| effects::is_stunned(effects, &index));
+            if book.casting.is_some() && (moving || stunned) {
+                book.casting = None;
+                requests.0.push(CastRequest::Interrupt);
+                log.system("Interrupted");
+            } else if let Some(cast) = &mut book.casting {
+                cast.elapsed += delta;
+                if cast.elapsed >= cast.cast_time {
+                    let cast = book.casting.take().unwrap();
+                    let event = finish(
+                        caster,
+                        &cast.ability,
+                        cast.target,
+                        book,
+                        &mut statuses,
+                        &index,
+                    );
+                    events.0.extend(event);
+                }
+            }
+
+            let ability = match &*attack {
+                Some(AttackCommand::Cast(ability)) => ability,
+                _ => continue,
+            };
+            if stunned {
+                log.system("You can't do that while stunned");
+                continue;
+            }
+            let start = start_cast(
+                (caster, player),
+                ability,
+                book,
+                moving,
+                target.0,
+                (&entities, &players, &positions, &statuses),
+                &index,
+            );
+            match start {
+                Ok(cast) => {
+                    requests.0.push(CastRequest::Start {
+                        ability: cast.ability.clone(),
+                        target: cast
+                            .target
+                            .and_then(|target| players.get(target))
+                            .map(|target| target.id.clone())
+                            .unwrap_or_default(),
+                    });
+                    book.global_cooldown = GLOBAL_COOLDOWN;
+                    if cast.cast_time == 0 {
+                        let event = finish(
+                            caster,
+                            &cast.ability,
+                            cast.target,
+                            book,
+                            &mut statuses,
+                            &index,
+                        );
+                        events.0.extend(event);
+                    } else {
+                        book.casting = Some(cast);
+                    }
+                }
+                Err(reason) => log.system(reason),
+            }
+        }
+    }
+}
+
+type Units<'s, 'a> = (
+    &'s Entities<'a>,
+    &'s ReadStorage<'a, Player>,
+    &'s ReadStorage<'a, Position>,
+    &'s WriteStorage<'a, Status>,
+);
+
+/// Checks whether `caster` can cast `ability` right now and picks its target
+fn start_cast(
+    (caster, player): (Entity, &Player),
+    ability: &str,
+    book: &Abilities,
+    moving: bool,
+    selected: Option<Entity>,
+    (entities, players, positions, statuses): Units,
+    index: &AssetIndex,
+) -> Result<Cast, &'static str> {
+    let data = match index.abilities.get(ability) {
+        Some(data) if book.known.iter().any(|known| known == ability) => data,
+        _ => return Err("You don't know that ability"),
+    };
+    if book.casting.is_some() {
+        return Err("You are already casting");
+    }
+    if book.remaining_cooldown(ability) > 0 {
+        return Err("That ability isn't ready yet");
+    }
+    if data.cast_time > 0 && moving {
+        return Err("You can't cast that while moving");
+    }
+    if let Some(status) = statuses.get(caster) {
+        if !status.alive {
+            return Err("You are dead");
+        }
+        if !status.can_afford(data.cost) {
+            return Err(match status.power.as_ref().map(|power| power.kind) {
+                Some(PowerKind::Energy) => "Not enough energy",
+                Some(PowerKind::Rage) => "Not enough rage",
+                _ => "Not enough mana",
+            });
+        }
+    }
+
+    let selected = selected.filter(|entity| entities.is_alive(*entity));
+    let target = match data.target {
+        TargetType::Caster => Some(caster),
+        TargetType::Untargeted => None,
+        TargetType::Enemy => match selected {
+            Some(target) if target != caster => Some(target),
+            _ => return Err("You have no target"),
+        },
+        TargetType::Friendly => selected
+            .filter(|target| {
+                players
+                    .get(*target)
+                    .is_some_and(|target| target.team == player.team)
+            })
+            .or(Some(caster)),
+    };
+    if let (Some(target), true) = (target, data.range > 0) {
+        let distance = match (positions.get(caster), positions.get(target)) {
+            (Some(from), Some(to)) => distance(from.0, to.0),
+            _ => 0.0,
+        };
+        if distance > data.range as f32 {
+            return Err("Out of range");
+        }
+    }
+
+    Ok(Cast {
+        ability: ability.to_string(),
+        target,
+        elapsed: 0,
+        cast_time: data.cast_time,
+    })
+}
+
+pub fn distance(from: Point, to: Point) -> f32 {
+    let offset = to - from;
+    ((offset.x() as f32).powi(2) + (offset.y() as f32).powi(2)).sqrt()
+}
+
+/// Pays for a cast that went through and starts its cooldown
+fn finish(
+    caster: Entity,
+    ability: &str,
+    target: Option<Entity>,
+    book: &mut Abilities,
+    statuses: &mut WriteStorage<Status>,
+    index: &AssetIndex,
+) -> Option<CastEvent> {
+    let data: &AbilityData = index.abilities.get(ability)?;
+    if data.cooldown > 0 {
+        book.cooldowns.insert(ability.to_string(), data.cooldown);
+    }
+    if let Some(status) = statuses.get_mut(caster) {
+        status.spend(data.cost);
+    }
+    Some(CastEvent {
+        caster,
+        ability: ability.to_string(),
+        target,
+    })
+}
+
+/// The UI elements of the cast bar
+pub struct CastBar {
+    pub panel: Entity,
+    pub name: Entity,
+    pub bar: Entity,
+}
+
+/// Creates the cast bar above the bottom panel, hidden while not casting
+pub fn create_cast_bar(world: &mut World) -> CastBar {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (220, 44),
+            )
+            .anchored(
+                Anchor::Bottom,
+                Point::new(0, -(ui::BOTTOM_PANEL_HEIGHT as i32) - 60),
+            )
+            .with_layout(Layout::Vertical, 8, 2)
+            .hidden(),
+        )
+        .build();
+    let name = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: String::new(),
+                    color: Color::WHITE,
+                },
+                (0, 16),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    let bar = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Bar {
+                    value: 0,
+                    max: 0,
+                    color: Some(CAST_BAR_COLOR),
+                },
+                (0, 8),
+            )
+            .with_parent(panel, 1),
+        )
+        .build();
+
+    CastBar { panel, name, bar }
+}
+
+/// Shows the cast bar while the player casts
+pub struct CastBarSystem;
+
+impl<'a> System<'a> for CastBarSystem {
+    type SystemData = (
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Abilities>,
+        Read<'a, AssetIndex>,
+        ReadExpect<'a, CastBar>,
+        WriteStorage<'a, UiComponent>,
+    );
+
+    fn run(&mut self, (players, abilities, index, cast_bar, mut nodes): Self::SystemData) {
+        let cast = (&players, &abilities)
+            .join()
+            .find(|(player, _)| player.is_local())
+            .and_then(|(_, book)| book.casting.as_ref());
+        if let Some(panel) = nodes.get_mut(cast_bar.panel) {
+            panel.visible = cast.is_some();
+        }
+        let cast = match cast {
+            Some(cast) => cast,
+            None => return,
+        };
+
+        if let Some(Widget::Label { text, .. }) =
+            nodes.get_mut(cast_bar.name).map(|n| &mut n.widget)
+        {
+            *text = index
+                .abilities
+                .get(&cast.ability)
+                .map(|data| data.name.clone())
+                .unwrap_or_else(|| cast.ability.clone());
+        }
+        if let Some(Widget::Bar { value, max, .. }) =
+            nodes.get_mut(cast_bar.bar).map(|n| &mut n.widget)
+        {
+            *value = cast.elapsed;
+            *max = cast.cast_time;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const ABILITIES: &str = r#"{
+        "fireball": { "name": "Fireball", "cast_time": 1500, "cooldown": 0, "target": "enemy" },
+        "frost_nova": { "name": "Frost Nova", "cooldown": 8000, "target": "untargeted" }
+    }"#;
+
+    /// A world with the local player knowing both abilities, stepped by 50 ms ticks
+    fn world() -> (World, Entity) {
+        let mut world = World::new();
+        System::setup(&mut AbilitySystem, &mut world);
+        world.insert(AssetIndex {
+            abilities: serde_json::from_str(ABILITIES).unwrap(),
+            ..Default::default()
+        });
+        world.insert(GameTime {
+            delta: Duration::from_millis(50),
+            ..Default::default()
+        });
+        world.insert(None::<AttackCommand>);
+        world.insert(None::<MovementCommand>);
+        let player = world
+            .create_entity()
+            .with(Player {
+                id: PLAYER_ID.to_string(),
+                ..Default::default()
+            })
+            .with(Abilities::new(vec![
+                "fireball".to_string(),
+                "frost_nova".to_string(),
+            ]))
+            .with(Status::new(100, None))
+            .build();
+        (world, player)
+    }
+
+    fn tick(world: &mut World, attack: Option<&str>) {
+        *world.write_resource::<Option<AttackCommand>>() =
+            attack.map(|ability| AttackCommand::Cast(ability.to_string()));
+        AbilitySystem.run_now(world);
+        world.maintain();
+    }
+
+    fn book<T>(world: &World, player: Entity, read: impl FnOnce(&Abilities) -> T) -> T {
+        read(world.read_storage::<Abilities>().get(player).unwrap())
+    }
+
+    fn last_line(world: &World) -> String {
+        let log = world.read_resource::<ChatLog>();
+        log.messages
+            .back()
+            .map(|message| message.text.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn remaining_cooldown_counts_the_global_cooldown() {
+        let mut book = Abilities::new(vec!["frost_nova".to_string()]);
+        book.cooldowns.insert("frost_nova".to_string(), 300);
+        book.global_cooldown = 700;
+        assert_eq!(book.remaining_cooldown("frost_nova"), 700);
+        assert_eq!(book.remaining_cooldown("fireball"), 700);
+        book.global_cooldown = 0;
+        assert_eq!(book.remaining_cooldown("frost_nova"), 300);
+    }
+
+    #[test]
+    fn instant_cast_starts_the_global_and_its_own_cooldown() {
+        let (mut world, player) = world();
+        tick(&mut world, Some("frost_nova"));
+        book(&world, player, |book| {
+            assert_eq!(book.global_cooldown, GLOBAL_COOLDOWN);
+            assert_eq!(book.cooldowns.get("frost_nova"), Some(&8000));
+        });
+        assert_eq!(world.read_resource::<CastEvents>().0.len(), 1);
+
+        tick(&mut world, None);
+        book(&world, player, |book| {
+            assert_eq!(book.global_cooldown, GLOBAL_COOLDOWN - 50);
+            assert_eq!(book.cooldowns.get("frost_nova"), Some(&7950));
+        });
+        assert!(world.read_resource::<CastEvents>().0.is_empty());
+    }
+
+    #[test]
+    fn casting_is_rejected_until_the_cooldown_runs_out() {
+        let (mut world, player) = world();
+        tick(&mut world, Some("frost_nova"));
+        tick(&mut world, Some("frost_nova"));
+        assert_eq!(last_line(&world), "That ability isn't ready yet");
+        assert!(world.read_resource::<CastRequests>().0.is_empty());
+
+        for _ in 0..8000 / 50 {
+            tick(&mut world, None);
+        }
+        book(&world, player, |book| {
+            assert_eq!(book.remaining_cooldown("frost_nova"), 0);
+            assert!(book.cooldowns.is_empty());
+        });
+        tick(&mut world, Some("frost_nova"));
+        assert_eq!(world.read_resource::<CastEvents>().0.len(), 1);
+    }
+
+    #[test]
+    fn the_global_cooldown_blocks_other_abilities() {
+        let (mut world, player) = world();
+        tick(&mut world, Some("frost_nova"));
+        tick(&mut world, Some("fireball"));
+        assert_eq!(last_line(&world), "That ability isn't ready yet");
+        assert!(book(&world, player, |book| book.casting.is_none()));
+    }
+}
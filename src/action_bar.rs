@@ -0,0 +1,289 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use log::{debug, error};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use crate::{
+    abilities::{self, GLOBAL_COOLDOWN},
+    assets::{AssetIndex, TargetType},
+    components::*,
+    render::{Layer, RenderQueue, TextMetrics},
+    ui,
+};
+
+/// Action of the slots of the bar
+pub const ACTION_BAR_ACTION: &str = "action_bar";
+/// Number of slots, bound to the keys 1 to 9 and 0
+pub const SLOT_COUNT: usize = 10;
+/// Directory action bars are saved in unless `GAME_SAVE_DIR` says otherwise
+const SAVE_DIR: &str = "saves";
+const SAVE_FILE: &str = "action_bar.json";
+/// Texture the ability icons are cut from, in cells of `ui::ICON_CELL`
+const ICON_TEXTURE: &str = "ability_icons";
+const ICON_SIZE: u32 = 32;
+const SLOT_SIZE: u32 = 36;
+const SLOT_SPACING: i32 = 4;
+
+const COOLDOWN_SHADE: Color = Color::RGBA(0, 0, 0, 170);
+const NO_RESOURCE_TINT: Color = Color::RGBA(30, 60, 220, 120);
+const OUT_OF_RANGE_TINT: Color = Color::RGBA(220, 30, 30, 120);
+
+/// Which ability is in which slot of the action bar, saved per character
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActionBar {
+    pub slots: Vec<Option<String>>,
+    /// Id of the character the bar was loaded for and is saved for
+    #[serde(skip)]
+    character: String,
+}
+
+impl ActionBar {
+    /// The first abilities of `known` in order
+    pub fn from_known(known: &[String]) -> Self {
+        Self {
+            slots: (0..SLOT_COUNT)
+                .map(|slot| known.get(slot).cloned())
+                .collect(),
+            character: String::new(),
+        }
+    }
+
+    /// The saved action bar of a character, or one filled from the known abilities
+    pub fn load_or_default(character: &str, known: &[String]) -> Self {
+        let path = save_path(character);
+        let saved = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str::<ActionBar>(&json).map_err(|e| e.to_string()));
+        let mut bar = match saved {
+            Ok(mut bar) => {
+                debug!("loaded action bar from {:?}", path);
+                bar.slots.resize(SLOT_COUNT, None);
+                bar
+            }
+            Err(error) => {
+                debug!("no action bar in {:?}: {}", path, error);
+                Self::from_known(known)
+            }
+        };
+        bar.character = character.to_string();
+        bar
+    }
+
+    /// Saves the bar for the character it was loaded for
+    pub fn save(&self) {
+        let path = save_path(&self.character);
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(self).map_err(|e| e.to_string()))
+            .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+        if let Err(error) = saved {
+            error!("saving action bar to {:?}: {}", path, error);
+        }
+    }
+
+    pub fn ability(&self, slot: usize) -> Option<&str> {
+        self.slots.get(slot)?.as_deref()
+    }
+
+    /// Swaps two slots, dragging onto an empty slot moves the ability there
+    pub fn swap(&mut self, from: usize, to: usize) {
+        if from < self.slots.len() && to < self.slots.len() {
+            self.slots.swap(from, to);
+        }
+    }
+}
+
+fn save_path(character: &str) -> PathBuf {
+    PathBuf::from(env::var("GAME_SAVE_DIR").unwrap_or_else(|_| SAVE_DIR.to_string()))
+        .join(character)
+        .join(SAVE_FILE)
+}
+
+/// The slot a number key is bound to
+pub fn slot_for_key(keycode: Keycode) -> Option<usize> {
+    let slot = match keycode {
+        Keycode::Num1 => 0,
+        Keycode::Num2 => 1,
+        Keycode::Num3 => 2,
+        Keycode::Num4 => 3,
+        Keycode::Num5 => 4,
+        Keycode::Num6 => 5,
+        Keycode::Num7 => 6,
+        Keycode::Num8 => 7,
+        Keycode::Num9 => 8,
+        Keycode::Num0 => 9,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+fn key_label(slot: usize) -> String {
+    ((slot + 1) % SLOT_COUNT).to_string()
+}
+
+/// Creates the row of slots on the right of the bottom panel
+pub fn create_action_bar(world: &mut World, bottom_panel: Entity) -> Entity {
+    let width = SLOT_COUNT as u32 * SLOT_SIZE + (SLOT_COUNT as u32 - 1) * SLOT_SPACING as u32;
+    let row = world
+        .create_entity()
+        .with(
+            UiComponent::new(Widget::Container, (width, SLOT_SIZE))
+                .with_parent(bottom_panel, 0)
+                .anchored(Anchor::Right, Point::new(-10, 0))
+                .with_layout(Layout::Horizontal, 0, SLOT_SPACING),
+        )
+        .build();
+    for slot in 0..SLOT_COUNT {
+        world
+            .create_entity()
+            .with(
+                UiComponent::new(
+                    Widget::ActionSlot {
+                        slot,
+                        action: ACTION_BAR_ACTION.to_string(),
+                    },
+                    (SLOT_SIZE, SLOT_SIZE),
+                )
+                .with_parent(row, slot as i32),
+            )
+            .build();
+    }
+    row
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, UiComponent>,
+    Read<'a, ui::UiState>,
+    Read<'a, ActionBar>,
+    ReadStorage<'a, Player>,
+    ReadStorage<'a, Abilities>,
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Status>,
+    Read<'a, Target>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the icons, cooldowns and key bindings into the action bar slots, and the icon
+/// being dragged under the mouse
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (entities, nodes, state, bar, players, books, positions, statuses, target, index) = data;
+    let player = local_player(&entities, &players);
+    let book = player.and_then(|player| books.get(player));
+    let status = player.and_then(|player| statuses.get(player));
+    let target_distance = match (player, target.0) {
+        (Some(player), Some(target)) => match (positions.get(player), positions.get(target)) {
+            (Some(from), Some(to)) => Some(abilities::distance(from.0, to.0)),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    for (entity, node) in (&entities, &nodes).join() {
+        let slot = match node.widget {
+            Widget::ActionSlot { slot, .. } if node.shown => slot,
+            _ => continue,
+        };
+        let bounds = node.bounds;
+        let z = node.depth as i32;
+        queue.fill_rect(Layer::Ui, z, ui::SLOT_BACKGROUND, bounds);
+
+        let ability = bar
+            .ability(slot)
+            .and_then(|id| Some((id, index.abilities.get(id)?)));
+        if let Some((id, data)) = ability {
+            let icon = Rect::from_center(bounds.center(), ICON_SIZE, ICON_SIZE);
+            let (texture, region) = ui::icon_region(&index, ICON_TEXTURE, data.icon);
+            queue.copy(Layer::Ui, z, texture, region, icon);
+
+            let affordable = status.is_none_or(|status| status.can_afford(data.cost));
+            let in_range = data.range == 0
+                || data.target != TargetType::Enemy
+                || target_distance.is_none_or(|distance| distance <= data.range as f32);
+            if !affordable {
+                queue.fill_rect(Layer::Ui, z, NO_RESOURCE_TINT, icon);
+            } else if !in_range {
+                queue.fill_rect(Layer::Ui, z, OUT_OF_RANGE_TINT, icon);
+            }
+
+            // The shade shrinks from the bottom up as the cooldown runs out
+            let cooldown = book.map_or(0, |book| book.cooldowns.get(id).copied().unwrap_or(0));
+            let global = book.map_or(0, |book| book.global_cooldown);
+            let (remaining, total) = if cooldown >= global {
+                (cooldown, data.cooldown)
+            } else {
+                (global, GLOBAL_COOLDOWN)
+            };
+            if remaining > 0 {
+                let height = (icon.height() as f32 * fraction(remaining, total)).ceil() as u32;
+                if height > 0 {
+                    queue.fill_rect(
+                        Layer::Ui,
+                        z,
+                        COOLDOWN_SHADE,
+                        Rect::new(icon.x(), icon.y(), icon.width(), height),
+                    );
+                }
+                if remaining >= 1000 {
+                    let seconds = remaining.div_ceil(1000).to_string();
+                    let (width, height) = font.text_size(&seconds)?;
+                    let text = Rect::from_center(icon.center(), width, height);
+                    ui::draw_text(queue, z, font, &seconds, Color::WHITE, text.top_left())?;
+                }
+            }
+        }
+
+        let border = if state.hovered == Some(entity) {
+            ui::HOVERED_BORDER
+        } else {
+            ui::SLOT_BORDER
+        };
+        queue.draw_rect(Layer::Ui, z, border, bounds);
+        ui::draw_text(
+            queue,
+            z,
+            font,
+            &key_label(slot),
+            Color::RGB(220, 220, 220),
+            bounds.top_left().offset(2, 0),
+        )?;
+    }
+
+    // The icon follows the mouse while a slot is dragged off itself
+    let dragged = state
+        .pressed
+        .and_then(|pressed| nodes.get(pressed))
+        .and_then(|node| match node.widget {
+            Widget::ActionSlot { slot, .. } => Some((slot, node.bounds)),
+            _ => None,
+        });
+    if let Some((slot, bounds)) = dragged {
+        let pointer = Point::new(state.pointer.0, state.pointer.1);
+        let data = bar.ability(slot).and_then(|id| index.abilities.get(id));
+        if let (Some(data), false) = (data, bounds.contains_point(pointer)) {
+            let (texture, region) = ui::icon_region(&index, ICON_TEXTURE, data.icon);
+            queue.copy(
+                Layer::Ui,
+                i32::MAX,
+                texture,
+                region,
+                Rect::from_center(pointer, ICON_SIZE, ICON_SIZE),
+            );
+        }
+    }
+
+    Ok(())
+}
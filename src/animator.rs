@@ -1,35 +1,125 @@
 use specs::prelude::*;
 
+use crate::assets::{AnimationData, AssetIndex, SpritesheetData};
 use crate::components::*;
+use crate::time::GameTime;
 
+/// Suffix of the clip names facing the given direction
+fn direction_name(direction: Direction) -> Option<&'static str> {
+    match direction {
+        Direction::Up => Some("up"),
+        Direction::Down => Some("down"),
+        Direction::Left => Some("left"),
+        Direction::Right => Some("right"),
+        Direction::Stationary => None,
+    }
+}
+
+/// Name of the clip played for `state`, preferring the variant facing `facing`. Clips
+/// without a direction (`death` rather than `death_down`) are used for every direction.
+fn clip_name(sheet: &SpritesheetData, state: AnimationState, facing: Direction) -> Option<String> {
+    direction_name(facing)
+        .map(|direction| format!("{}_{}", state.name(), direction))
+        .into_iter()
+        .chain(Some(state.name().to_string()))
+        .find(|name| sheet.animations.contains_key(name))
+}
+
+/// The clip the controller is playing, if its spritesheet has one for the current state
+fn clip<'a>(
+    index: &'a AssetIndex,
+    controller: &AnimationController,
+) -> Option<(String, &'a AnimationData)> {
+    let sheet = index.spritesheets.get(&controller.spritesheet)?;
+    let name = clip_name(sheet, controller.state, controller.facing)?;
+    let data = sheet.animations.get(&name)?;
+    Some((name, data))
+}
+
+/// The sprite of the frame the controller is currently on
+pub fn current_sprite(index: &AssetIndex, controller: &AnimationController) -> Option<Sprite> {
+    let (name, data) = clip(index, controller)?;
+    index.sprite(
+        &controller.spritesheet,
+        &name,
+        data.frame_at(controller.elapsed),
+    )
+}
+
+/// Moves every animation controller between states and updates the sprite to the current
+/// frame. Movement picks between idle and walking, finishing a cast triggers an attack, taking
+/// damage a hit and running out of health death.
 pub struct Animator;
 
 impl<'a> System<'a> for Animator {
     type SystemData = (
-        WriteStorage<'a, MovementAnimation>,
-        WriteStorage<'a, Sprite>,
+        Entities<'a>,
+        Read<'a, AssetIndex>,
+        Read<'a, GameTime>,
+        Read<'a, CombatEvents>,
+        Read<'a, CastEvents>,
         ReadStorage<'a, Player>,
+        ReadStorage<'a, Status>,
+        WriteStorage<'a, AnimationController>,
+        WriteStorage<'a, Sprite>,
     );
 
-    fn run(&mut self, mut data: Self::SystemData) {
-        use self::Direction::*;
-        //TODO: This code can be made nicer and more idiomatic using more pattern matching.
-        // Look up "rust irrefutable patterns" and use them here.
-        for (anim, sprite, player) in (&mut data.0, &mut data.1, &data.2).join() {
-            if player.velocity == Stationary {
-                continue;
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, index, time, events, casts, players, statuses, mut controllers, mut sprites) =
+            data;
+        use self::AnimationState::*;
+
+        let hits: Vec<Entity> = events
+            .0
+            .iter()
+            .filter(|event| matches!(event.kind, CombatKind::Damage | CombatKind::Crit))
+            .map(|event| event.entity)
+            .collect();
+        let casters: Vec<Entity> = casts.0.iter().map(|cast| cast.caster).collect();
+
+        for (entity, player, controller, sprite) in
+            (&entities, &players, &mut controllers, &mut sprites).join()
+        {
+            if player.velocity != Direction::Stationary {
+                controller.facing = player.velocity;
             }
+            let alive = statuses.get(entity).is_none_or(|status| status.alive);
 
-            let frames = match player.velocity {
-                Left => &anim.left_frames,
-                Right => &anim.right_frames,
-                Up => &anim.up_frames,
-                Down => &anim.down_frames,
-                Stationary => &anim.left_frames,
+            let triggered = if !alive {
+                Some(Death)
+            } else if casters.contains(&entity) {
+                Some(Attack)
+            } else if hits.contains(&entity) {
+                Some(Hit)
+            } else {
+                None
+            };
+            let base = if player.velocity == Direction::Stationary {
+                Idle
+            } else {
+                Walk
             };
+            // Clips missing from the spritesheet count as finished
+            let finished =
+                clip(&index, controller).is_none_or(|(_, data)| data.finished(controller.elapsed));
+            let busy = controller.state == Death || (controller.state.is_one_shot() && !finished);
+            let current_priority = if busy { controller.state.priority() } else { 0 };
 
-            anim.current_frame = (anim.current_frame + 1) % frames.len();
-            *sprite = frames[anim.current_frame].clone();
+            let next = match triggered {
+                Some(state) if state.priority() >= current_priority => state,
+                _ if busy && controller.state.is_one_shot() => controller.state,
+                _ => base,
+            };
+            if next != controller.state || (triggered == Some(next) && next.is_one_shot()) {
+                controller.state = next;
+                controller.elapsed = 0;
+            } else {
+                controller.elapsed = controller.elapsed.saturating_add(time.delta_ms());
+            }
+
+            if let Some(frame) = current_sprite(&index, controller) {
+                *sprite = frame;
+            }
         }
     }
 }
@@ -0,0 +1,1127 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{debug, error, info};
+use sdl2::image::LoadTexture;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::ttf::{Font, Sdl2TtfContext};
+use sdl2::video::WindowContext;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::components::*;
+
+/// Directory the assets are loaded from unless `GAME_ASSETS_DIR` says otherwise
+pub const ASSETS_DIR: &str = "assets";
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Describes every asset of the game by id. All paths are relative to the assets directory.
+/// Unknown keys are an error, so a misspelled section isn't skipped without notice.
+///
+/// ```json
+/// {
+///     "textures": { "bardo": "bardo.png" },
+///     "fonts": { "ui": { "path": "DejaVuSansMono.ttf", "size": 13 } },
+///     "maps": { "start": "maps/start.json" },
+///     "spritesheets": { "bardo": "spritesheets/bardo.json" },
+///     "sounds": { "hit": "sounds/hit.wav" },
+///     "skins": ["bardo", "reaper"],
+///     "start_map": "start",
+///     "abilities": "abilities.json",
+///     "starting_abilities": ["strike"],
+///     "effects": "effects.json",
+///     "particles": "particles.json",
+///     "items": "items.json",
+///     "quests": "quests.json"
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub textures: HashMap<String, String>,
+    #[serde(default)]
+    pub fonts: HashMap<String, FontEntry>,
+    #[serde(default)]
+    pub maps: HashMap<String, String>,
+    #[serde(default)]
+    pub spritesheets: HashMap<String, String>,
+    #[serde(default)]
+    pub sounds: HashMap<String, String>,
+    /// Spritesheet ids of the character skins, indexed by the skin number the server sends
+    #[serde(default)]
+    pub skins: Vec<String>,
+    #[serde(default)]
+    pub start_map: Option<String>,
+    /// File with the definitions of every ability
+    #[serde(default)]
+    pub abilities: Option<String>,
+    /// Ability ids the player knows from the start
+    #[serde(default)]
+    pub starting_abilities: Vec<String>,
+    /// File with the definitions of every buff and debuff
+    #[serde(default)]
+    pub effects: Option<String>,
+    /// File with the definitions of every particle emitter
+    #[serde(default)]
+    pub particles: Option<String>,
+    /// File with the definitions of every item
+    #[serde(default)]
+    pub items: Option<String>,
+    /// File with the definitions of every quest
+    #[serde(default)]
+    pub quests: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FontEntry {
+    pub path: String,
+    pub size: u16,
+}
+
+/// A tile map drawn on the ground layer
+///
+/// ```json
+/// {
+///     "tileset": "darkdimension",
+///     "tile_size": 16,
+///     "columns": 40,
+///     "rows": 30,
+///     "origin": [-320, -240],
+///     "fill": 291,
+///     "tiles": [],
+///     "emitters": [{ "effect": "embers", "position": [-200, -100] }]
+/// }
+/// ```
+///
+/// `tiles` holds one tileset index per cell, row by row. Cells it doesn't cover use `fill`.
+/// `emitters` are particle emitters that run for as long as the map is shown.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MapData {
+    pub tileset: String,
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// World position of the top left corner of the map
+    #[serde(default)]
+    pub origin: [i32; 2],
+    #[serde(default)]
+    pub fill: u32,
+    #[serde(default)]
+    pub tiles: Vec<u32>,
+    #[serde(default)]
+    pub emitters: Vec<MapEmitter>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MapEmitter {
+    /// Id of the emitter in the particle definitions
+    pub effect: String,
+    /// World position
+    pub position: [i32; 2],
+}
+
+impl MapData {
+    pub fn tile(&self, column: u32, row: u32) -> u32 {
+        self.tiles
+            .get((row * self.columns + column) as usize)
+            .copied()
+            .unwrap_or(self.fill)
+    }
+}
+
+/// Frame duration of animations that don't specify one, in milliseconds
+const DEFAULT_FRAME_DURATION: u32 = 100;
+/// Speed of projectiles that don't specify one, in world pixels per second
+const DEFAULT_PROJECTILE_SPEED: u32 = 300;
+
+/// Names the animations of a texture and where their frames are
+///
+/// ```json
+/// {
+///     "texture": "bardo",
+///     "origin": [13, 18],
+///     "animations": {
+///         "walk_down": {
+///             "mode": "pingpong",
+///             "frame_duration": 120,
+///             "frames": [
+///                 { "rect": [0, 0, 26, 36] },
+///                 { "rect": [26, 0, 26, 36], "duration": 200, "origin": [13, 20] }
+///             ]
+///         }
+///     }
+/// }
+/// ```
+///
+/// The origin of a frame is the point drawn at the position of the entity, relative to the
+/// top left corner of the frame. Frames without one use the origin of the spritesheet, or
+/// their center if that has none either.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpritesheetData {
+    pub texture: String,
+    #[serde(default)]
+    pub origin: Option<[i32; 2]>,
+    pub animations: HashMap<String, AnimationData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimationData {
+    #[serde(default)]
+    pub mode: LoopMode,
+    /// Duration of the frames that don't specify one, in milliseconds
+    #[serde(default = "default_frame_duration")]
+    pub frame_duration: u32,
+    pub frames: Vec<FrameData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameData {
+    pub rect: [i32; 4],
+    /// In milliseconds
+    #[serde(default)]
+    pub duration: Option<u32>,
+    #[serde(default)]
+    pub origin: Option<[i32; 2]>,
+}
+
+/// What an animation does after its last frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoopMode {
+    /// Starts over from the first frame
+    #[default]
+    Loop,
+    /// Stays on the last frame
+    Once,
+    /// Plays backwards to the first frame, then forwards again
+    PingPong,
+}
+
+fn default_frame_duration() -> u32 {
+    DEFAULT_FRAME_DURATION
+}
+
+impl AnimationData {
+    fn duration(&self, frame: usize) -> u32 {
+        self.frames[frame].duration.unwrap_or(self.frame_duration)
+    }
+
+    /// Index of the frame shown `elapsed` milliseconds after the animation started
+    pub fn frame_at(&self, elapsed: u32) -> usize {
+        let count = self.frames.len();
+        let order: Vec<usize> = match self.mode {
+            LoopMode::PingPong => (0..count)
+                .chain((1..count.saturating_sub(1)).rev())
+                .collect(),
+            LoopMode::Loop | LoopMode::Once => (0..count).collect(),
+        };
+        let total: u32 = order.iter().map(|frame| self.duration(*frame)).sum();
+        if total == 0 {
+            return 0;
+        }
+        let mut time = match self.mode {
+            LoopMode::Once => elapsed.min(total - 1),
+            LoopMode::Loop | LoopMode::PingPong => elapsed % total,
+        };
+        for frame in order {
+            let duration = self.duration(frame);
+            if time < duration {
+                return frame;
+            }
+            time -= duration;
+        }
+        count.saturating_sub(1)
+    }
+
+    /// Whether the animation has played through once, which is when one shot clips end
+    pub fn finished(&self, elapsed: u32) -> bool {
+        let total: u32 = (0..self.frames.len())
+            .map(|frame| self.duration(frame))
+            .sum();
+        elapsed >= total
+    }
+}
+
+/// An ability characters can cast, times are in milliseconds
+///
+/// ```json
+/// {
+///     "fireball": {
+///         "name": "Fireball",
+///         "cost": 30,
+///         "cast_time": 2000,
+///         "cooldown": 6000,
+///         "range": 300,
+///         "target": "enemy",
+///         "icon": 1,
+///         "visual": {
+///             "spritesheet": "spells",
+///             "projectile": "fireball",
+///             "speed": 400,
+///             "impact": "fireball_impact"
+///         }
+///     }
+/// }
+/// ```
+///
+/// The cost is paid in the power of the caster, be it mana, energy or rage.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AbilityData {
+    pub name: String,
+    #[serde(default)]
+    pub cost: u32,
+    /// Zero for instant abilities
+    #[serde(default)]
+    pub cast_time: u32,
+    #[serde(default)]
+    pub cooldown: u32,
+    /// Farthest the target may be from the caster in world pixels, zero for no limit
+    #[serde(default)]
+    pub range: u32,
+    #[serde(default)]
+    pub target: TargetType,
+    /// Cell of the ability icon atlas, counted row by row
+    #[serde(default)]
+    pub icon: u32,
+    #[serde(default)]
+    pub visual: Option<SpellVisual>,
+}
+
+/// What an ability looks like when it goes off, with clips from a single spritesheet
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpellVisual {
+    pub spritesheet: String,
+    /// Clip of a projectile flying from the caster to the target. Abilities without one
+    /// hit as soon as they are cast.
+    #[serde(default)]
+    pub projectile: Option<String>,
+    /// World pixels the projectile flies per second
+    #[serde(default = "default_projectile_speed")]
+    pub speed: u32,
+    /// Clip played once on the target when the ability hits
+    #[serde(default)]
+    pub impact: Option<String>,
+    /// Particle emitter following the projectile
+    #[serde(default)]
+    pub trail: Option<String>,
+    /// Particle emitter started on the target when the ability hits
+    #[serde(default)]
+    pub burst: Option<String>,
+}
+
+fn default_projectile_speed() -> u32 {
+    DEFAULT_PROJECTILE_SPEED
+}
+
+/// What an ability is cast on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetType {
+    /// The caster itself
+    Caster,
+    /// The selected target, which must be someone else
+    #[default]
+    Enemy,
+    /// The selected target if it is in the caster's team, the caster otherwise
+    Friendly,
+    /// Nothing, like abilities that hit everyone around the caster
+    Untargeted,
+}
+
+/// A buff or debuff, applied and removed by the server
+///
+/// ```json
+/// {
+///     "frostbite": {
+///         "name": "Frostbite",
+///         "harmful": true,
+///         "icon": 0,
+///         "tint": [140, 180, 255]
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct EffectData {
+    pub name: String,
+    /// Debuffs are put on characters by their enemies
+    #[serde(default)]
+    pub harmful: bool,
+    /// Keeps the character from moving and casting
+    #[serde(default)]
+    pub stun: bool,
+    /// Cell of the effect icon atlas, counted row by row
+    #[serde(default)]
+    pub icon: u32,
+    /// Color the sprite of an affected character is multiplied with
+    #[serde(default)]
+    pub tint: Option<[u8; 3]>,
+}
+
+/// A particle emitter. Times are in milliseconds, distances in world pixels and angles in
+/// degrees clockwise from the right.
+///
+/// ```json
+/// {
+///     "sparks": {
+///         "burst": 20,
+///         "lifetime": [300, 600],
+///         "speed": [60, 160],
+///         "direction": 270,
+///         "spread": 60,
+///         "gravity": 300,
+///         "size": [4, 1],
+///         "colors": [[255, 240, 180, 255], [255, 120, 20, 0]],
+///         "additive": true
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmitterData {
+    /// Particles spawned per second while the emitter runs
+    #[serde(default)]
+    pub rate: f32,
+    /// Particles spawned at once when the emitter starts
+    #[serde(default)]
+    pub burst: u32,
+    /// How long the emitter spawns particles, zero to keep going until it is removed
+    #[serde(default)]
+    pub duration: u32,
+    /// Shortest and longest life of a particle
+    pub lifetime: [u32; 2],
+    /// Slowest and fastest start speed, in world pixels per second
+    #[serde(default)]
+    pub speed: [f32; 2],
+    #[serde(default)]
+    pub direction: f32,
+    /// How far the direction of a particle may turn from `direction` either way
+    #[serde(default)]
+    pub spread: f32,
+    /// Distance from the emitter particles spawn within
+    #[serde(default)]
+    pub radius: f32,
+    /// Downward acceleration in world pixels per second squared, negative to rise
+    #[serde(default)]
+    pub gravity: f32,
+    /// Size at the start and at the end of the life of a particle
+    #[serde(default = "default_particle_size")]
+    pub size: [f32; 2],
+    /// Colors a particle goes through over its life, evenly spaced
+    pub colors: Vec<[u8; 4]>,
+    /// Adds the color onto what is below instead of blending over it
+    #[serde(default)]
+    pub additive: bool,
+}
+
+fn default_particle_size() -> [f32; 2] {
+    [2.0, 2.0]
+}
+
+/// Something a character can carry in its bags
+///
+/// ```json
+/// {
+///     "rusty_sword": {
+///         "name": "Rusty Sword",
+///         "icon": 0,
+///         "slot": "main_hand",
+///         "stats": { "strength": 3 }
+///     },
+///     "healing_potion": {
+///         "name": "Healing Potion",
+///         "icon": 5,
+///         "stack": 20,
+///         "description": "Restores 50 health."
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemData {
+    pub name: String,
+    /// Cell of the item icon atlas, counted row by row
+    #[serde(default)]
+    pub icon: u32,
+    /// Where the item is worn, `None` for items that can't be equipped
+    #[serde(default)]
+    pub slot: Option<EquipSlot>,
+    /// Most items of this kind a single bag slot holds
+    #[serde(default = "default_stack_size")]
+    pub stack: u32,
+    /// What the item adds to the stats of the character wearing it
+    #[serde(default)]
+    pub stats: Stats,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn default_stack_size() -> u32 {
+    1
+}
+
+/// A quest the server can give. The server decides who may take it and counts the
+/// progress, the client only shows it.
+///
+/// ```json
+/// {
+///     "wolf_pelts": {
+///         "title": "Pelts for the Winter",
+///         "giver": "merchant",
+///         "description": "Bring me the pelts of the wolves east of town.",
+///         "objectives": [{ "text": "Wolf Pelt", "count": 6 }]
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuestData {
+    pub title: String,
+    /// Id of the character that gives the quest and takes it back once it is done
+    #[serde(default)]
+    pub giver: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub objectives: Vec<ObjectiveData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ObjectiveData {
+    pub text: String,
+    /// How many times it has to be done, like the number of creatures to slay
+    #[serde(default = "default_objective_count")]
+    pub count: u32,
+}
+
+fn default_objective_count() -> u32 {
+    1
+}
+
+/// Primary stats of a character, or what an item adds to them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub strength: i32,
+    pub agility: i32,
+    pub intellect: i32,
+    pub stamina: i32,
+    pub armor: i32,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.strength += other.strength;
+        self.agility += other.agility;
+        self.intellect += other.intellect;
+        self.stamina += other.stamina;
+        self.armor += other.armor;
+    }
+
+    /// The stats with their names, in the order they are listed in
+    pub fn named(&self) -> [(&'static str, i32); 5] {
+        [
+            ("Strength", self.strength),
+            ("Agility", self.agility),
+            ("Intellect", self.intellect),
+            ("Stamina", self.stamina),
+            ("Armor", self.armor),
+        ]
+    }
+}
+
+/// Where an item is worn. On the wire it is the index into `EquipSlot::ALL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipSlot {
+    Head,
+    Neck,
+    Chest,
+    Hands,
+    Legs,
+    Feet,
+    Finger,
+    MainHand,
+    OffHand,
+}
+
+impl EquipSlot {
+    pub const ALL: [EquipSlot; 9] = [
+        Self::Head,
+        Self::Neck,
+        Self::Chest,
+        Self::Hands,
+        Self::Legs,
+        Self::Feet,
+        Self::Finger,
+        Self::MainHand,
+        Self::OffHand,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Head => "Head",
+            Self::Neck => "Neck",
+            Self::Chest => "Chest",
+            Self::Hands => "Hands",
+            Self::Legs => "Legs",
+            Self::Feet => "Feet",
+            Self::Finger => "Finger",
+            Self::MainHand => "Main Hand",
+            Self::OffHand => "Off Hand",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|slot| *slot == self)
+            .unwrap_or_default()
+    }
+}
+impl fmt::Display for EquipSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.index().to_string())
+    }
+}
+impl FromStr for EquipSlot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<usize>()
+            .ok()
+            .and_then(|index| Self::ALL.get(index).copied())
+            .ok_or_else(|| format!("unknown equipment slot: {}", s))
+    }
+}
+
+/// Maps asset ids to handles. Unlike the asset manager it holds no SDL resources, so it
+/// can live in the world for systems that need to look up textures by id.
+#[derive(Debug, Clone, Default)]
+pub struct AssetIndex {
+    pub textures: HashMap<String, TextureHandle>,
+    /// Width and height of every loaded texture
+    pub texture_sizes: HashMap<TextureHandle, (u32, u32)>,
+    pub maps: HashMap<String, MapData>,
+    pub spritesheets: HashMap<String, SpritesheetData>,
+    pub skins: Vec<String>,
+    pub start_map: Option<String>,
+    pub abilities: HashMap<String, AbilityData>,
+    pub starting_abilities: Vec<String>,
+    pub effects: HashMap<String, EffectData>,
+    pub particles: HashMap<String, EmitterData>,
+    pub items: HashMap<String, ItemData>,
+    pub quests: HashMap<String, QuestData>,
+}
+
+impl AssetIndex {
+    /// The handle of the texture with the given id, or of the missing texture
+    pub fn texture(&self, id: &str) -> TextureHandle {
+        match self.textures.get(id) {
+            Some(handle) => *handle,
+            None => {
+                error!("unknown texture {}", id);
+                MISSING_TEXTURE
+            }
+        }
+    }
+
+    pub fn texture_size(&self, handle: TextureHandle) -> (u32, u32) {
+        self.texture_sizes.get(&handle).copied().unwrap_or((1, 1))
+    }
+
+    pub fn map_data(&self, id: &str) -> Option<&MapData> {
+        self.maps.get(id)
+    }
+
+    /// The spritesheet id of the given skin, falling back to the first skin
+    pub fn skin(&self, skin: usize) -> &str {
+        self.skins
+            .get(skin)
+            .or_else(|| self.skins.first())
+            .map(|id| id.as_str())
+            .unwrap_or_default()
+    }
+
+    /// The sprite of frame `frame` of an animation, or `None` if the spritesheet doesn't
+    /// have that animation
+    pub fn sprite(&self, spritesheet: &str, animation: &str, frame: usize) -> Option<Sprite> {
+        let sheet = self.spritesheets.get(spritesheet)?;
+        let frame = sheet.animations.get(animation)?.frames.get(frame)?;
+        let [x, y, width, height] = frame.rect;
+        let [origin_x, origin_y] = frame
+            .origin
+            .or(sheet.origin)
+            .unwrap_or([width / 2, height / 2]);
+        Some(Sprite {
+            spritesheet: self.texture(&sheet.texture),
+            region: Rect::new(x, y, width.max(0) as u32, height.max(0) as u32),
+            origin: Point::new(origin_x, origin_y),
+        })
+    }
+}
+
+/// Handle of the magenta texture drawn in place of textures that failed to load
+pub const MISSING_TEXTURE: TextureHandle = TextureHandle(0);
+
+/// How often the files of the assets directory are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The asset a watched file was loaded into
+#[derive(Debug, Clone)]
+enum WatchedAsset {
+    Manifest,
+    Texture(TextureHandle),
+    Font(String, u16),
+    Map(String),
+    Spritesheet(String),
+    Sound(String),
+    Abilities,
+    Effects,
+    Particles,
+    Items,
+    Quests,
+}
+
+#[derive(Debug)]
+struct WatchedFile {
+    asset: WatchedAsset,
+    /// Modification time when the file was last loaded, whether that worked or not. A file
+    /// read while it was still being written changes again when the writing finishes.
+    checked: Option<SystemTime>,
+}
+
+/// Owns every texture, font, map and sound of the game and hands them out by id
+pub struct AssetManager<'a> {
+    root: PathBuf,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    ttf_context: &'a Sdl2TtfContext,
+    index: AssetIndex,
+    textures: Vec<Texture<'a>>,
+    fonts: HashMap<String, Font<'a, 'static>>,
+    sounds: HashMap<String, Vec<u8>>,
+    watched: HashMap<PathBuf, WatchedFile>,
+    last_poll: Instant,
+}
+
+impl<'a> AssetManager<'a> {
+    /// Loads everything listed in the manifest of `root`. Assets that fail to load are
+    /// logged and left out, only a missing manifest is an error.
+    pub fn load(
+        root: &Path,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        ttf_context: &'a Sdl2TtfContext,
+    ) -> Result<Self, String> {
+        let mut assets = Self {
+            root: root.to_path_buf(),
+            texture_creator,
+            ttf_context,
+            index: AssetIndex::default(),
+            textures: vec![missing_texture(texture_creator)?],
+            fonts: HashMap::new(),
+            sounds: HashMap::new(),
+            watched: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        assets.load_manifest()?;
+        Ok(assets)
+    }
+
+    /// Reads the manifest and loads the assets it lists. Textures that are already loaded
+    /// are replaced in place, so their handles stay valid.
+    fn load_manifest(&mut self) -> Result<(), String> {
+        let manifest_path = self.root.join(MANIFEST_FILE);
+        let checked = modified(&manifest_path);
+        let manifest = read_manifest(&self.root)?;
+        self.watch(&manifest_path, WatchedAsset::Manifest, checked);
+
+        let mut data = Vec::new();
+        for (id, path) in &manifest.maps {
+            data.push((self.root.join(path), WatchedAsset::Map(id.clone())));
+        }
+        for (id, path) in &manifest.spritesheets {
+            data.push((self.root.join(path), WatchedAsset::Spritesheet(id.clone())));
+        }
+        let lists = [
+            (&manifest.abilities, WatchedAsset::Abilities),
+            (&manifest.effects, WatchedAsset::Effects),
+            (&manifest.particles, WatchedAsset::Particles),
+            (&manifest.items, WatchedAsset::Items),
+            (&manifest.quests, WatchedAsset::Quests),
+        ];
+        for (path, asset) in lists {
+            if let Some(path) = path {
+                data.push((self.root.join(path), asset));
+            }
+        }
+        // Taken before build_index reads the files, like in load_and_watch
+        let checked: Vec<_> = data.iter().map(|(path, _)| modified(path)).collect();
+        let mut index = build_index(&manifest, &self.root);
+
+        // Textures loaded before keep their handles, the ones the manifest gained go after
+        // them in the order build_index gave them
+        let mut texture_ids: Vec<(String, TextureHandle)> = index.textures.drain().collect();
+        texture_ids.sort_by_key(|(_, handle)| handle.0);
+        for (id, _) in texture_ids {
+            let handle = match self.index.textures.get(&id) {
+                Some(handle) => *handle,
+                None => {
+                    // New textures start out as the missing texture, so one that fails to
+                    // load still has a handle to reload into
+                    self.textures.push(missing_texture(self.texture_creator)?);
+                    TextureHandle(self.textures.len() - 1)
+                }
+            };
+            index.textures.insert(id, handle);
+        }
+        index.texture_sizes = std::mem::take(&mut self.index.texture_sizes);
+        self.index = index;
+
+        for ((path, asset), checked) in data.into_iter().zip(checked) {
+            self.watch(&path, asset, checked);
+        }
+        for (id, path) in &manifest.textures {
+            let handle = self.index.textures[id];
+            self.load_and_watch(self.root.join(path), WatchedAsset::Texture(handle));
+        }
+        for (id, entry) in &manifest.fonts {
+            self.load_and_watch(
+                self.root.join(&entry.path),
+                WatchedAsset::Font(id.clone(), entry.size),
+            );
+        }
+        for (id, path) in &manifest.sounds {
+            self.load_and_watch(self.root.join(path), WatchedAsset::Sound(id.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Loads the texture at `path` into the slot of `handle`. On failure the old texture is
+    /// kept.
+    fn load_texture(&mut self, handle: TextureHandle, path: &Path) -> bool {
+        match self.texture_creator.load_texture(path) {
+            Ok(texture) => {
+                debug!("loaded texture {:?}", path);
+                let query = texture.query();
+                self.index
+                    .texture_sizes
+                    .insert(handle, (query.width, query.height));
+                self.textures[handle.0] = texture;
+                true
+            }
+            Err(error) => {
+                error!("loading texture {:?}: {}", path, error);
+                false
+            }
+        }
+    }
+
+    /// Loads the font at `path` into the store of `id`. On failure the old font is kept.
+    fn load_font(&mut self, id: &str, size: u16, path: &Path) -> bool {
+        match self.ttf_context.load_font(path, size) {
+            Ok(font) => {
+                debug!("loaded font {:?}", path);
+                self.fonts.insert(id.to_string(), font);
+                true
+            }
+            Err(error) => {
+                error!("loading font {:?}: {}", path, error);
+                false
+            }
+        }
+    }
+
+    /// Reads the sound file at `path` into the store of `id`. On failure the old sound is kept.
+    fn load_sound(&mut self, id: &str, path: &Path) -> bool {
+        match fs::read(path) {
+            Ok(sound) => {
+                debug!("loaded sound {:?}", path);
+                self.sounds.insert(id.to_string(), sound);
+                true
+            }
+            Err(error) => {
+                error!("loading sound {:?}: {}", path, error);
+                false
+            }
+        }
+    }
+
+    /// Reads the JSON at `path` and hands it to `store`. On failure the old data is kept.
+    fn load_data<T: DeserializeOwned>(
+        &mut self,
+        path: &Path,
+        store: impl FnOnce(&mut AssetIndex, T),
+    ) -> bool {
+        match read_data(path) {
+            Some(data) => {
+                store(&mut self.index, data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Loads the file at `path` as `asset`, returns whether it loaded
+    fn load_asset(&mut self, path: &Path, asset: &WatchedAsset) -> bool {
+        match asset {
+            WatchedAsset::Manifest => match self.load_manifest() {
+                Ok(()) => true,
+                Err(error) => {
+                    error!("{}", error);
+                    false
+                }
+            },
+            WatchedAsset::Texture(handle) => self.load_texture(*handle, path),
+            WatchedAsset::Font(id, size) => self.load_font(id, *size, path),
+            WatchedAsset::Map(id) => self.load_data(path, |index, map| {
+                index.maps.insert(id.clone(), map);
+            }),
+            WatchedAsset::Spritesheet(id) => self.load_data(path, |index, sheet| {
+                index.spritesheets.insert(id.clone(), sheet);
+            }),
+            WatchedAsset::Sound(id) => self.load_sound(id, path),
+            WatchedAsset::Abilities => {
+                self.load_data(path, |index, abilities| index.abilities = abilities)
+            }
+            WatchedAsset::Effects => self.load_data(path, |index, effects| index.effects = effects),
+            WatchedAsset::Particles => {
+                self.load_data(path, |index, particles| index.particles = particles)
+            }
+            WatchedAsset::Items => self.load_data(path, |index, items| index.items = items),
+            WatchedAsset::Quests => self.load_data(path, |index, quests| index.quests = quests),
+        }
+    }
+
+    /// Loads `path` and watches it, whether it loaded or not, so it is tried again once it
+    /// changes
+    fn load_and_watch(&mut self, path: PathBuf, asset: WatchedAsset) {
+        // Taken before reading, so a write during the load still counts as a change
+        let checked = modified(&path);
+        self.load_asset(&path, &asset);
+        self.watch(&path, asset, checked);
+    }
+
+    fn watch(&mut self, path: &Path, asset: WatchedAsset, checked: Option<SystemTime>) {
+        self.watched
+            .insert(path.to_path_buf(), WatchedFile { asset, checked });
+    }
+
+    /// Reloads the watched files that changed on disk since they were last loaded. Checks at
+    /// most every `POLL_INTERVAL` and returns whether anything was reloaded. A file that
+    /// fails to load or was deleted is tried once, then again when it changes.
+    pub fn reload_changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for (path, file) in &self.watched {
+            let modified = modified(path);
+            if modified != file.checked {
+                changed.push((path.clone(), file.asset.clone(), modified));
+            }
+        }
+        let mut reloaded = false;
+        for (path, asset, modified) in changed {
+            info!("reloading {:?}", path);
+            reloaded |= self.load_asset(&path, &asset);
+            if let Some(file) = self.watched.get_mut(&path) {
+                file.checked = modified;
+            }
+        }
+        reloaded
+    }
+
+    pub fn index(&self) -> &AssetIndex {
+        &self.index
+    }
+
+    /// The texture of `handle` to change its color or alpha mod
+    pub fn texture_mut(&mut self, handle: TextureHandle) -> &mut Texture<'a> {
+        let index = if handle.0 < self.textures.len() {
+            handle.0
+        } else {
+            MISSING_TEXTURE.0
+        };
+        &mut self.textures[index]
+    }
+
+    pub fn font(&self, id: &str) -> Result<&Font<'a, 'static>, String> {
+        self.fonts
+            .get(id)
+            .ok_or_else(|| format!("unknown font {}", id))
+    }
+
+    /// Raw bytes of the sound file with the given id
+    // Nothing plays sounds yet, the manifest lists them for the audio to come
+    #[allow(dead_code)]
+    pub fn sound(&self, id: &str) -> Option<&[u8]> {
+        self.sounds.get(id).map(|sound| sound.as_slice())
+    }
+}
+
+fn missing_texture(texture_creator: &TextureCreator<WindowContext>) -> Result<Texture<'_>, String> {
+    let mut surface = Surface::new(1, 1, PixelFormatEnum::RGBA8888)?;
+    surface.fill_rect(None, Color::MAGENTA)?;
+    texture_creator
+        .create_texture_from_surface(&surface)
+        .map_err(|e| e.to_string())
+}
+
+/// Builds the index of everything `manifest` lists, reading the data files from `root`.
+/// Textures get their handles in the order of their ids but are left for the renderer to
+/// load. Data files that fail to load are logged and left out.
+pub fn build_index(manifest: &Manifest, root: &Path) -> AssetIndex {
+    let mut index = AssetIndex::default();
+
+    // Sorted so handles are the same from run to run
+    let mut texture_ids: Vec<&String> = manifest.textures.keys().collect();
+    texture_ids.sort();
+    for (i, id) in texture_ids.into_iter().enumerate() {
+        index
+            .textures
+            .insert(id.clone(), TextureHandle(MISSING_TEXTURE.0 + 1 + i));
+    }
+    index.texture_sizes.insert(MISSING_TEXTURE, (1, 1));
+    for (id, path) in &manifest.maps {
+        if let Some(map) = read_data(&root.join(path)) {
+            index.maps.insert(id.clone(), map);
+        }
+    }
+    for (id, path) in &manifest.spritesheets {
+        if let Some(sheet) = read_data(&root.join(path)) {
+            index.spritesheets.insert(id.clone(), sheet);
+        }
+    }
+    fn read_list<T: DeserializeOwned>(root: &Path, path: &Option<String>) -> Option<T> {
+        path.as_ref().and_then(|path| read_data(&root.join(path)))
+    }
+    if let Some(abilities) = read_list(root, &manifest.abilities) {
+        index.abilities = abilities;
+    }
+    if let Some(effects) = read_list(root, &manifest.effects) {
+        index.effects = effects;
+    }
+    if let Some(particles) = read_list(root, &manifest.particles) {
+        index.particles = particles;
+    }
+    if let Some(items) = read_list(root, &manifest.items) {
+        index.items = items;
+    }
+    if let Some(quests) = read_list(root, &manifest.quests) {
+        index.quests = quests;
+    }
+    index.skins = manifest.skins.clone();
+    index.start_map = manifest.start_map.clone();
+    index.starting_abilities = manifest.starting_abilities.clone();
+    index
+}
+
+/// Reads the manifest of the assets directory `root`
+pub fn read_manifest(root: &Path) -> Result<Manifest, String> {
+    let manifest_path = root.join(MANIFEST_FILE);
+    fs::read_to_string(&manifest_path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .map_err(|e| format!("reading {:?}: {}", manifest_path, e))
+}
+
+/// Reads the JSON file at `path` into a `T`
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Reads the JSON file at `path`, logging it if that fails
+fn read_data<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    match load_json(path) {
+        Ok(data) => {
+            debug!("loaded {:?}", path);
+            Some(data)
+        }
+        Err(error) => {
+            error!("loading {:?}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(mode: LoopMode, durations: &[Option<u32>]) -> AnimationData {
+        AnimationData {
+            mode,
+            frame_duration: DEFAULT_FRAME_DURATION,
+            frames: durations
+                .iter()
+                .map(|duration| FrameData {
+                    rect: [0, 0, 16, 16],
+                    duration: *duration,
+                    origin: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn manifest_lists_sounds_and_rejects_unknown_keys() {
+        let manifest: Manifest =
+            serde_json::from_str(r#"{ "sounds": { "hit": "sounds/hit.wav" } }"#).unwrap();
+        assert_eq!(manifest.sounds["hit"], "sounds/hit.wav");
+
+        let error = serde_json::from_str::<Manifest>(r#"{ "sound": {} }"#).unwrap_err();
+        assert!(error.to_string().contains("unknown field `sound`"));
+    }
+
+    #[test]
+    fn loop_starts_over_after_the_last_frame() {
+        let clip = animation(LoopMode::Loop, &[None, Some(50), None]);
+        assert_eq!(clip.frame_at(0), 0);
+        assert_eq!(clip.frame_at(99), 0);
+        assert_eq!(clip.frame_at(100), 1);
+        assert_eq!(clip.frame_at(149), 1);
+        assert_eq!(clip.frame_at(150), 2);
+        assert_eq!(clip.frame_at(250), 0);
+        assert!(!clip.finished(249));
+        assert!(clip.finished(250));
+    }
+
+    #[test]
+    fn once_stays_on_the_last_frame() {
+        let clip = animation(LoopMode::Once, &[None, None, None]);
+        assert_eq!(clip.frame_at(150), 1);
+        assert_eq!(clip.frame_at(299), 2);
+        assert_eq!(clip.frame_at(10_000), 2);
+        assert!(clip.finished(300));
+    }
+
+    #[test]
+    fn ping_pong_plays_back_without_repeating_the_ends() {
+        let clip = animation(LoopMode::PingPong, &[None, None, None]);
+        let frames: Vec<usize> = (0..6).map(|step| clip.frame_at(step * 100)).collect();
+        assert_eq!(frames, vec![0, 1, 2, 1, 0, 1]);
+        // One play through is the frames forwards
+        assert!(clip.finished(300));
+    }
+
+    #[test]
+    fn empty_or_zero_length_clips_show_the_first_frame() {
+        assert_eq!(animation(LoopMode::Loop, &[]).frame_at(500), 0);
+        assert_eq!(animation(LoopMode::Loop, &[Some(0)]).frame_at(500), 0);
+        assert!(animation(LoopMode::Once, &[]).finished(0));
+    }
+}
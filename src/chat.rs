@@ -0,0 +1,362 @@
+use std::collections::VecDeque;
+
+use log::trace;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::{
+    components::*,
+    render::{RenderQueue, TextMetrics},
+    ui, ServerUpdate,
+};
+
+/// Number of received lines kept in the scrollback
+const SCROLLBACK_SIZE: usize = 200;
+/// Number of sent lines that can be recalled with the arrow keys
+const HISTORY_SIZE: usize = 50;
+/// Longest line that can be typed, keeps the command inside a single datagram
+pub const MAX_MESSAGE_LENGTH: usize = 160;
+const PADDING: i32 = 6;
+/// Draws the chat above every UI element
+const CHAT_Z: i32 = i32::MAX;
+
+/// All chat lines received so far, newest last
+#[derive(Default)]
+pub struct ChatLog {
+    pub messages: VecDeque<ChatMessage>,
+    /// How many lines the view is scrolled up from the newest message
+    pub scroll: usize,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.messages.len() == SCROLLBACK_SIZE {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+        // Keep the lines the player is reading in place while scrolled up
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.messages.len() - 1);
+        }
+    }
+
+    pub fn system(&mut self, text: &str) {
+        self.push(ChatMessage::from_str(ChatChannel::System, text));
+    }
+
+    pub fn scroll_by(&mut self, lines: i32) {
+        let max_scroll = self.messages.len().saturating_sub(1) as i32;
+        self.scroll = (self.scroll as i32 + lines).clamp(0, max_scroll) as usize;
+    }
+}
+
+/// State of the chat input box. While `active` the keyboard goes to the chat instead of
+/// moving the player.
+#[derive(Default)]
+pub struct ChatInput {
+    pub active: bool,
+    pub text: String,
+    /// Previously sent lines, newest last
+    pub history: VecDeque<String>,
+    /// Position in `history` while browsing it with the arrow keys
+    pub history_index: Option<usize>,
+}
+
+impl ChatInput {
+    pub fn open(&mut self) {
+        self.active = true;
+        self.text.clear();
+        self.history_index = None;
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+        self.text.clear();
+        self.history_index = None;
+    }
+
+    fn remember(&mut self, line: &str) {
+        if self.history.back().map(|last| last != line).unwrap_or(true) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.to_string());
+        }
+    }
+
+    fn history_previous(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match self.history_index {
+            Some(index) => index.saturating_sub(1),
+            None => self.history.len() - 1,
+        };
+        self.history_index = Some(index);
+        self.text = self.history[index].clone();
+    }
+
+    fn history_next(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => {
+                self.history_index = Some(index + 1);
+                self.text = self.history[index + 1].clone();
+            }
+            Some(_) => {
+                self.history_index = None;
+                self.text.clear();
+            }
+            None => {}
+        }
+    }
+}
+
+/// Handles a keyboard event while the chat input is active. Returns the message to send
+/// once the player submits a line.
+pub fn handle_input(
+    input: &mut ChatInput,
+    log: &mut ChatLog,
+    event: &Event,
+) -> Option<ChatMessage> {
+    match event {
+        Event::TextInput { text, .. } => {
+            for character in text.chars().filter(|character| !character.is_control()) {
+                if input.text.chars().count() < MAX_MESSAGE_LENGTH {
+                    input.text.push(character);
+                }
+            }
+        }
+        Event::KeyDown {
+            keycode: Some(keycode),
+            ..
+        } => match keycode {
+            Keycode::Escape => input.close(),
+            Keycode::Backspace => {
+                input.text.pop();
+            }
+            Keycode::Up => input.history_previous(),
+            Keycode::Down => input.history_next(),
+            Keycode::PageUp => log.scroll_by(3),
+            Keycode::PageDown => log.scroll_by(-3),
+            Keycode::Return | Keycode::KpEnter => {
+                let line = input.text.trim().to_string();
+                input.close();
+                if line.is_empty() {
+                    return None;
+                }
+                input.remember(&line);
+                log.scroll = 0;
+                return match parse_line(&line) {
+                    Ok(message) => Some(message),
+                    Err(error) => {
+                        log.system(&error);
+                        None
+                    }
+                };
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    None
+}
+
+/// Turns a typed line into a message. Lines starting with `/p` go to the party, `/w name`
+/// whispers to a player and everything else (or `/s`) is said out loud.
+fn parse_line(line: &str) -> Result<ChatMessage, String> {
+    let sender = PLAYER_ID.to_string();
+    if !line.starts_with('/') {
+        return Ok(ChatMessage::new(
+            ChatChannel::Say,
+            sender,
+            String::new(),
+            line.to_string(),
+        ));
+    }
+
+    let mut parts = line.splitn(2, ' ');
+    let command = parts.next().unwrap_or_default();
+    let rest = parts.next().unwrap_or_default().trim();
+    // A whisper needs a name as well, so it has its own usage
+    if matches!(command, "/w" | "/whisper") {
+        let mut parts = rest.splitn(2, ' ');
+        let target = parts.next().unwrap_or_default();
+        let text = parts.next().unwrap_or_default().trim();
+        if text.is_empty() {
+            return Err("Usage: /w <name> <message>".to_string());
+        }
+        return Ok(ChatMessage::new(
+            ChatChannel::Whisper,
+            sender,
+            target.to_string(),
+            text.to_string(),
+        ));
+    }
+    if rest.is_empty() {
+        return Err(format!("Usage: {} <message>", command));
+    }
+    match command {
+        "/s" | "/say" => Ok(ChatMessage::new(
+            ChatChannel::Say,
+            sender,
+            String::new(),
+            rest.to_string(),
+        )),
+        "/p" | "/party" => Ok(ChatMessage::new(
+            ChatChannel::Party,
+            sender,
+            String::new(),
+            rest.to_string(),
+        )),
+        _ => Err(format!("Unknown command: {}", command)),
+    }
+}
+
+/// Moves chat lines from the server updates of the tick into the chat log
+pub struct ChatListener;
+
+impl<'a> System<'a> for ChatListener {
+    type SystemData = (ReadExpect<'a, Vec<ServerUpdate>>, Write<'a, ChatLog>);
+
+    fn run(&mut self, (server_updates, mut log): Self::SystemData) {
+        for server_update in server_updates.iter() {
+            if let ServerUpdate::Chat(message) = server_update {
+                trace!("chat message: {:?}", message);
+                log.push(message.clone());
+            }
+        }
+    }
+}
+
+fn channel_color(channel: ChatChannel) -> Color {
+    match channel {
+        ChatChannel::Say => Color::BLACK,
+        ChatChannel::Party => Color::RGB(0, 70, 200),
+        ChatChannel::Whisper => Color::RGB(160, 0, 160),
+        ChatChannel::System => Color::RGB(170, 90, 0),
+    }
+}
+
+fn format_message(message: &ChatMessage) -> String {
+    match message.channel {
+        ChatChannel::Say => format!("{}: {}", message.sender, message.text),
+        ChatChannel::Party => format!("[Party] {}: {}", message.sender, message.text),
+        ChatChannel::Whisper => format!("{} whispers: {}", message.sender, message.text),
+        ChatChannel::System => message.text.clone(),
+    }
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (Read<'a, ChatLog>, Read<'a, ChatInput>);
+
+/// Queues the scrollback and, when typing, the input line into the bottom panel
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (log, input) = data;
+    let line_height = font.line_height();
+    let (_, height) = queue.size();
+    let panel_top = (height - ui::BOTTOM_PANEL_HEIGHT) as i32;
+    let mut y = height as i32 - PADDING - line_height;
+
+    if input.active {
+        let prompt = format!("> {}_", input.text);
+        ui::draw_text(
+            queue,
+            CHAT_Z,
+            font,
+            &prompt,
+            Color::BLACK,
+            Point::new(PADDING, y),
+        )?;
+        y -= line_height;
+    }
+
+    for message in log.messages.iter().rev().skip(log.scroll) {
+        if y < panel_top + PADDING {
+            break;
+        }
+        ui::draw_text(
+            queue,
+            CHAT_Z,
+            font,
+            &format_message(message),
+            channel_color(message.channel),
+            Point::new(PADDING, y),
+        )?;
+        y -= line_height;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_and_channel_commands() {
+        let message = parse_line("hello there").unwrap();
+        assert_eq!(message.channel, ChatChannel::Say);
+        assert_eq!(message.sender, PLAYER_ID);
+        assert_eq!(message.text, "hello there");
+
+        let message = parse_line("/say hi").unwrap();
+        assert_eq!(
+            (message.channel, message.text.as_str()),
+            (ChatChannel::Say, "hi")
+        );
+        let message = parse_line("/p  pull now ").unwrap();
+        assert_eq!(
+            (message.channel, message.text.as_str()),
+            (ChatChannel::Party, "pull now")
+        );
+    }
+
+    #[test]
+    fn whispers_need_a_name_and_a_message() {
+        let message = parse_line("/w reaper see you at the inn").unwrap();
+        assert_eq!(message.channel, ChatChannel::Whisper);
+        assert_eq!(message.target, "reaper");
+        assert_eq!(message.text, "see you at the inn");
+
+        assert_eq!(
+            parse_line("/w reaper").unwrap_err(),
+            "Usage: /w <name> <message>"
+        );
+        assert_eq!(
+            parse_line("/whisper").unwrap_err(),
+            "Usage: /w <name> <message>"
+        );
+        assert_eq!(parse_line("/w").unwrap_err(), "Usage: /w <name> <message>");
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected() {
+        assert_eq!(
+            parse_line("/dance now").unwrap_err(),
+            "Unknown command: /dance"
+        );
+        assert_eq!(parse_line("/s").unwrap_err(), "Usage: /s <message>");
+    }
+
+    #[test]
+    fn every_message_of_a_tick_reaches_the_log() {
+        let mut world = World::new();
+        System::setup(&mut ChatListener, &mut world);
+        world.insert(vec![
+            ServerUpdate::Chat(ChatMessage::from_str(ChatChannel::Say, "reaper;hello")),
+            ServerUpdate::Chat(ChatMessage::from_str(ChatChannel::Party, "reaper;pull now")),
+        ]);
+        ChatListener.run_now(&world);
+
+        let log = world.read_resource::<ChatLog>();
+        let texts: Vec<&str> = log.messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, ["hello", "pull now"]);
+    }
+}
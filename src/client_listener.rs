@@ -10,22 +10,17 @@ pub struct ClientListener;
 
 impl<'a> System<'a> for ClientListener {
     type SystemData = (
-        ReadExpect<'a, Option<ServerUpdate>>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
         ReadStorage<'a, ExternalControlled>,
         WriteStorage<'a, Player>,
         WriteStorage<'a, Position>,
     );
 
     fn run(&mut self, mut data: Self::SystemData) {
-        let server_update = match &*data.0 {
-            Some(server_update) => server_update,
-            None => return, // no change
-        };
-
-        match server_update {
-            ServerUpdate::Update(updated_player) => {
+        for server_update in data.0.iter() {
+            if let ServerUpdate::Update(updated_player) = server_update {
                 trace!("server update: {:?}", updated_player);
-                for (mut player, position) in (&mut data.2, &mut data.3).join() {
+                for (player, position) in (&mut data.2, &mut data.3).join() {
                     if player.id == updated_player.id {
                         position.0.x = updated_player.pos.x;
                         position.0.y = updated_player.pos.y;
@@ -33,10 +28,11 @@ impl<'a> System<'a> for ClientListener {
                         player.char_name = updated_player.id.clone();
                         player.pos = updated_player.pos;
                         player.velocity = updated_player.velocity;
+                        player.team = updated_player.team;
+                        player.world_pos = updated_player.world_pos;
                     }
                 }
             }
-            _ => {}
-        };
+        }
     }
 }
@@ -0,0 +1,137 @@
+use sdl2::pixels::Color;
+use specs::prelude::*;
+
+use crate::{
+    components::*,
+    render::{Layer, RenderQueue, TextMetrics},
+    time::GameTime,
+    ui,
+};
+
+/// Milliseconds a number stays on screen
+const LIFETIME: u32 = 1200;
+/// Hits landing on the same entity within this many milliseconds are added to the same
+/// number
+const BATCH_WINDOW: u32 = 200;
+/// Pixels a number rises per second
+const DRIFT: f32 = 30.0;
+/// Height above the position of the entity where numbers appear
+const SPAWN_HEIGHT: i32 = 40;
+
+impl FloatingText {
+    fn text(&self) -> String {
+        let hits = if self.hits > 1 {
+            format!(" x{}", self.hits)
+        } else {
+            String::new()
+        };
+        match self.kind {
+            CombatKind::Damage => format!("-{}{}", self.amount, hits),
+            CombatKind::Crit => format!("-{}!{}", self.amount, hits),
+            CombatKind::Heal => format!("+{}{}", self.amount, hits),
+            CombatKind::Miss => format!("Miss{}", hits),
+        }
+    }
+
+    fn color(&self) -> Color {
+        let color = match self.kind {
+            CombatKind::Damage => Color::RGB(255, 255, 255),
+            CombatKind::Crit => Color::RGB(255, 200, 0),
+            CombatKind::Heal => Color::RGB(60, 255, 60),
+            CombatKind::Miss => Color::RGB(180, 180, 180),
+        };
+        // Fade out over the second half of the lifetime
+        let remaining = (LIFETIME - self.age.min(LIFETIME)) as f32 / (LIFETIME / 2) as f32;
+        Color::RGBA(
+            color.r,
+            color.g,
+            color.b,
+            (remaining.min(1.0) * 255.0) as u8,
+        )
+    }
+
+    fn scale(&self) -> f32 {
+        match self.kind {
+            CombatKind::Crit => 1.6,
+            CombatKind::Miss => 0.9,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Spawns floating numbers for the combat events of this tick and ages the existing ones
+pub struct CombatTextSystem;
+
+impl<'a> System<'a> for CombatTextSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, CombatEvents>,
+        Read<'a, GameTime>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, FloatingText>,
+    );
+
+    fn run(&mut self, (entities, events, time, positions, mut texts): Self::SystemData) {
+        for (entity, text) in (&entities, &mut texts).join() {
+            text.age += time.delta_ms();
+            if text.age >= LIFETIME {
+                entities.delete(entity).ok();
+            }
+        }
+
+        for event in &events.0 {
+            let batch = (&mut texts).join().find(|text| {
+                text.target == event.entity && text.kind == event.kind && text.age < BATCH_WINDOW
+            });
+            match batch {
+                Some(text) => {
+                    text.amount += event.amount;
+                    text.hits += 1;
+                }
+                None => {
+                    let origin = match positions.get(event.entity) {
+                        Some(position) => position.0.offset(0, -SPAWN_HEIGHT),
+                        None => continue,
+                    };
+                    texts
+                        .insert(
+                            entities.create(),
+                            FloatingText {
+                                target: event.entity,
+                                kind: event.kind,
+                                amount: event.amount,
+                                hits: 1,
+                                origin,
+                                age: 0,
+                            },
+                        )
+                        .ok();
+                }
+            }
+        }
+    }
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (ReadStorage<'a, FloatingText>, Read<'a, GameTime>);
+
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let size = queue.size();
+
+    let (texts, time) = data;
+    for text in texts.join() {
+        // Keep rising between simulation steps
+        let age = text.age as f32 + time.alpha * time.delta_ms() as f32;
+        let drift = (age * DRIFT / 1000.0) as i32;
+        let screen_position = ui::world_to_screen(text.origin, size).offset(0, -drift);
+        let label = text.text();
+        let target = ui::scaled_text_rect(font, &label, screen_position, text.scale())?;
+        queue.text(Layer::Effects, 0, &label, text.color(), target);
+    }
+
+    Ok(())
+}
@@ -1,16 +1,26 @@
 use std::{
+    collections::HashMap,
     fmt,
     net::{SocketAddr, UdpSocket},
+    ops::Deref,
     str::FromStr,
 };
 
+use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use specs::prelude::*;
+use specs::storage::MaskedStorage;
 use specs_derive::Component;
 
+use crate::assets::{EquipSlot, Stats};
+use crate::dialog::DialogTree;
+
 pub static RECV_SERVER_PORT: u16 = 8877;
 pub static SEND_SERVER_PORT: u16 = 8878;
 
+/// The id this client logs in with and sends commands as
+pub static PLAYER_ID: &str = "blub_id";
+
 static CLIENT_ADDR: [u8; 4] = [0, 0, 0, 0];
 
 pub struct Dimension {
@@ -25,8 +35,33 @@ pub const DIMENSION: Dimension = Dimension {
 #[derive(Clone, Debug)]
 pub enum ServerUpdate {
     Update(Player),
-    Login(String),
-    Nothing,
+    Login,
+    Chat(ChatMessage),
+    Dialog(DialogUpdate),
+    Combat(CombatUpdate),
+    Status(StatusUpdate),
+    Progress(ProgressUpdate),
+    Effect(EffectUpdate),
+    Inventory(InventoryUpdate),
+    Loot(LootUpdate),
+    Quest(QuestUpdate),
+    Ping(MinimapPing),
+}
+
+#[derive(Clone, Debug)]
+pub enum DialogUpdate {
+    /// Start a conversation from a dialog tree on disk, optionally at a given node
+    Open {
+        npc: String,
+        dialog: String,
+        node: Option<String>,
+    },
+    /// Start a conversation from a tree sent by the server
+    Tree {
+        npc: String,
+        tree: DialogTree,
+    },
+    Close,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -35,11 +70,164 @@ pub enum MovementCommand {
     Move(Direction),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum AttackCommand {
     Stop,
-    Cast(),
+    /// Cast the ability with the given id
+    Cast(String),
+}
+
+/// A cast in progress, times are in milliseconds
+#[derive(Clone, Debug)]
+pub struct Cast {
+    pub ability: String,
+    pub target: Option<Entity>,
+    pub elapsed: u32,
+    pub cast_time: u32,
+}
+
+/// The abilities a character knows and when it can cast them again, times are in
+/// milliseconds
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct Abilities {
+    /// Ability ids in the order they were learned
+    pub known: Vec<String>,
+    /// Time left until an ability can be cast again, by id
+    pub cooldowns: HashMap<String, u32>,
+    /// Time left until any ability can be cast again
+    pub global_cooldown: u32,
+    pub casting: Option<Cast>,
 }
+
+impl Abilities {
+    pub fn new(known: Vec<String>) -> Self {
+        Self {
+            known,
+            ..Default::default()
+        }
+    }
+
+    /// Time left until the ability can be cast, counting the global cooldown
+    pub fn remaining_cooldown(&self, ability: &str) -> u32 {
+        self.cooldowns
+            .get(ability)
+            .copied()
+            .unwrap_or(0)
+            .max(self.global_cooldown)
+    }
+}
+
+/// Asks the server to cast an ability: `A0;caster_id;ability;target_id`, or to stop the
+/// current cast: `A1;caster_id`
+#[derive(Clone, Debug)]
+pub enum CastRequest {
+    Start {
+        ability: String,
+        /// Player id of the target, empty for none
+        target: String,
+    },
+    Interrupt,
+}
+
+impl CastRequest {
+    pub fn to_command(&self) -> String {
+        match self {
+            CastRequest::Start { ability, target } => {
+                format!("A0;{};{};{}", PLAYER_ID, ability, target)
+            }
+            CastRequest::Interrupt => format!("A1;{}", PLAYER_ID),
+        }
+    }
+}
+
+/// Cast requests of the current tick, sent to the server by the game loop
+#[derive(Default)]
+pub struct CastRequests(pub Vec<CastRequest>);
+
+/// An ability that finished casting this tick
+#[derive(Clone, Debug)]
+pub struct CastEvent {
+    pub caster: Entity,
+    pub ability: String,
+    pub target: Option<Entity>,
+}
+
+/// Casts finished in the current tick, filled by the ability system
+#[derive(Default)]
+pub struct CastEvents(pub Vec<CastEvent>);
+
+/// A spell flying from its caster to its target
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct Projectile {
+    pub cast: CastEvent,
+    /// Where it is, in the same coordinates as `Position`
+    pub position: Point,
+    /// Milliseconds since it was cast, for its animation
+    pub elapsed: u32,
+}
+
+/// A spell animation played once on an entity
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct SpellEffect {
+    pub spritesheet: String,
+    pub clip: String,
+    pub target: Entity,
+    pub elapsed: u32,
+}
+
+/// A single particle, in the same coordinates as `Position`
+#[derive(Debug, Clone)]
+pub struct Particle {
+    pub position: (f32, f32),
+    /// World pixels per second
+    pub velocity: (f32, f32),
+    pub age: u32,
+    pub lifetime: u32,
+}
+
+/// Spawns and moves the particles of an emitter definition
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct ParticleEmitter {
+    /// Id of the emitter in the particle definitions
+    pub effect: String,
+    /// Where particles spawn, unless the emitter is attached to an entity
+    pub position: Point,
+    /// Entity the emitter follows. It stops spawning once the entity is gone.
+    pub attached: Option<Entity>,
+    /// Map the emitter belongs to, it is removed when another map is shown
+    pub map: Option<String>,
+    /// Milliseconds since it started
+    pub elapsed: u32,
+    /// Part of a particle left over from the last steps
+    pub carry: f32,
+    pub particles: Vec<Particle>,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect: &str, position: Point) -> Self {
+        Self {
+            effect: effect.to_string(),
+            position,
+            attached: None,
+            map: None,
+            elapsed: 0,
+            carry: 0.0,
+            particles: Vec::new(),
+        }
+    }
+
+    pub fn attached_to(effect: &str, entity: Entity) -> Self {
+        Self {
+            attached: Some(entity),
+            ..Self::new(effect, Point::new(0, 0))
+        }
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct ServerRuntime {
     pub send_socket: UdpSocket,
@@ -139,11 +327,17 @@ impl Player {
             world_pos,
         }
     }
+
+    /// Whether this is the character played on this client
+    pub fn is_local(&self) -> bool {
+        self.id == PLAYER_ID
+    }
+
     pub fn from_str(string: &str) -> Self {
-        let mut parts = string.split(";").into_iter();
+        let mut parts = string.split(';');
         Self {
-            id: parts.next().unwrap_or(&String::new()).to_string(),
-            char_name: parts.next().unwrap_or(&String::new()).to_string(),
+            id: parts.next().unwrap_or_default().to_string(),
+            char_name: parts.next().unwrap_or_default().to_string(),
             skin: parts.next().unwrap_or("0").parse::<usize>().unwrap_or(0),
             pos: Point::new(
                 parts.next().unwrap_or("0").parse::<i32>().unwrap_or(0),
@@ -163,6 +357,17 @@ impl Player {
     }
 }
 
+/// The entity of the character played on this client, once the server sent it
+pub fn local_player<D>(entities: &Entities, players: &Storage<Player, D>) -> Option<Entity>
+where
+    D: Deref<Target = MaskedStorage<Player>>,
+{
+    (entities, players)
+        .join()
+        .find(|(_, player)| player.is_local())
+        .map(|(entity, _)| entity)
+}
+
 #[derive(Component, Debug, Default)]
 #[storage(NullStorage)]
 pub struct ExternalControlled;
@@ -176,41 +381,1318 @@ pub struct KeyboardControlled;
 #[storage(VecStorage)]
 pub struct Position(pub Point);
 
-/// The current speed and direction of a given entity
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Velocity {
-    pub speed: i32,
-    pub direction: Direction,
+/// Where a UI element sits inside its parent (or the window for root elements)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// How a UI element arranges its children
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Children are placed by their own anchor and offset
+    Free,
+    /// Children are stacked top to bottom
+    Vertical,
+    /// Children are stacked left to right
+    Horizontal,
+}
+
+/// A texture region drawn as a nine-slice: the corners keep their size, the edges stretch
+/// along one axis and the center stretches along both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NineSlice {
+    /// The texture to cut the slices from
+    pub texture: TextureHandle,
+    /// The region of the texture containing the whole frame
+    pub source: Rect,
+    /// Thickness of the frame in the source texture
+    pub inset: u32,
+    /// Thickness of the frame on screen
+    pub border: u32,
 }
 
+#[derive(Debug, Clone)]
+pub enum Widget {
+    /// Invisible element that only groups and lays out its children
+    Container,
+    Panel {
+        frame: Option<NineSlice>,
+        background: Option<Color>,
+    },
+    Label {
+        text: String,
+        color: Color,
+    },
+    /// Word wrapped text of which only the first `revealed` characters are drawn
+    Paragraph {
+        text: String,
+        color: Color,
+        revealed: usize,
+    },
+    /// A spritesheet region scaled to fit the bounds
+    Image {
+        spritesheet: TextureHandle,
+        region: Rect,
+    },
+    /// A bar filled to `value / max` with the numbers on top, `color` of `None` uses the
+    /// health color ramp
+    Bar {
+        value: u32,
+        max: u32,
+        color: Option<Color>,
+    },
+    Button {
+        label: String,
+        /// Reported in the click event so the game knows which button was pressed
+        action: String,
+    },
+    List {
+        items: Vec<String>,
+        selected: Option<usize>,
+        action: String,
+    },
+    /// The map around the player, drawn by the minimap module. Clicks and the mouse wheel
+    /// are reported with `action`.
+    Minimap {
+        action: String,
+    },
+    /// A slot of the action bar, drawn by the action bar module. Clicks are reported as
+    /// selecting `slot`, dragging onto another slot as a move between the two.
+    ActionSlot {
+        slot: usize,
+        action: String,
+    },
+    /// A bag or equipment slot, drawn by the inventory module. Clicks are reported as
+    /// selecting `slot`, dragging onto another item slot as a move or a transfer.
+    ItemSlot {
+        slot: usize,
+        action: String,
+    },
+}
+
+/// An element of the retained UI. Elements form a tree through `parent`, the layout pass
+/// computes `bounds` every frame and the UI is drawn and hit-tested from those.
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
-pub struct UiComponent {}
+pub struct UiComponent {
+    pub widget: Widget,
+    pub parent: Option<Entity>,
+    pub anchor: Anchor,
+    pub offset: Point,
+    /// Requested size, a zero dimension fills the parent along that axis
+    pub size: (u32, u32),
+    pub layout: Layout,
+    /// Space between the bounds and the children
+    pub padding: i32,
+    /// Space between stacked children
+    pub spacing: i32,
+    /// Position among the siblings, lower comes first and is drawn below
+    pub order: i32,
+    pub visible: bool,
+    pub focusable: bool,
+    /// Screen rectangle computed by the layout pass
+    pub bounds: Rect,
+    /// Whether this element and all of its ancestors are visible, computed by the layout pass
+    pub shown: bool,
+    /// Position in drawing order, computed by the layout pass
+    pub depth: usize,
+}
+
+impl UiComponent {
+    pub fn new(widget: Widget, size: (u32, u32)) -> Self {
+        let focusable = matches!(widget, Widget::Button { .. } | Widget::List { .. });
+        Self {
+            widget,
+            parent: None,
+            anchor: Anchor::TopLeft,
+            offset: Point::new(0, 0),
+            size,
+            layout: Layout::Free,
+            padding: 0,
+            spacing: 0,
+            order: 0,
+            visible: true,
+            focusable,
+            bounds: Rect::new(0, 0, 1, 1),
+            shown: false,
+            depth: 0,
+        }
+    }
+    pub fn with_parent(mut self, parent: Entity, order: i32) -> Self {
+        self.parent = Some(parent);
+        self.order = order;
+        self
+    }
+    pub fn anchored(mut self, anchor: Anchor, offset: Point) -> Self {
+        self.anchor = anchor;
+        self.offset = offset;
+        self
+    }
+    pub fn with_layout(mut self, layout: Layout, padding: i32, spacing: i32) -> Self {
+        self.layout = layout;
+        self.padding = padding;
+        self.spacing = spacing;
+        self
+    }
+    pub fn hidden(mut self) -> Self {
+        self.visible = false;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatChannel {
+    Say,
+    Party,
+    Whisper,
+    System,
+}
+impl fmt::Display for ChatChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channel = match self {
+            Self::Say => 0,
+            Self::Party => 1,
+            Self::Whisper => 2,
+            Self::System => 3,
+        };
+        f.write_str(&channel.to_string())
+    }
+}
+impl FromStr for ChatChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(ChatChannel::Say),
+            "1" => Ok(ChatChannel::Party),
+            "2" => Ok(ChatChannel::Whisper),
+            "3" => Ok(ChatChannel::System),
+            _ => Err(format!("unknown chat channel: {}", s)),
+        }
+    }
+}
+
+/// A single line of chat, either received from the server or typed by the player.
+///
+/// On the wire the channel is part of the opcode (`C0;` say, `C1;` party, `C2;` whisper,
+/// `C3;` system). The server sends `C<n>;sender;text` (`C3;text` for system messages) and
+/// the client sends `C<n>;player_id;text`, or `C2;player_id;target;text` for whispers.
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub channel: ChatChannel,
+    pub sender: String,
+    /// Recipient of an outgoing whisper, empty otherwise
+    pub target: String,
+    pub text: String,
+}
+
+impl ChatMessage {
+    pub fn new(channel: ChatChannel, sender: String, target: String, text: String) -> Self {
+        Self {
+            channel,
+            sender,
+            target,
+            text,
+        }
+    }
+    pub fn from_str(channel: ChatChannel, string: &str) -> Self {
+        if channel == ChatChannel::System {
+            return Self::new(channel, String::new(), String::new(), string.to_string());
+        }
+        let mut parts = string.splitn(2, ';');
+        Self::new(
+            channel,
+            parts.next().unwrap_or_default().to_string(),
+            String::new(),
+            parts.next().unwrap_or_default().to_string(),
+        )
+    }
+    /// Serializes the message into the command sent to the server
+    pub fn to_command(&self) -> String {
+        match self.channel {
+            ChatChannel::Whisper => format!(
+                "C{};{};{};{}",
+                self.channel, self.sender, self.target, self.text
+            ),
+            _ => format!("C{};{};{}", self.channel, self.sender, self.text),
+        }
+    }
+}
+
+/// What a character spends to cast its abilities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerKind {
+    Mana,
+    Energy,
+    Rage,
+}
+impl PowerKind {
+    /// Points gained per second unless the server says otherwise. Rage runs out instead.
+    pub fn default_regen(&self) -> i32 {
+        match self {
+            Self::Mana => 2,
+            Self::Energy => 10,
+            Self::Rage => -1,
+        }
+    }
+}
+impl fmt::Display for PowerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Mana => 0,
+            Self::Energy => 1,
+            Self::Rage => 2,
+        };
+        f.write_str(&kind.to_string())
+    }
+}
+impl FromStr for PowerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(PowerKind::Mana),
+            "1" => Ok(PowerKind::Energy),
+            "2" => Ok(PowerKind::Rage),
+            _ => Err(format!("unknown power kind: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Power {
+    pub kind: PowerKind,
+    pub value: u32,
+    pub max: u32,
+    /// Points gained per second, negative for rage which runs out on its own
+    pub regen: i32,
+}
+
+impl Power {
+    /// Full power, or an empty one for rage which is built up in combat
+    pub fn new(kind: PowerKind, max: u32) -> Self {
+        Self {
+            kind,
+            value: if kind == PowerKind::Rage { 0 } else { max },
+            max,
+            regen: kind.default_regen(),
+        }
+    }
+
+    /// Adds `amount` points, or removes them when negative, staying within 0 and `max`
+    pub fn add(&mut self, amount: i32) {
+        self.value = (self.value as i64 + amount as i64).clamp(0, self.max as i64) as u32;
+    }
+}
 
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
 pub struct Status {
     pub alive: bool,
     pub health: u32,
+    pub max_health: u32,
+    /// `None` for characters whose abilities cost nothing
+    pub power: Option<Power>,
+}
+
+impl Status {
+    pub fn new(max_health: u32, power: Option<Power>) -> Self {
+        Self {
+            alive: true,
+            health: max_health,
+            max_health,
+            power,
+        }
+    }
+
+    /// Whether there is enough of the power left to pay `cost`
+    pub fn can_afford(&self, cost: u32) -> bool {
+        self.power.as_ref().is_none_or(|power| cost <= power.value)
+    }
+
+    pub fn spend(&mut self, cost: u32) {
+        if let Some(power) = &mut self.power {
+            power.value = power.value.saturating_sub(cost);
+        }
+    }
+}
+
+/// A buff or debuff on a character and how long it lasts
+#[derive(Debug, Clone)]
+pub struct Aura {
+    /// Id of the effect in the effect definitions
+    pub effect: String,
+    /// Time left in milliseconds
+    pub remaining: u32,
+    pub duration: u32,
+    pub stacks: u32,
+}
+
+/// The buffs and debuffs on a character, as the server applies and removes them
+#[derive(Component, Debug, Clone, Default)]
+#[storage(VecStorage)]
+pub struct Effects(pub Vec<Aura>);
+
+impl Effects {
+    /// Adds an aura, replacing the one of the same effect so reapplying refreshes it
+    pub fn apply(&mut self, aura: Aura) {
+        self.remove(&aura.effect);
+        self.0.push(aura);
+    }
+
+    pub fn remove(&mut self, effect: &str) {
+        self.0.retain(|aura| aura.effect != effect);
+    }
+}
+
+/// Fraction of `max` that `value` makes up, 0 when there is no maximum
+pub fn fraction(value: u32, max: u32) -> f32 {
+    if max == 0 {
+        return 0.0;
+    }
+    (value as f32 / max as f32).clamp(0.0, 1.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CombatKind {
+    Damage,
+    Heal,
+    Miss,
+    Crit,
+}
+impl fmt::Display for CombatKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Damage => 0,
+            Self::Heal => 1,
+            Self::Miss => 2,
+            Self::Crit => 3,
+        };
+        f.write_str(&kind.to_string())
+    }
+}
+impl FromStr for CombatKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(CombatKind::Damage),
+            "1" => Ok(CombatKind::Heal),
+            "2" => Ok(CombatKind::Miss),
+            "3" => Ok(CombatKind::Crit),
+            _ => Err(format!("unknown combat kind: {}", s)),
+        }
+    }
+}
+
+/// Parses a number field of a server message, `name` tells which field is broken
+fn parse_field<T: FromStr>(field: Option<&str>, name: &str) -> Result<T, String> {
+    let field = field.unwrap_or_default();
+    field
+        .parse::<T>()
+        .map_err(|_| format!("invalid {}: {:?}", name, field))
+}
+
+/// Like `parse_field` for a field that may be left out, which then is `default`
+fn parse_optional<T: FromStr>(field: Option<&str>, name: &str, default: T) -> Result<T, String> {
+    match field {
+        Some(_) => parse_field(field, name),
+        None => Ok(default),
+    }
+}
+
+/// The outcome of a hit or heal as sent by the server: `H0;target_id;kind;amount;ability`.
+/// The ability is left out for hits that don't come from one.
+#[derive(Clone, Debug)]
+pub struct CombatUpdate {
+    pub target: String,
+    pub kind: CombatKind,
+    pub amount: u32,
+    pub ability: Option<String>,
+}
+
+impl CombatUpdate {
+    pub fn from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        Ok(Self {
+            target: parts.next().unwrap_or_default().to_string(),
+            kind: parts.next().unwrap_or_default().parse::<CombatKind>()?,
+            amount: parse_field(parts.next(), "amount")?,
+            ability: parts
+                .next()
+                .filter(|ability| !ability.is_empty())
+                .map(str::to_string),
+        })
+    }
+}
+
+/// The health and power of a character as sent by the server:
+/// `R0;id;health;max_health;kind;value;max;regen`. The last four are left out for
+/// characters without power.
+#[derive(Clone, Debug)]
+pub struct StatusUpdate {
+    pub id: String,
+    pub health: u32,
+    pub max_health: u32,
+    pub power: Option<Power>,
+}
+
+impl StatusUpdate {
+    pub fn from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        let id = parts.next().unwrap_or_default().to_string();
+        let health = parse_field(parts.next(), "health")?;
+        let max_health = parse_field(parts.next(), "max health")?;
+        let power = match parts.next().filter(|kind| !kind.is_empty()) {
+            Some(kind) => {
+                let kind = kind.parse::<PowerKind>()?;
+                Some(Power {
+                    kind,
+                    value: parse_field(parts.next(), "power")?,
+                    max: parse_field(parts.next(), "max power")?,
+                    regen: parse_optional(parts.next(), "regen", kind.default_regen())?,
+                })
+            }
+            None => None,
+        };
+        Ok(Self {
+            id,
+            health,
+            max_health,
+            power,
+        })
+    }
+}
+
+/// Level, experience and primary stats of a character, synced from the server
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq)]
+#[storage(VecStorage)]
+pub struct CharacterStats {
+    pub level: u32,
+    pub experience: u32,
+    /// Experience the next level takes, 0 at the highest level
+    pub next_level: u32,
+    /// Stats without the equipment
+    pub stats: Stats,
+}
+
+/// Progress of a character sent by the server:
+/// `X0;id;level;experience;next_level;strength;agility;intellect;stamina;armor`
+#[derive(Clone, Debug)]
+pub struct ProgressUpdate {
+    pub id: String,
+    pub progress: CharacterStats,
+}
+
+impl ProgressUpdate {
+    pub fn from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        let id = parts.next().unwrap_or_default().to_string();
+        let level = parse_field(parts.next(), "level")?;
+        let experience = parse_field(parts.next(), "experience")?;
+        let next_level = parse_field(parts.next(), "next level")?;
+        let mut stat = |name| parse_field::<i32>(parts.next(), name);
+        let stats = Stats {
+            strength: stat("strength")?,
+            agility: stat("agility")?,
+            intellect: stat("intellect")?,
+            stamina: stat("stamina")?,
+            armor: stat("armor")?,
+        };
+        Ok(Self {
+            id,
+            progress: CharacterStats {
+                level,
+                experience,
+                next_level,
+                stats,
+            },
+        })
+    }
+}
+
+/// A buff or debuff put on or taken off a character by the server:
+/// `E0;target_id;effect;duration;stacks` applies or refreshes an effect for `duration`
+/// milliseconds, `E1;target_id;effect` removes it early
+#[derive(Clone, Debug)]
+pub enum EffectUpdate {
+    Apply { target: String, aura: Aura },
+    Remove { target: String, effect: String },
 }
 
+impl EffectUpdate {
+    pub fn apply_from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        let target = parts.next().unwrap_or_default().to_string();
+        let effect = parts.next().unwrap_or_default().to_string();
+        let duration = parse_field(parts.next(), "effect duration")?;
+        let stacks = parse_optional(parts.next(), "effect stacks", 1)?;
+        Ok(Self::Apply {
+            target,
+            aura: Aura {
+                effect,
+                remaining: duration,
+                duration,
+                stacks,
+            },
+        })
+    }
+
+    pub fn remove_from_str(string: &str) -> Self {
+        let mut parts = string.split(';');
+        Self::Remove {
+            target: parts.next().unwrap_or_default().to_string(),
+            effect: parts.next().unwrap_or_default().to_string(),
+        }
+    }
+
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Apply { target, .. } | Self::Remove { target, .. } => target,
+        }
+    }
+}
+
+/// Number of slots in the bags of a character
+pub const BAG_SLOTS: usize = 20;
+
+/// Some amount of a single item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemStack {
+    /// Id of the item in the item definitions
+    pub item: String,
+    pub count: u32,
+}
+
+/// The bags of a character, as the server fills them
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct Inventory {
+    pub slots: Vec<Option<ItemStack>>,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self {
+            slots: vec![None; BAG_SLOTS],
+        }
+    }
+}
+
+impl Inventory {
+    pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    /// Puts a stack into a slot, `None` empties it
+    pub fn set(&mut self, slot: usize, stack: Option<ItemStack>) {
+        if let Some(current) = self.slots.get_mut(slot) {
+            *current = stack;
+        }
+    }
+
+    /// Moves the stack in `from` onto `to`. Stacks of the same item are merged up to
+    /// `stack_size`, other stacks trade places.
+    pub fn move_stack(&mut self, from: usize, to: usize, stack_size: u32) {
+        if from >= self.slots.len() || to >= self.slots.len() || from == to {
+            return;
+        }
+        if let (Some(source), Some(target)) = (&self.slots[from], &self.slots[to]) {
+            if source.item == target.item {
+                let moved = source.count.min(stack_size.saturating_sub(target.count));
+                if let Some(target) = &mut self.slots[to] {
+                    target.count += moved;
+                }
+                if let Some(source) = &mut self.slots[from] {
+                    source.count -= moved;
+                }
+                if self.slots[from]
+                    .as_ref()
+                    .is_some_and(|stack| stack.count == 0)
+                {
+                    self.slots[from] = None;
+                }
+                return;
+            }
+        }
+        self.slots.swap(from, to);
+    }
+}
+
+/// The items a character wears, by id
+#[derive(Component, Debug, Clone, Default)]
+#[storage(VecStorage)]
+pub struct Equipment(pub HashMap<EquipSlot, String>);
+
+/// A change to the bags or gear of a character sent by the server:
+/// `I0;id;slot;item;count` puts `count` of an item into a bag slot, `I1;id;equip_slot;item`
+/// puts an item into an equipment slot. An empty item empties the slot.
+#[derive(Clone, Debug)]
+pub enum InventoryUpdate {
+    Bag {
+        id: String,
+        slot: usize,
+        stack: Option<ItemStack>,
+    },
+    Equipment {
+        id: String,
+        slot: EquipSlot,
+        item: Option<String>,
+    },
+}
+
+impl InventoryUpdate {
+    pub fn bag_from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        let id = parts.next().unwrap_or_default().to_string();
+        let slot = parse_field(parts.next(), "bag slot")?;
+        let item = parts.next().unwrap_or_default();
+        let count = parse_optional(parts.next(), "item count", 1)?;
+        let stack = (!item.is_empty() && count > 0).then(|| ItemStack {
+            item: item.to_string(),
+            count,
+        });
+        Ok(Self::Bag { id, slot, stack })
+    }
+
+    pub fn equipment_from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        Ok(Self::Equipment {
+            id: parts.next().unwrap_or_default().to_string(),
+            slot: parts.next().unwrap_or_default().parse::<EquipSlot>()?,
+            item: parts
+                .next()
+                .filter(|item| !item.is_empty())
+                .map(|item| item.to_string()),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Bag { id, .. } | Self::Equipment { id, .. } => id,
+        }
+    }
+}
+
+/// Asks the server to do something with an item of the player: `U0;player_id;slot` uses
+/// the item in a bag slot, `U1;player_id;slot` equips it, `U2;player_id;equip_slot` puts a
+/// worn item back into the bags, `U3;player_id;slot` drops it and
+/// `U4;player_id;from;to` moves it to another bag slot, merging stacks of the same item
+#[derive(Clone, Debug)]
+pub enum ItemRequest {
+    Use(usize),
+    Equip(usize),
+    Unequip(EquipSlot),
+    Drop(usize),
+    Move { from: usize, to: usize },
+}
+
+impl ItemRequest {
+    pub fn to_command(&self) -> String {
+        match self {
+            ItemRequest::Use(slot) => format!("U0;{};{}", PLAYER_ID, slot),
+            ItemRequest::Equip(slot) => format!("U1;{};{}", PLAYER_ID, slot),
+            ItemRequest::Unequip(slot) => format!("U2;{};{}", PLAYER_ID, slot),
+            ItemRequest::Drop(slot) => format!("U3;{};{}", PLAYER_ID, slot),
+            ItemRequest::Move { from, to } => format!("U4;{};{};{}", PLAYER_ID, from, to),
+        }
+    }
+}
+
+/// Items lying in the world for the player to pick up, like a bag or a corpse
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct Loot {
+    /// Id the server knows the loot by
+    pub id: String,
+    /// Clip of the loot spritesheet it is drawn with
+    pub clip: String,
+    /// Title of the loot window, like the name of the creature that dropped it
+    pub name: String,
+    pub items: Vec<ItemStack>,
+}
+
+/// The loot shown in the loot window
+#[derive(Default)]
+pub struct OpenLoot(pub Option<Entity>);
+
+/// Loot appearing or going away, sent by the server:
+/// `O0;loot_id;clip;name;x;y;item:count,item:count` puts loot into the world or replaces
+/// what is in it, `O1;loot_id` takes it away once it is empty or rots
+#[derive(Clone, Debug)]
+pub enum LootUpdate {
+    Drop { loot: Loot, position: Point },
+    Remove { id: String },
+}
+
+impl LootUpdate {
+    pub fn drop_from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        let id = parts.next().unwrap_or_default().to_string();
+        let clip = parts.next().unwrap_or_default().to_string();
+        let name = parts.next().unwrap_or_default().to_string();
+        let position = Point::new(
+            parse_field(parts.next(), "loot x")?,
+            parse_field(parts.next(), "loot y")?,
+        );
+        let mut items = Vec::new();
+        for entry in parts.next().unwrap_or_default().split(',') {
+            let (item, count) = match entry.split_once(':') {
+                Some((item, count)) => (item, Some(count)),
+                None => (entry, None),
+            };
+            let count = parse_optional(count, "item count", 1)?;
+            if !item.is_empty() && count > 0 {
+                items.push(ItemStack {
+                    item: item.to_string(),
+                    count,
+                });
+            }
+        }
+        Ok(Self::Drop {
+            loot: Loot {
+                id,
+                clip,
+                name,
+                items,
+            },
+            position,
+        })
+    }
+
+    pub fn remove_from_str(string: &str) -> Self {
+        Self::Remove {
+            id: string.split(';').next().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// Asks the server for loot: `G0;player_id;loot_id;index` takes one of the stacks,
+/// `G1;player_id;loot_id` takes everything. What was taken arrives as inventory updates.
+#[derive(Clone, Debug)]
+pub enum LootRequest {
+    Take { loot: String, index: usize },
+    TakeAll { loot: String },
+}
+
+impl LootRequest {
+    pub fn to_command(&self) -> String {
+        match self {
+            LootRequest::Take { loot, index } => format!("G0;{};{};{}", PLAYER_ID, loot, index),
+            LootRequest::TakeAll { loot } => format!("G1;{};{}", PLAYER_ID, loot),
+        }
+    }
+}
+
+/// Where the player stands with a quest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuestState {
+    /// The giver offers it
+    Available,
+    /// Taken and in progress
+    Active,
+    /// Every objective is done, the giver waits for the player to return
+    Complete,
+}
+
+impl FromStr for QuestState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(QuestState::Available),
+            "1" => Ok(QuestState::Active),
+            "2" => Ok(QuestState::Complete),
+            _ => Err(format!("unknown quest state: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quest {
+    pub id: String,
+    pub state: QuestState,
+    /// How far along each objective is, in the order of the quest's objectives
+    pub progress: Vec<u32>,
+}
+
+/// Every quest the server told the player about, in the order they arrived
+#[derive(Default)]
+pub struct QuestLog {
+    pub quests: Vec<Quest>,
+    /// Id of the quest shown in the quest log window
+    pub selected: Option<String>,
+}
+
+impl QuestLog {
+    /// The quests the player has taken, in the order they were taken
+    pub fn accepted(&self) -> impl Iterator<Item = &Quest> {
+        self.quests
+            .iter()
+            .filter(|quest| quest.state != QuestState::Available)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Quest> {
+        self.quests.iter().find(|quest| quest.id == id)
+    }
+}
+
+/// A quest changing state or going away, sent by the server: `Q0;quest_id;state;progress`
+/// with the progress of every objective separated by commas, `Q1;quest_id` once it was
+/// turned in or abandoned
+#[derive(Clone, Debug)]
+pub enum QuestUpdate {
+    State(Quest),
+    Remove { id: String },
+}
+
+impl QuestUpdate {
+    pub fn state_from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        let id = parts.next().unwrap_or_default().to_string();
+        let state = parts.next().unwrap_or_default().parse::<QuestState>()?;
+        let progress = parts
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|count| !count.is_empty())
+            .map(|count| parse_field(Some(count), "objective progress"))
+            .collect::<Result<_, _>>()?;
+        Ok(QuestUpdate::State(Quest {
+            id,
+            state,
+            progress,
+        }))
+    }
+
+    pub fn remove_from_str(string: &str) -> Self {
+        QuestUpdate::Remove {
+            id: string.split(';').next().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// Asks the server to drop a quest from the log: `Q2;player_id;quest_id`. Numbered after the
+/// quest updates of the server so the opcodes don't overlap.
+#[derive(Clone, Debug)]
+pub enum QuestRequest {
+    Abandon(String),
+}
+
+impl QuestRequest {
+    pub fn to_command(&self) -> String {
+        match self {
+            QuestRequest::Abandon(quest) => format!("Q2;{};{}", PLAYER_ID, quest),
+        }
+    }
+}
+
+/// A point marked on the minimap, in world coordinates: `N0;sender;x;y`. Sent when the
+/// player clicks the minimap, the server relays it to the party.
+#[derive(Clone, Debug)]
+pub struct MinimapPing {
+    pub sender: String,
+    pub position: Point,
+}
+
+impl MinimapPing {
+    pub fn from_str(string: &str) -> Result<Self, String> {
+        let mut parts = string.split(';');
+        Ok(Self {
+            sender: parts.next().unwrap_or_default().to_string(),
+            position: Point::new(
+                parse_field(parts.next(), "x")?,
+                parse_field(parts.next(), "y")?,
+            ),
+        })
+    }
+
+    pub fn to_command(&self) -> String {
+        format!(
+            "N0;{};{};{}",
+            self.sender,
+            self.position.x(),
+            self.position.y()
+        )
+    }
+}
+
+/// A change in health that happened to an entity this tick
+#[derive(Clone, Debug)]
+pub struct CombatEvent {
+    pub entity: Entity,
+    pub kind: CombatKind,
+    pub amount: u32,
+}
+
+/// Combat events of the current tick, filled by the health checker
+#[derive(Default)]
+pub struct CombatEvents(pub Vec<CombatEvent>);
+
+/// A number floating up from an entity that got hit or healed
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct FloatingText {
+    pub target: Entity,
+    pub kind: CombatKind,
+    pub amount: u32,
+    /// Number of hits batched into this text
+    pub hits: u32,
+    /// Where the text spawned, in world coordinates
+    pub origin: Point,
+    /// Milliseconds since the text appeared
+    pub age: u32,
+}
+
+/// The entity the player has selected with Tab
+#[derive(Default)]
+pub struct Target(pub Option<Entity>);
+
+/// Refers to a texture owned by the asset manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TextureHandle(pub usize);
+
 #[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
 pub struct Sprite {
     /// The specific spritesheet to render from
-    pub spritesheet: usize,
+    pub spritesheet: TextureHandle,
     /// The current region of the spritesheet to be rendered
     pub region: Rect,
+    /// The point of the region drawn at the position of the entity
+    pub origin: Point,
+}
+
+/// What a character is doing, picks the clip of its spritesheet that is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Attack,
+    Hit,
+    Death,
+}
+
+impl AnimationState {
+    /// Prefix of the clip names of this state in spritesheet definitions
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Walk => "walk",
+            Self::Attack => "attack",
+            Self::Hit => "hit",
+            Self::Death => "death",
+        }
+    }
+
+    /// States that play their clip once and then return to idle or walking
+    pub fn is_one_shot(self) -> bool {
+        matches!(self, Self::Attack | Self::Hit)
+    }
+
+    /// A state only interrupts states of the same or a lower priority
+    pub fn priority(self) -> u8 {
+        match self {
+            Self::Idle | Self::Walk => 0,
+            Self::Hit => 1,
+            Self::Attack => 2,
+            Self::Death => 3,
+        }
+    }
 }
 
 #[derive(Component, Debug)]
 #[storage(VecStorage)]
-pub struct MovementAnimation {
-    // The current frame in the animation of the direction this entity is moving in
-    pub current_frame: usize,
-    pub up_frames: Vec<Sprite>,
-    pub down_frames: Vec<Sprite>,
-    pub left_frames: Vec<Sprite>,
-    pub right_frames: Vec<Sprite>,
+pub struct AnimationController {
+    /// Id of the spritesheet definition the clips come from
+    pub spritesheet: String,
+    pub state: AnimationState,
+    /// Direction of the last movement, picks between the directional variants of a clip
+    pub facing: Direction,
+    /// Milliseconds since the clip of the current state started
+    pub elapsed: u32,
+}
+
+impl AnimationController {
+    pub fn new(spritesheet: &str) -> Self {
+        Self {
+            spritesheet: spritesheet.to_string(),
+            state: AnimationState::Idle,
+            facing: Direction::Down,
+            elapsed: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(item: &str, count: u32) -> Option<ItemStack> {
+        Some(ItemStack {
+            item: item.to_string(),
+            count,
+        })
+    }
+
+    #[test]
+    fn chat_message_keeps_semicolons_in_the_text() {
+        let message = ChatMessage::from_str(ChatChannel::Party, "reaper;meet me; now");
+        assert_eq!(message.channel, ChatChannel::Party);
+        assert_eq!(message.sender, "reaper");
+        assert_eq!(message.text, "meet me; now");
+
+        let message = ChatMessage::from_str(ChatChannel::System, "Server; restarting");
+        assert_eq!(message.sender, "");
+        assert_eq!(message.text, "Server; restarting");
+    }
+
+    #[test]
+    fn combat_update_with_and_without_an_ability() {
+        let update = CombatUpdate::from_str("reaper;3;42;fireball").unwrap();
+        assert_eq!(update.target, "reaper");
+        assert_eq!(update.kind, CombatKind::Crit);
+        assert_eq!(update.amount, 42);
+        assert_eq!(update.ability.as_deref(), Some("fireball"));
+
+        let update = CombatUpdate::from_str("reaper;0;7").unwrap();
+        assert_eq!(update.kind, CombatKind::Damage);
+        assert_eq!(update.ability, None);
+
+        assert!(CombatUpdate::from_str("reaper;9;7").is_err());
+        assert!(CombatUpdate::from_str("reaper;0;lots").is_err());
+        assert!(CombatUpdate::from_str("reaper;0").is_err());
+    }
+
+    #[test]
+    fn status_update_with_and_without_power() {
+        let update = StatusUpdate::from_str("blub_id;60;100;0;30;100;5").unwrap();
+        assert_eq!(update.id, "blub_id");
+        assert_eq!((update.health, update.max_health), (60, 100));
+        assert_eq!(
+            update.power,
+            Some(Power {
+                kind: PowerKind::Mana,
+                value: 30,
+                max: 100,
+                regen: 5,
+            })
+        );
+
+        let update = StatusUpdate::from_str("blub_id;60;100;2;10;100").unwrap();
+        assert_eq!(update.power.map(|power| power.regen), Some(-1));
+
+        let update = StatusUpdate::from_str("reaper;20;100").unwrap();
+        assert_eq!(update.power, None);
+
+        assert!(StatusUpdate::from_str("reaper;20;100;7;0;0").is_err());
+        assert!(StatusUpdate::from_str("reaper;dead;100").is_err());
+        assert!(StatusUpdate::from_str("reaper;20").is_err());
+        assert!(StatusUpdate::from_str("reaper;20;100;0;full;100").is_err());
+        assert!(StatusUpdate::from_str("reaper;20;100;0;30;100;fast").is_err());
+    }
+
+    #[test]
+    fn effect_update_applies_and_removes() {
+        let update = EffectUpdate::apply_from_str("blub_id;poison;7400;3").unwrap();
+        let EffectUpdate::Apply { target, aura } = update else {
+            panic!("expected an applied effect");
+        };
+        assert_eq!(target, "blub_id");
+        assert_eq!(aura.effect, "poison");
+        assert_eq!(
+            (aura.remaining, aura.duration, aura.stacks),
+            (7400, 7400, 3)
+        );
+
+        let update = EffectUpdate::apply_from_str("blub_id;shield;0").unwrap();
+        let EffectUpdate::Apply { aura, .. } = update else {
+            panic!("expected an applied effect");
+        };
+        assert_eq!(aura.stacks, 1);
+
+        assert!(EffectUpdate::apply_from_str("blub_id;poison;soon").is_err());
+        assert!(EffectUpdate::apply_from_str("blub_id;poison;100;many").is_err());
+
+        let update = EffectUpdate::remove_from_str("blub_id;poison");
+        assert_eq!(update.target(), "blub_id");
+        assert!(matches!(update, EffectUpdate::Remove { effect, .. } if effect == "poison"));
+    }
+
+    #[test]
+    fn inventory_update_fills_and_empties_slots() {
+        let update = InventoryUpdate::bag_from_str("blub_id;2;copper_ring;1").unwrap();
+        let InventoryUpdate::Bag {
+            id,
+            slot,
+            stack: bag_stack,
+        } = update
+        else {
+            panic!("expected a bag update");
+        };
+        assert_eq!((id.as_str(), slot), ("blub_id", 2));
+        assert_eq!(bag_stack, stack("copper_ring", 1));
+
+        let update = InventoryUpdate::bag_from_str("blub_id;4;;0").unwrap();
+        assert!(matches!(
+            update,
+            InventoryUpdate::Bag {
+                slot: 4,
+                stack: None,
+                ..
+            }
+        ));
+
+        assert!(InventoryUpdate::bag_from_str("blub_id;first;copper_ring;1").is_err());
+        assert!(InventoryUpdate::bag_from_str("blub_id;2;copper_ring;some").is_err());
+
+        let update = InventoryUpdate::equipment_from_str("blub_id;8;wooden_shield").unwrap();
+        assert!(matches!(
+            update,
+            InventoryUpdate::Equipment { slot: EquipSlot::OffHand, item: Some(item), .. }
+                if item == "wooden_shield"
+        ));
+        let update = InventoryUpdate::equipment_from_str("blub_id;0;").unwrap();
+        assert!(matches!(
+            update,
+            InventoryUpdate::Equipment { item: None, .. }
+        ));
+
+        assert!(InventoryUpdate::equipment_from_str("blub_id;9;ring").is_err());
+    }
+
+    #[test]
+    fn loot_update_drops_items_at_a_position() {
+        let update =
+            LootUpdate::drop_from_str("reaper;corpse;Reaper;50;-20;wolf_pelt:3,rusty_sword")
+                .unwrap();
+        let LootUpdate::Drop { loot, position } = update else {
+            panic!("expected dropped loot");
+        };
+        assert_eq!(
+            (loot.id.as_str(), loot.clip.as_str(), loot.name.as_str()),
+            ("reaper", "corpse", "Reaper")
+        );
+        assert_eq!(position, Point::new(50, -20));
+        assert_eq!(
+            loot.items,
+            vec![
+                stack("wolf_pelt", 3).unwrap(),
+                stack("rusty_sword", 1).unwrap()
+            ]
+        );
+
+        let update = LootUpdate::drop_from_str("bag;bag;;0;0;").unwrap();
+        assert!(matches!(update, LootUpdate::Drop { loot, .. } if loot.items.is_empty()));
+
+        assert!(LootUpdate::drop_from_str("bag;bag;;left;0;").is_err());
+        assert!(LootUpdate::drop_from_str("bag;bag;;0;0;wolf_pelt:lots").is_err());
+
+        let update = LootUpdate::remove_from_str("bag");
+        assert!(matches!(update, LootUpdate::Remove { id } if id == "bag"));
+    }
+
+    #[test]
+    fn progress_update_reads_level_experience_and_stats() {
+        let update = ProgressUpdate::from_str("blub_id;12;4200;9000;14;9;11;16;-5").unwrap();
+        assert_eq!(update.id, "blub_id");
+        assert_eq!(
+            update.progress,
+            CharacterStats {
+                level: 12,
+                experience: 4200,
+                next_level: 9000,
+                stats: Stats {
+                    strength: 14,
+                    agility: 9,
+                    intellect: 11,
+                    stamina: 16,
+                    armor: -5,
+                },
+            }
+        );
+
+        assert!(ProgressUpdate::from_str("blub_id;12;lots;9000;14;9;11;16;5").is_err());
+        assert!(ProgressUpdate::from_str("blub_id;12;4200;9000;14;9").is_err());
+    }
+
+    #[test]
+    fn quest_update_reads_state_and_progress() {
+        let update = QuestUpdate::state_from_str("wolf_pelts;1;3,0").unwrap();
+        let QuestUpdate::State(quest) = update else {
+            panic!("expected a quest state");
+        };
+        assert_eq!(
+            quest,
+            Quest {
+                id: "wolf_pelts".to_string(),
+                state: QuestState::Active,
+                progress: vec![3, 0],
+            }
+        );
+
+        let update = QuestUpdate::state_from_str("lost_ring;0;").unwrap();
+        assert!(matches!(update, QuestUpdate::State(quest) if quest.progress.is_empty()));
+
+        assert!(QuestUpdate::state_from_str("wolf_pelts;1;3,x").is_err());
+        assert!(QuestUpdate::state_from_str("wolf_pelts;5;").is_err());
+
+        let update = QuestUpdate::remove_from_str("wolf_pelts");
+        assert!(matches!(update, QuestUpdate::Remove { id } if id == "wolf_pelts"));
+
+        let abandon = QuestRequest::Abandon("wolf_pelts".to_string());
+        assert_eq!(abandon.to_command(), format!("Q2;{};wolf_pelts", PLAYER_ID));
+    }
+
+    #[test]
+    fn minimap_ping_round_trips() {
+        let ping = MinimapPing::from_str("friend;400;-100").unwrap();
+        assert_eq!(ping.sender, "friend");
+        assert_eq!(ping.position, Point::new(400, -100));
+        assert_eq!(ping.to_command(), "N0;friend;400;-100");
+
+        assert!(MinimapPing::from_str("friend;north;-100").is_err());
+        assert!(MinimapPing::from_str("friend;400").is_err());
+    }
+
+    #[test]
+    fn move_stack_merges_up_to_the_stack_size() {
+        let mut bags = Inventory::default();
+        bags.set(0, stack("wolf_pelt", 15));
+        bags.set(1, stack("wolf_pelt", 10));
+        bags.move_stack(0, 1, 20);
+        assert_eq!(bags.get(0), stack("wolf_pelt", 5).as_ref());
+        assert_eq!(bags.get(1), stack("wolf_pelt", 20).as_ref());
+
+        bags.set(2, stack("wolf_pelt", 5));
+        bags.move_stack(0, 2, 20);
+        assert_eq!(bags.get(0), None);
+        assert_eq!(bags.get(2), stack("wolf_pelt", 10).as_ref());
+    }
+
+    #[test]
+    fn move_stack_swaps_other_items_and_ignores_bad_slots() {
+        let mut bags = Inventory::default();
+        bags.set(0, stack("wolf_pelt", 3));
+        bags.set(1, stack("copper_ring", 1));
+        bags.move_stack(0, 1, 20);
+        assert_eq!(bags.get(0), stack("copper_ring", 1).as_ref());
+        assert_eq!(bags.get(1), stack("wolf_pelt", 3).as_ref());
+
+        bags.move_stack(1, 5, 20);
+        assert_eq!(bags.get(1), None);
+        assert_eq!(bags.get(5), stack("wolf_pelt", 3).as_ref());
+
+        bags.move_stack(5, BAG_SLOTS, 20);
+        assert_eq!(bags.get(5), stack("wolf_pelt", 3).as_ref());
+    }
 }
@@ -0,0 +1,482 @@
+//! NPC conversations.
+//!
+//! Conversations are dialog trees stored as JSON files in `assets/dialogs`, one tree per
+//! file:
+//!
+//! ```json
+//! {
+//!     "id": "bardo_greeting",
+//!     "start": "hello",
+//!     "nodes": {
+//!         "hello": {
+//!             "speaker": "Bardo",
+//!             "portrait": { "texture": "bardo", "region": [26, 0, 26, 36] },
+//!             "pages": ["First page of text.", "Second page of text."],
+//!             "choices": [
+//!                 { "text": "Tell me more.", "next": "more" },
+//!                 { "text": "Goodbye.", "reply": "bye" }
+//!             ]
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! The server opens a conversation with `D0;npc_id;dialog_id[;node_id]` for trees on disk
+//! or `D1;npc_id;{json tree}` to send the whole tree, and closes it with `D2;`. Every choice
+//! the player makes is sent back as `D0;player_id;npc_id;dialog_id;node_id;choice;reply`.
+//! A choice without `next` ends the conversation.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use log::{debug, error};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use serde::Deserialize;
+use specs::prelude::*;
+
+use crate::{assets::AssetIndex, components::*, ui, ServerUpdate};
+
+/// Directory of the dialog trees inside the assets directory
+pub const DIALOG_DIR: &str = "dialogs";
+/// Action of the list of choices in the dialog window
+pub const DIALOG_CHOICE_ACTION: &str = "dialog_choice";
+/// Characters revealed per tick by the typewriter effect
+const CHARS_PER_TICK: usize = 2;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogTree {
+    pub id: String,
+    pub start: String,
+    pub nodes: HashMap<String, DialogNode>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogNode {
+    pub speaker: String,
+    #[serde(default)]
+    pub portrait: Option<Portrait>,
+    pub pages: Vec<String>,
+    #[serde(default)]
+    pub choices: Vec<DialogChoice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Portrait {
+    /// Id of the texture in the asset manifest
+    pub texture: String,
+    /// x, y, width and height of the region in the spritesheet
+    pub region: [i32; 4],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogChoice {
+    pub text: String,
+    /// Node to continue with, the conversation ends without one
+    #[serde(default)]
+    pub next: Option<String>,
+    /// Passed on to the server with the choice
+    #[serde(default)]
+    pub reply: Option<String>,
+}
+
+/// All dialog trees loaded from disk, by id
+#[derive(Default)]
+pub struct DialogLibrary(pub HashMap<String, DialogTree>);
+
+impl DialogLibrary {
+    pub fn load(directory: &Path) -> Self {
+        let mut trees = HashMap::new();
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) => {
+                error!("reading dialog directory {:?}: {}", directory, error);
+                return Self(trees);
+            }
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+                continue;
+            }
+            match fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|json| parse_tree(&json))
+            {
+                Ok(tree) => {
+                    debug!("loaded dialog {} from {:?}", tree.id, path);
+                    trees.insert(tree.id.clone(), tree);
+                }
+                Err(error) => error!("loading dialog {:?}: {}", path, error),
+            }
+        }
+        Self(trees)
+    }
+}
+
+/// Parses a dialog tree and checks that every node it refers to exists
+pub fn parse_tree(json: &str) -> Result<DialogTree, String> {
+    let tree: DialogTree = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if !tree.nodes.contains_key(&tree.start) {
+        return Err(format!("start node {} does not exist", tree.start));
+    }
+    for (id, node) in &tree.nodes {
+        if node.pages.is_empty() {
+            return Err(format!("node {} has no pages", id));
+        }
+        for next in node
+            .choices
+            .iter()
+            .filter_map(|choice| choice.next.as_ref())
+        {
+            if !tree.nodes.contains_key(next) {
+                return Err(format!("node {} leads to missing node {}", id, next));
+            }
+        }
+    }
+    Ok(tree)
+}
+
+/// The conversation currently on screen
+pub struct DialogState {
+    pub npc: String,
+    pub tree: DialogTree,
+    pub node: String,
+    pub page: usize,
+    /// Number of characters of the page shown so far
+    pub revealed: usize,
+}
+
+impl DialogState {
+    fn current(&self) -> &DialogNode {
+        &self.tree.nodes[&self.node]
+    }
+
+    fn page_text(&self) -> &str {
+        &self.current().pages[self.page]
+    }
+
+    fn page_done(&self) -> bool {
+        self.revealed >= self.page_text().chars().count()
+    }
+
+    fn last_page(&self) -> bool {
+        self.page + 1 == self.current().pages.len()
+    }
+
+    /// Whether the choices of the node are on screen and can be picked
+    fn choosing(&self) -> bool {
+        self.last_page() && self.page_done() && !self.current().choices.is_empty()
+    }
+
+    fn go_to(&mut self, node: String) {
+        self.node = node;
+        self.page = 0;
+        self.revealed = 0;
+    }
+}
+
+#[derive(Default)]
+pub struct ActiveDialog(pub Option<DialogState>);
+
+/// A choice made by the player, sent to the server
+#[derive(Debug, Clone)]
+pub struct DialogReply {
+    pub npc: String,
+    pub dialog: String,
+    pub node: String,
+    pub choice: usize,
+    pub reply: String,
+}
+
+impl DialogReply {
+    pub fn to_command(&self) -> String {
+        format!(
+            "D0;{};{};{};{};{};{}",
+            PLAYER_ID, self.npc, self.dialog, self.node, self.choice, self.reply
+        )
+    }
+}
+
+/// Handles a key press while a conversation is open. Space and Enter skip the typewriter
+/// and turn pages, the number keys pick a choice and Escape walks away.
+pub fn handle_input(active: &mut ActiveDialog, event: &Event) -> Option<DialogReply> {
+    let keycode = match event {
+        Event::KeyDown {
+            keycode: Some(keycode),
+            repeat: false,
+            ..
+        } => *keycode,
+        _ => return None,
+    };
+    let state = active.0.as_mut()?;
+    match keycode {
+        Keycode::Escape => active.0 = None,
+        Keycode::Space | Keycode::Return | Keycode::KpEnter => {
+            if !state.page_done() {
+                state.revealed = state.page_text().chars().count();
+            } else if !state.last_page() {
+                state.page += 1;
+                state.revealed = 0;
+            } else if state.current().choices.is_empty() {
+                active.0 = None;
+            }
+        }
+        _ => {
+            let index = keycode as i32 - Keycode::Num1 as i32;
+            if (0..9).contains(&index) {
+                return choose(active, index as usize);
+            }
+        }
+    }
+    None
+}
+
+/// Picks a choice of the current node, moving on to the next node or ending the
+/// conversation
+pub fn choose(active: &mut ActiveDialog, index: usize) -> Option<DialogReply> {
+    let state = active.0.as_mut()?;
+    if !state.choosing() {
+        return None;
+    }
+    let choice = state.current().choices.get(index)?.clone();
+    let reply = DialogReply {
+        npc: state.npc.clone(),
+        dialog: state.tree.id.clone(),
+        node: state.node.clone(),
+        choice: index,
+        reply: choice.reply.unwrap_or_default(),
+    };
+    match choice.next {
+        Some(next) => state.go_to(next),
+        None => active.0 = None,
+    }
+    Some(reply)
+}
+
+/// The UI elements making up the dialog window
+pub struct DialogWindow {
+    pub panel: Entity,
+    pub portrait: Entity,
+    pub speaker: Entity,
+    pub text: Entity,
+    pub choices: Entity,
+    pub hint: Entity,
+}
+
+pub fn create_dialog_window(world: &mut World) -> DialogWindow {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (560, 190),
+            )
+            .anchored(Anchor::Bottom, Point::new(0, -106))
+            .with_layout(Layout::Free, 14, 0)
+            .hidden(),
+        )
+        .build();
+    let portrait = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Image {
+                    spritesheet: TextureHandle::default(),
+                    region: Rect::new(0, 0, 1, 1),
+                },
+                (52, 72),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    let speaker = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: String::new(),
+                    color: Color::RGB(255, 210, 120),
+                },
+                (300, 18),
+            )
+            .with_parent(panel, 1)
+            .anchored(Anchor::TopLeft, Point::new(64, 0)),
+        )
+        .build();
+    let text = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Paragraph {
+                    text: String::new(),
+                    color: Color::WHITE,
+                    revealed: 0,
+                },
+                (466, 72),
+            )
+            .with_parent(panel, 2)
+            .anchored(Anchor::TopLeft, Point::new(64, 22)),
+        )
+        .build();
+    let choices = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::List {
+                    items: Vec::new(),
+                    selected: None,
+                    action: DIALOG_CHOICE_ACTION.to_string(),
+                },
+                (466, 66),
+            )
+            .with_parent(panel, 3)
+            .anchored(Anchor::TopLeft, Point::new(64, 96)),
+        )
+        .build();
+    let hint = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: "[Space]".to_string(),
+                    color: Color::GRAY,
+                },
+                (60, 18),
+            )
+            .with_parent(panel, 4)
+            .anchored(Anchor::BottomRight, Point::new(0, 0)),
+        )
+        .build();
+
+    DialogWindow {
+        panel,
+        portrait,
+        speaker,
+        text,
+        choices,
+        hint,
+    }
+}
+
+/// Opens and closes conversations on request of the server, runs the typewriter and keeps
+/// the dialog window in sync with the conversation
+pub struct DialogSystem;
+
+impl<'a> System<'a> for DialogSystem {
+    type SystemData = (
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        Read<'a, DialogLibrary>,
+        Write<'a, ActiveDialog>,
+        ReadExpect<'a, DialogWindow>,
+        WriteStorage<'a, UiComponent>,
+        Read<'a, AssetIndex>,
+    );
+
+    fn run(
+        &mut self,
+        (server_updates, library, mut active, window, mut nodes, index): Self::SystemData,
+    ) {
+        for server_update in server_updates.iter() {
+            if let ServerUpdate::Dialog(update) = server_update {
+                match update {
+                    DialogUpdate::Open { npc, dialog, node } => match library.0.get(dialog) {
+                        Some(tree) => open(&mut active, npc, tree.clone(), node.clone()),
+                        None => error!("server opened unknown dialog {}", dialog),
+                    },
+                    DialogUpdate::Tree { npc, tree } => open(&mut active, npc, tree.clone(), None),
+                    DialogUpdate::Close => active.0 = None,
+                }
+            }
+        }
+
+        if let Some(state) = active.0.as_mut() {
+            if !state.page_done() {
+                state.revealed += CHARS_PER_TICK;
+            }
+        }
+
+        sync_window(active.0.as_ref(), &window, &mut nodes, &index);
+    }
+}
+
+fn open(active: &mut ActiveDialog, npc: &str, tree: DialogTree, node: Option<String>) {
+    let node = node
+        .filter(|node| tree.nodes.contains_key(node))
+        .unwrap_or_else(|| tree.start.clone());
+    debug!("opening dialog {} at {} with {}", tree.id, node, npc);
+    active.0 = Some(DialogState {
+        npc: npc.to_string(),
+        tree,
+        node,
+        page: 0,
+        revealed: 0,
+    });
+}
+
+fn sync_window(
+    state: Option<&DialogState>,
+    window: &DialogWindow,
+    nodes: &mut WriteStorage<UiComponent>,
+    index: &AssetIndex,
+) {
+    if let Some(panel) = nodes.get_mut(window.panel) {
+        panel.visible = state.is_some();
+    }
+    let state = match state {
+        Some(state) => state,
+        None => return,
+    };
+    let node = state.current();
+
+    if let Some(portrait) = nodes.get_mut(window.portrait) {
+        portrait.visible = node.portrait.is_some();
+        if let (
+            Some(source),
+            Widget::Image {
+                spritesheet,
+                region,
+            },
+        ) = (&node.portrait, &mut portrait.widget)
+        {
+            let [x, y, width, height] = source.region;
+            *spritesheet = index.texture(&source.texture);
+            *region = Rect::new(x, y, width.max(1) as u32, height.max(1) as u32);
+        }
+    }
+    if let Some(Widget::Label { text, .. }) = nodes.get_mut(window.speaker).map(|n| &mut n.widget) {
+        *text = node.speaker.clone();
+    }
+    if let Some(Widget::Paragraph { text, revealed, .. }) =
+        nodes.get_mut(window.text).map(|n| &mut n.widget)
+    {
+        *text = state.page_text().to_string();
+        *revealed = state.revealed;
+    }
+    if let Some(choices) = nodes.get_mut(window.choices) {
+        choices.visible = state.choosing();
+        if let Widget::List {
+            items, selected, ..
+        } = &mut choices.widget
+        {
+            *items = node
+                .choices
+                .iter()
+                .enumerate()
+                .map(|(index, choice)| format!("{}. {}", index + 1, choice.text))
+                .collect();
+            if selected.map(|index| index >= items.len()).unwrap_or(false) {
+                *selected = None;
+            }
+        }
+    }
+    if let Some(hint) = nodes.get_mut(window.hint) {
+        hint.visible = state.page_done() && !state.choosing();
+    }
+}
@@ -0,0 +1,210 @@
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use specs::prelude::*;
+
+use crate::{
+    assets::AssetIndex,
+    chat::ChatLog,
+    components::*,
+    render::{Layer, RenderQueue, TextMetrics},
+    time::GameTime,
+    ui,
+    unit_frames::{UnitFrame, UnitFrames},
+    ServerUpdate,
+};
+
+/// Texture the effect icons are cut from, in cells of `ui::ICON_CELL`
+const ICON_TEXTURE: &str = "effect_icons";
+/// Size the icons are drawn at under the unit frames
+const ICON_SIZE: u32 = 24;
+const ICON_SPACING: i32 = 4;
+
+const BUFF_BORDER: Color = Color::RGB(90, 90, 110);
+const DEBUFF_BORDER: Color = Color::RGB(200, 40, 40);
+
+/// Whether one of the effects keeps the character from acting
+pub fn is_stunned(effects: &Effects, index: &AssetIndex) -> bool {
+    effects.0.iter().any(|aura| {
+        index
+            .effects
+            .get(&aura.effect)
+            .is_some_and(|effect| effect.stun)
+    })
+}
+
+pub type ControlData<'a> = (
+    ReadStorage<'a, Player>,
+    ReadStorage<'a, Effects>,
+    Read<'a, AssetIndex>,
+);
+
+/// Whether the player's character is stunned and ignores movement input
+pub fn input_locked(data: ControlData) -> bool {
+    let (players, effects, index) = data;
+    (&players, &effects)
+        .join()
+        .any(|(player, effects)| player.is_local() && is_stunned(effects, &index))
+}
+
+/// Color the sprite of a character is multiplied with, from the latest effect that has one
+pub fn tint(effects: &Effects, index: &AssetIndex) -> Color {
+    effects
+        .0
+        .iter()
+        .rev()
+        .find_map(|aura| index.effects.get(&aura.effect)?.tint)
+        .map_or(Color::WHITE, |[r, g, b]| Color::RGB(r, g, b))
+}
+
+/// Puts the effects the server sends on characters and lets them run out. Effects on the
+/// player are written to the chat log.
+pub struct EffectSystem;
+
+impl<'a> System<'a> for EffectSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, Effects>,
+        Read<'a, GameTime>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        Read<'a, AssetIndex>,
+        Write<'a, ChatLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, players, mut effects, time, server_updates, index, mut log) = data;
+        for effects in (&mut effects).join() {
+            for aura in effects.0.iter_mut() {
+                aura.remaining = aura.remaining.saturating_sub(time.delta_ms());
+            }
+            // Effects without a duration last until the server removes them
+            effects
+                .0
+                .retain(|aura| aura.duration == 0 || aura.remaining > 0);
+        }
+
+        for server_update in server_updates.iter() {
+            let update = match server_update {
+                ServerUpdate::Effect(update) => update,
+                _ => continue,
+            };
+            let target = (&entities, &players)
+                .join()
+                .find(|(_, player)| player.id == update.target())
+                .map(|(entity, _)| entity);
+            let target = match target.and_then(|target| effects.entry(target).ok()) {
+                Some(entry) => entry.or_insert_with(Effects::default),
+                None => continue,
+            };
+            match update {
+                EffectUpdate::Apply { aura, .. } => target.apply(aura.clone()),
+                EffectUpdate::Remove { effect, .. } => target.remove(effect),
+            }
+
+            if update.target() != PLAYER_ID {
+                continue;
+            }
+            let (effect, applied) = match update {
+                EffectUpdate::Apply { aura, .. } => (&aura.effect, true),
+                EffectUpdate::Remove { effect, .. } => (effect, false),
+            };
+            if let Some(effect) = index.effects.get(effect) {
+                let text = match (applied, effect.harmful) {
+                    (true, true) => format!("You are afflicted by {}", effect.name),
+                    (true, false) => format!("You gain {}", effect.name),
+                    (false, _) => format!("{} fades from you", effect.name),
+                };
+                log.system(&text);
+            }
+        }
+    }
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, Player>,
+    ReadStorage<'a, Effects>,
+    ReadStorage<'a, UiComponent>,
+    Read<'a, Target>,
+    Option<ReadExpect<'a, UnitFrames>>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues a row of effect icons under the unit frames of the player and its target, buffs
+/// first, with the seconds left and the number of stacks
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (entities, players, effects, nodes, target, frames, index) = data;
+    let frames = match frames {
+        Some(frames) => frames,
+        None => return Ok(()),
+    };
+    let player = local_player(&entities, &players);
+
+    for (frame, unit) in [(&frames.player, player), (&frames.target, target.0)] {
+        let effects = match unit.and_then(|unit| effects.get(unit)) {
+            Some(effects) => effects,
+            None => continue,
+        };
+        draw_row(queue, font, frame, effects, &nodes, &index)?;
+    }
+
+    Ok(())
+}
+
+fn draw_row(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    frame: &UnitFrame,
+    effects: &Effects,
+    nodes: &ReadStorage<UiComponent>,
+    index: &AssetIndex,
+) -> Result<(), String> {
+    let panel = match nodes.get(frame.panel) {
+        Some(panel) if panel.shown => panel,
+        _ => return Ok(()),
+    };
+    let z = panel.depth as i32;
+    let mut auras: Vec<_> = effects
+        .0
+        .iter()
+        .filter_map(|aura| Some((aura, index.effects.get(&aura.effect)?)))
+        .collect();
+    auras.sort_by_key(|(_, effect)| effect.harmful);
+
+    let mut position = panel.bounds.bottom_left().offset(0, ICON_SPACING);
+    for (aura, effect) in auras {
+        let icon = Rect::new(position.x(), position.y(), ICON_SIZE, ICON_SIZE);
+        let (texture, region) = ui::icon_region(index, ICON_TEXTURE, effect.icon);
+        queue.copy(Layer::Ui, z, texture, region, icon);
+        let border = if effect.harmful {
+            DEBUFF_BORDER
+        } else {
+            BUFF_BORDER
+        };
+        queue.draw_rect(Layer::Ui, z, border, icon);
+
+        if aura.stacks > 1 {
+            let stacks = aura.stacks.to_string();
+            let (width, height) = font.text_size(&stacks)?;
+            let corner = icon
+                .bottom_right()
+                .offset(-(width as i32), -(height as i32));
+            ui::draw_text(queue, z, font, &stacks, Color::WHITE, corner)?;
+        }
+        if aura.duration > 0 {
+            let seconds = aura.remaining.div_ceil(1000).to_string();
+            let (width, _) = font.text_size(&seconds)?;
+            let below = Point::new(icon.center().x() - width as i32 / 2, icon.bottom());
+            ui::draw_text(queue, z, font, &seconds, Color::WHITE, below)?;
+        }
+
+        position = position.offset(ICON_SIZE as i32 + ICON_SPACING, 0);
+    }
+
+    Ok(())
+}
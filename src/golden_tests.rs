@@ -0,0 +1,567 @@
+use std::env;
+use std::path::Path;
+
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::assets::{AssetIndex, EquipSlot, Stats, ASSETS_DIR};
+use crate::components::*;
+use crate::render::{RenderQueue, Renderer, BACKGROUND};
+use crate::software_renderer::{Image, SoftwareRenderer};
+use crate::{
+    action_bar, chat, inventory, loot, map, minimap, progression, quests, queue_frame, ui,
+    unit_frames, DIMENSION,
+};
+
+/// Golden images, relative to the crate root. Run the tests with `UPDATE_GOLDEN=1` to
+/// write the current output as the new golden images.
+const GOLDEN_DIR: &str = "tests/golden";
+/// Where the output of a failing test is written, relative to the crate root
+const FAILED_DIR: &str = "target/golden";
+/// Largest difference of a color channel for two pixels to count as the same
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Share of the pixels that may differ, for glyph rasterization differences
+const MAX_DIFFERENT_PIXELS: f64 = 0.002;
+
+/// A world set up like the game sets it up, drawn by the software renderer
+struct Scene {
+    world: World,
+    renderer: SoftwareRenderer,
+}
+
+impl Scene {
+    fn new() -> Self {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join(ASSETS_DIR);
+        let (renderer, index) =
+            SoftwareRenderer::load(&root, DIMENSION.width, DIMENSION.height).unwrap();
+        let mut world = World::new();
+        System::setup(&mut ui::UiLayout, &mut world);
+        System::setup(&mut unit_frames::UnitFrameSystem, &mut world);
+        crate::setup_draw_data(&mut world);
+        world.insert(index);
+        Self { world, renderer }
+    }
+
+    fn character(&mut self, spritesheet: &str, animation: &str, position: Point) -> Entity {
+        let sprite = self
+            .world
+            .read_resource::<AssetIndex>()
+            .sprite(spritesheet, animation, 0)
+            .unwrap();
+        self.world
+            .create_entity()
+            .with(Position(position))
+            .with(Status::new(100, None))
+            .with(sprite)
+            .build()
+    }
+
+    fn set_health(&mut self, entity: Entity, health: u32) {
+        if let Some(status) = self.world.write_storage::<Status>().get_mut(entity) {
+            status.health = health;
+        }
+    }
+
+    fn add_effect(&mut self, entity: Entity, effect: &str, remaining: u32, stacks: u32) {
+        let mut effects = self.world.write_storage::<Effects>();
+        if let Ok(entry) = effects.entry(entity) {
+            entry.or_insert_with(Effects::default).apply(Aura {
+                effect: effect.to_string(),
+                remaining,
+                duration: remaining,
+                stacks,
+            });
+        }
+    }
+
+    /// A player that only shows up on the minimap
+    fn remote_player(&mut self, id: &str, team: u8, world_pos: Point) {
+        let player = Player {
+            id: id.to_string(),
+            team,
+            world_pos,
+            ..Default::default()
+        };
+        self.world.create_entity().with(player).build();
+    }
+
+    /// Lays out the UI and draws a frame the way the game loop does
+    fn render(&mut self) -> &Image {
+        ui::UiLayout.run_now(&self.world);
+        self.world.maintain();
+
+        let mut queue = RenderQueue::default();
+        queue.begin(self.renderer.output_size().unwrap());
+        queue_frame(&mut queue, &self.world, self.renderer.font()).unwrap();
+        self.renderer.clear(BACKGROUND);
+        queue.flush(&mut self.renderer).unwrap();
+        self.renderer.frame()
+    }
+}
+
+/// The player of this client at `world_pos` on the minimap
+fn own_player(world_pos: Point) -> Player {
+    Player {
+        id: PLAYER_ID.to_string(),
+        char_name: PLAYER_ID.to_string(),
+        team: 1,
+        world_pos,
+        ..Default::default()
+    }
+}
+
+fn assert_golden(name: &str, image: &Image) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let golden_path = root.join(GOLDEN_DIR).join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        image.save_png(&golden_path).unwrap();
+        return;
+    }
+
+    let golden = Image::load_png(&golden_path)
+        .unwrap_or_else(|e| panic!("{}, run with UPDATE_GOLDEN=1 to create it", e));
+    assert_eq!(
+        (image.width, image.height),
+        (golden.width, golden.height),
+        "size of {}",
+        name
+    );
+    let different = image
+        .pixels
+        .chunks(4)
+        .zip(golden.pixels.chunks(4))
+        .filter(|(actual, expected)| {
+            actual
+                .iter()
+                .zip(expected.iter())
+                .any(|(actual, expected)| actual.abs_diff(*expected) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let allowed = (image.width * image.height) as f64 * MAX_DIFFERENT_PIXELS;
+    if different as f64 > allowed {
+        let actual_path = root.join(FAILED_DIR).join(format!("{}.png", name));
+        image.save_png(&actual_path).unwrap();
+        panic!(
+            "{} differs from its golden image in {} pixels, the output is in {:?}",
+            name, different, actual_path
+        );
+    }
+}
+
+#[test]
+fn characters_in_front_of_the_map_sorted_by_depth() {
+    let mut scene = Scene::new();
+    *scene.world.write_resource::<map::CurrentMap>() = map::CurrentMap(Some("start".into()));
+    let bardo = scene.character("bardo", "idle", Point::new(0, 0));
+    let reaper = scene.character("reaper", "walk_left", Point::new(16, 24));
+    scene.set_health(bardo, 70);
+    scene.set_health(reaper, 20);
+    scene
+        .world
+        .create_entity()
+        .with(FloatingText {
+            target: bardo,
+            kind: CombatKind::Crit,
+            amount: 42,
+            hits: 1,
+            origin: Point::new(0, -40),
+            age: 200,
+        })
+        .build();
+
+    assert_golden("characters", scene.render());
+}
+
+#[test]
+fn interface_with_unit_frames_menu_and_chat() {
+    let mut scene = Scene::new();
+    ui::create_bottom_panel(&mut scene.world);
+    let menu = ui::create_game_menu(&mut scene.world);
+    let frames = unit_frames::create_unit_frames(&mut scene.world);
+    scene.world.insert(frames);
+    if let Some(panel) = scene
+        .world
+        .write_storage::<UiComponent>()
+        .get_mut(menu.panel)
+    {
+        panel.visible = true;
+    }
+
+    let player = scene
+        .world
+        .create_entity()
+        .with(Player::new(
+            PLAYER_ID.to_string(),
+            PLAYER_ID.to_string(),
+            0,
+            Point::new(0, 0),
+            Point::new(0, 0),
+            Direction::Stationary,
+            1,
+        ))
+        .with(Status::new(100, Some(Power::new(PowerKind::Mana, 100))))
+        .build();
+    scene.set_health(player, 60);
+    {
+        let mut log = scene.world.write_resource::<chat::ChatLog>();
+        log.system("Welcome to the realm");
+        log.push(ChatMessage::from_str(
+            ChatChannel::Say,
+            "reaper;hello there",
+        ));
+    }
+    unit_frames::UnitFrameSystem.run_now(&scene.world);
+
+    assert_golden("interface", scene.render());
+}
+
+#[test]
+fn minimap_with_player_dots_and_a_ping() {
+    let mut scene = Scene::new();
+    *scene.world.write_resource::<map::CurrentMap>() = map::CurrentMap(Some("start".into()));
+    minimap::create_minimap(&mut scene.world);
+    scene
+        .world
+        .create_entity()
+        .with(own_player(Point::new(100, 50)))
+        .build();
+    scene.remote_player("friend", 1, Point::new(300, 150));
+    scene.remote_player("reaper", 2, Point::new(-200, 0));
+    scene.remote_player("merchant", 0, Point::new(100, -300));
+    scene
+        .world
+        .write_resource::<minimap::MinimapState>()
+        .ping(MinimapPing {
+            sender: "friend".to_string(),
+            position: Point::new(400, -100),
+        });
+
+    assert_golden("minimap", scene.render());
+}
+
+#[test]
+fn action_bar_with_a_cooldown_and_too_little_mana() {
+    let mut scene = Scene::new();
+    let bottom_panel = ui::create_bottom_panel(&mut scene.world);
+    action_bar::create_action_bar(&mut scene.world, bottom_panel);
+
+    let mut status = Status::new(100, Some(Power::new(PowerKind::Mana, 100)));
+    status.spend(70);
+    let known = scene
+        .world
+        .read_resource::<AssetIndex>()
+        .starting_abilities
+        .clone();
+    let mut book = Abilities::new(known.clone());
+    book.cooldowns.insert("fireball".to_string(), 4500);
+    scene
+        .world
+        .create_entity()
+        .with(own_player(Point::new(0, 0)))
+        .with(status)
+        .with(book)
+        .build();
+    scene
+        .world
+        .insert(action_bar::ActionBar::from_known(&known));
+
+    assert_golden("action_bar", scene.render());
+}
+
+#[test]
+fn effects_tint_sprites_and_show_under_the_unit_frames() {
+    let mut scene = Scene::new();
+    let frames = unit_frames::create_unit_frames(&mut scene.world);
+    scene.world.insert(frames);
+    let player = scene.character("bardo", "idle", Point::new(0, 0));
+    scene
+        .world
+        .write_storage()
+        .insert(player, own_player(Point::new(0, 0)))
+        .unwrap();
+    let reaper = scene.character("reaper", "walk_left", Point::new(60, 24));
+    scene.add_effect(player, "shield", 0, 1);
+    scene.add_effect(player, "poison", 7400, 3);
+    scene.add_effect(reaper, "frostbite", 5000, 1);
+    unit_frames::UnitFrameSystem.run_now(&scene.world);
+
+    assert_golden("effects", scene.render());
+}
+
+#[test]
+fn projectiles_particles_and_impacts_in_flight() {
+    let mut scene = Scene::new();
+    *scene.world.write_resource::<map::CurrentMap>() = map::CurrentMap(Some("start".into()));
+    let bardo = scene.character("bardo", "idle", Point::new(0, 0));
+    let reaper = scene.character("reaper", "walk_left", Point::new(16, 24));
+    scene
+        .world
+        .create_entity()
+        .with(Projectile {
+            cast: CastEvent {
+                caster: bardo,
+                ability: "fireball".to_string(),
+                target: Some(reaper),
+            },
+            position: Point::new(-90, -60),
+            elapsed: 90,
+        })
+        .build();
+    let trail = (0..8)
+        .map(|i| Particle {
+            position: (-100.0 - i as f32 * 4.0, -64.0 + (i % 3) as f32 * 3.0),
+            velocity: (0.0, 0.0),
+            age: i * 40,
+            lifetime: 400,
+        })
+        .collect();
+    scene
+        .world
+        .create_entity()
+        .with(ParticleEmitter {
+            particles: trail,
+            ..ParticleEmitter::new("fire_trail", Point::new(-90, -60))
+        })
+        .build();
+    let sparks = (0..6)
+        .map(|i| Particle {
+            position: (4.0 + i as f32 * 5.0, 10.0 - (i % 2) as f32 * 6.0),
+            velocity: (0.0, 0.0),
+            age: i * 30,
+            lifetime: 300,
+        })
+        .collect();
+    scene
+        .world
+        .create_entity()
+        .with(ParticleEmitter {
+            particles: sparks,
+            ..ParticleEmitter::attached_to("hit", reaper)
+        })
+        .build();
+    scene
+        .world
+        .create_entity()
+        .with(SpellEffect {
+            spritesheet: "spells".to_string(),
+            clip: "heal".to_string(),
+            target: bardo,
+            elapsed: 100,
+        })
+        .build();
+
+    assert_golden("spells", scene.render());
+}
+
+#[test]
+fn bags_and_character_windows_with_a_tooltip() {
+    let mut scene = Scene::new();
+    ui::create_bottom_panel(&mut scene.world);
+    let windows = inventory::create_inventory_windows(&mut scene.world);
+    {
+        let mut nodes = scene.world.write_storage::<UiComponent>();
+        inventory::toggle(&mut nodes, windows.bags);
+        inventory::toggle(&mut nodes, windows.character);
+    }
+    scene.world.insert(windows);
+
+    let mut bags = Inventory::default();
+    let stacks = [
+        (0, "healing_potion", 7),
+        (1, "wolf_pelt", 3),
+        (2, "copper_ring", 1),
+        (6, "rusty_sword", 1),
+    ];
+    for (slot, item, count) in stacks {
+        bags.set(
+            slot,
+            Some(ItemStack {
+                item: item.to_string(),
+                count,
+            }),
+        );
+    }
+    let mut worn = Equipment::default();
+    worn.0.insert(EquipSlot::Head, "leather_cap".to_string());
+    worn.0.insert(EquipSlot::Chest, "padded_tunic".to_string());
+    worn.0
+        .insert(EquipSlot::OffHand, "wooden_shield".to_string());
+    scene
+        .world
+        .create_entity()
+        .with(Player::new(
+            PLAYER_ID.to_string(),
+            PLAYER_ID.to_string(),
+            0,
+            Point::new(0, 0),
+            Point::new(0, 0),
+            Direction::Stationary,
+            1,
+        ))
+        .with(bags)
+        .with(worn)
+        .build();
+
+    ui::UiLayout.run_now(&scene.world);
+    let ring = {
+        let entities = scene.world.entities();
+        let nodes = scene.world.read_storage::<UiComponent>();
+        (&entities, &nodes)
+            .join()
+            .find(|(_, node)| {
+                matches!(&node.widget, Widget::ItemSlot { slot: 2, action }
+                    if action == inventory::BAG_ACTION)
+            })
+            .map(|(entity, node)| (entity, node.bounds.center()))
+    };
+    if let Some((slot, center)) = ring {
+        let mut state = scene.world.write_resource::<ui::UiState>();
+        state.hovered = Some(slot);
+        state.pointer = (center.x(), center.y());
+    }
+
+    assert_golden("inventory", scene.render());
+}
+
+#[test]
+fn experience_bar_level_up_banner_and_character_stats() {
+    let mut scene = Scene::new();
+    ui::create_bottom_panel(&mut scene.world);
+    let experience_bar = progression::create_experience_bar(&mut scene.world);
+    scene.world.insert(experience_bar);
+    scene.world.insert(Vec::<ServerUpdate>::new());
+    let windows = inventory::create_inventory_windows(&mut scene.world);
+    inventory::toggle(&mut scene.world.write_storage(), windows.character);
+    scene.world.insert(windows);
+
+    scene
+        .world
+        .create_entity()
+        .with(own_player(Point::new(0, 0)))
+        .with(Equipment::default())
+        .with(CharacterStats {
+            level: 12,
+            experience: 4200,
+            next_level: 9000,
+            stats: Stats {
+                strength: 14,
+                agility: 9,
+                intellect: 11,
+                stamina: 16,
+                armor: 5,
+            },
+        })
+        .build();
+    *scene.world.write_resource::<progression::LevelUpBanner>() = progression::LevelUpBanner {
+        level: Some(12),
+        age: 500,
+    };
+    progression::ProgressionSystem.run_now(&scene.world);
+
+    assert_golden("progression", scene.render());
+}
+
+#[test]
+fn loot_in_the_world_with_its_window_open() {
+    let mut scene = Scene::new();
+    *scene.world.write_resource::<map::CurrentMap>() = map::CurrentMap(Some("start".into()));
+    let window = loot::create_loot_window(&mut scene.world);
+    scene.world.insert(window);
+    scene.world.insert(Vec::<ServerUpdate>::new());
+    let player = scene.character("bardo", "idle", Point::new(0, 0));
+    scene
+        .world
+        .write_storage()
+        .insert(
+            player,
+            Player::new(
+                PLAYER_ID.to_string(),
+                PLAYER_ID.to_string(),
+                0,
+                Point::new(0, 0),
+                Point::new(0, 0),
+                Direction::Stationary,
+                1,
+            ),
+        )
+        .unwrap();
+
+    let stack = |item: &str, count| ItemStack {
+        item: item.to_string(),
+        count,
+    };
+    scene
+        .world
+        .create_entity()
+        .with(Loot {
+            id: "bag".to_string(),
+            clip: "bag".to_string(),
+            name: String::new(),
+            items: vec![stack("healing_potion", 2)],
+        })
+        .with(Position(Point::new(140, -30)))
+        .build();
+    let corpse = scene
+        .world
+        .create_entity()
+        .with(Loot {
+            id: "reaper".to_string(),
+            clip: "corpse".to_string(),
+            name: "Reaper".to_string(),
+            items: vec![
+                stack("wolf_pelt", 3),
+                stack("rusty_sword", 1),
+                stack("copper_ring", 1),
+            ],
+        })
+        .with(Position(Point::new(50, 20)))
+        .build();
+    scene.world.write_resource::<OpenLoot>().0 = Some(corpse);
+    loot::LootSystem.run_now(&scene.world);
+
+    assert_golden("loot", scene.render());
+}
+
+#[test]
+fn quest_log_tracker_and_quest_giver_markers() {
+    let mut scene = Scene::new();
+    *scene.world.write_resource::<map::CurrentMap>() = map::CurrentMap(Some("start".into()));
+    let window = quests::create_quest_window(&mut scene.world);
+    inventory::toggle(&mut scene.world.write_storage(), window.panel);
+    scene.world.insert(window);
+    let tracker = quests::create_quest_tracker(&mut scene.world);
+    scene.world.insert(tracker);
+    scene.world.insert(Vec::<ServerUpdate>::new());
+
+    let givers = [
+        ("merchant", "reaper", "idle_down", Point::new(130, 160)),
+        ("bardo", "bardo", "idle", Point::new(260, 150)),
+    ];
+    for (id, spritesheet, animation, position) in givers {
+        let npc = scene.character(spritesheet, animation, position);
+        let player = Player {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        scene.world.write_storage().insert(npc, player).unwrap();
+    }
+
+    {
+        let mut log = scene.world.write_resource::<QuestLog>();
+        let quests = [
+            ("wolf_pelts", QuestState::Active, vec![3]),
+            ("reaper_sightings", QuestState::Complete, vec![4, 3]),
+            ("lost_ring", QuestState::Available, vec![]),
+        ];
+        for (id, state, progress) in quests {
+            log.quests.push(Quest {
+                id: id.to_string(),
+                state,
+                progress,
+            });
+        }
+    }
+    quests::QuestSystem.run_now(&scene.world);
+
+    assert_golden("quests", scene.render());
+}
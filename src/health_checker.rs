@@ -1,29 +1,76 @@
 use specs::prelude::*;
 
-use crate::{components::*, AttackCommand};
+use crate::{components::*, ServerUpdate};
 
+/// Rage gained per point of damage taken
+const RAGE_PER_DAMAGE: f32 = 0.5;
+
+/// Applies hits, heals and status updates from the server to the status of characters
 pub struct HealthChecker;
 
 impl<'a> System<'a> for HealthChecker {
     type SystemData = (
         WriteStorage<'a, Status>,
-        ReadStorage<'a, KeyboardControlled>,
-        ReadExpect<'a, Option<AttackCommand>>,
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        Write<'a, CombatEvents>,
     );
 
     fn run(&mut self, mut data: Self::SystemData) {
-        let shoot_command = match &*data.2 {
-            Some(shoot_command) => shoot_command,
-            None => return, // no change
-        };
+        let events = &mut data.4 .0;
+        events.clear();
 
-        for status in (&mut data.0).join() {
-            match shoot_command {
-                AttackCommand::Cast() => {
-                    status.health -= 10;
+        for server_update in data.3.iter() {
+            match server_update {
+                ServerUpdate::Combat(update) => {
+                    for (entity, player, status) in (&data.1, &data.2, &mut data.0).join() {
+                        if player.id == update.target {
+                            apply(entity, status, update.kind, update.amount, events);
+                        }
+                    }
+                }
+                ServerUpdate::Status(update) => {
+                    for (player, status) in (&data.2, &mut data.0).join() {
+                        if player.id == update.id {
+                            status.health = update.health.min(update.max_health);
+                            status.max_health = update.max_health;
+                            status.alive = status.health > 0;
+                            status.power = update.power.clone();
+                        }
+                    }
                 }
-                AttackCommand::Stop => {}
+                _ => {}
             }
         }
     }
 }
+
+fn apply(
+    entity: Entity,
+    status: &mut Status,
+    kind: CombatKind,
+    amount: u32,
+    events: &mut Vec<CombatEvent>,
+) {
+    match kind {
+        CombatKind::Damage | CombatKind::Crit => {
+            status.health = status.health.saturating_sub(amount);
+            if let Some(power) = &mut status.power {
+                if power.kind == PowerKind::Rage {
+                    power.add((amount as f32 * RAGE_PER_DAMAGE).ceil() as i32);
+                }
+            }
+        }
+        CombatKind::Heal => {
+            status.health = (status.health + amount).min(status.max_health);
+        }
+        CombatKind::Miss => {}
+    }
+    status.alive = status.health > 0;
+    events.push(CombatEvent {
+        entity,
+        kind,
+        amount,
+    });
+}
@@ -0,0 +1,592 @@
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use specs::prelude::*;
+
+use crate::{
+    assets::{AssetIndex, EquipSlot, Stats},
+    chat::ChatLog,
+    components::*,
+    loot::LOOT_ACTION,
+    render::{Layer, RenderQueue, TextMetrics},
+    ui::{self, UiState},
+    ServerUpdate,
+};
+
+/// Actions of the bag and equipment slots
+pub const BAG_ACTION: &str = "inventory";
+pub const EQUIPMENT_ACTION: &str = "equipment";
+
+/// Texture the item icons are cut from, in cells of `ui::ICON_CELL`
+const ICON_TEXTURE: &str = "item_icons";
+const ICON_SIZE: u32 = 32;
+const SLOT_SIZE: u32 = 36;
+const SLOT_SPACING: i32 = 4;
+const BAG_COLUMNS: usize = 5;
+/// Equipment slots in the left column of the character window, the rest go on the right
+const LEFT_COLUMN: usize = 5;
+const WINDOW_PADDING: i32 = 16;
+const TITLE_HEIGHT: u32 = 20;
+/// Width of the stat list between the two columns of the character window
+const STATS_WIDTH: u32 = 140;
+const COLUMN_SPACING: i32 = 10;
+const TOOLTIP_WIDTH: u32 = 200;
+const TOOLTIP_PADDING: i32 = 6;
+
+const WINDOW_BACKGROUND: Color = Color::RGB(30, 30, 40);
+const TOOLTIP_BACKGROUND: Color = Color::RGBA(15, 15, 25, 235);
+const SLOT_NAME_COLOR: Color = Color::RGB(170, 170, 170);
+const BONUS_COLOR: Color = Color::RGB(100, 220, 100);
+const DESCRIPTION_COLOR: Color = Color::RGB(230, 210, 120);
+
+/// The bag window toggled with B and the character window toggled with C
+pub struct InventoryWindows {
+    pub bags: Entity,
+    pub character: Entity,
+    /// Where the level, experience and stats of the character are listed
+    pub stats: Entity,
+}
+
+fn create_window(
+    world: &mut World,
+    title: &str,
+    size: (u32, u32),
+    anchor: Anchor,
+    offset: Point,
+) -> Entity {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(WINDOW_BACKGROUND),
+                },
+                size,
+            )
+            .anchored(anchor, offset)
+            .with_layout(Layout::Vertical, WINDOW_PADDING, SLOT_SPACING)
+            .hidden(),
+        )
+        .build();
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: title.to_string(),
+                    color: Color::WHITE,
+                },
+                (0, TITLE_HEIGHT),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    panel
+}
+
+fn create_slot(world: &mut World, parent: Entity, order: i32, action: &str, slot: usize) {
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::ItemSlot {
+                    slot,
+                    action: action.to_string(),
+                },
+                (SLOT_SIZE, SLOT_SIZE),
+            )
+            .with_parent(parent, order),
+        )
+        .build();
+}
+
+/// Length of `count` slots in a row or column
+fn span(count: usize) -> u32 {
+    count as u32 * SLOT_SIZE + count.saturating_sub(1) as u32 * SLOT_SPACING as u32
+}
+
+/// Creates the hidden bag and character windows
+pub fn create_inventory_windows(world: &mut World) -> InventoryWindows {
+    let rows = BAG_SLOTS.div_ceil(BAG_COLUMNS);
+    let bags = create_window(
+        world,
+        "Bags",
+        (
+            span(BAG_COLUMNS) + 2 * WINDOW_PADDING as u32,
+            TITLE_HEIGHT + span(rows) + SLOT_SPACING as u32 + 2 * WINDOW_PADDING as u32,
+        ),
+        Anchor::BottomRight,
+        Point::new(-10, -(ui::BOTTOM_PANEL_HEIGHT as i32) - 10),
+    );
+    for row in 0..rows {
+        let container = world
+            .create_entity()
+            .with(
+                UiComponent::new(Widget::Container, (0, SLOT_SIZE))
+                    .with_parent(bags, row as i32 + 1)
+                    .with_layout(Layout::Horizontal, 0, SLOT_SPACING),
+            )
+            .build();
+        let slots = row * BAG_COLUMNS..((row + 1) * BAG_COLUMNS).min(BAG_SLOTS);
+        for (order, slot) in slots.enumerate() {
+            create_slot(world, container, order as i32, BAG_ACTION, slot);
+        }
+    }
+
+    let height = span(LEFT_COLUMN);
+    let character = create_window(
+        world,
+        "Character",
+        (
+            2 * SLOT_SIZE + STATS_WIDTH + 2 * (COLUMN_SPACING + WINDOW_PADDING) as u32,
+            TITLE_HEIGHT + height + SLOT_SPACING as u32 + 2 * WINDOW_PADDING as u32,
+        ),
+        Anchor::Left,
+        Point::new(10, -40),
+    );
+    let row = world
+        .create_entity()
+        .with(
+            UiComponent::new(Widget::Container, (0, height))
+                .with_parent(character, 1)
+                .with_layout(Layout::Horizontal, 0, COLUMN_SPACING),
+        )
+        .build();
+    let column = |world: &mut World, order: i32| {
+        world
+            .create_entity()
+            .with(
+                UiComponent::new(Widget::Container, (SLOT_SIZE, 0))
+                    .with_parent(row, order)
+                    .with_layout(Layout::Vertical, 0, SLOT_SPACING),
+            )
+            .build()
+    };
+    let left = column(world, 0);
+    let stats = world
+        .create_entity()
+        .with(UiComponent::new(Widget::Container, (STATS_WIDTH, 0)).with_parent(row, 1))
+        .build();
+    let right = column(world, 2);
+    for (slot, _) in EquipSlot::ALL.iter().enumerate() {
+        let (parent, order) = if slot < LEFT_COLUMN {
+            (left, slot)
+        } else {
+            (right, slot - LEFT_COLUMN)
+        };
+        create_slot(world, parent, order as i32, EQUIPMENT_ACTION, slot);
+    }
+
+    InventoryWindows {
+        bags,
+        character,
+        stats,
+    }
+}
+
+/// Shows a hidden window or hides a shown one
+pub fn toggle(nodes: &mut WriteStorage<UiComponent>, window: Entity) {
+    if let Some(node) = nodes.get_mut(window) {
+        node.visible = !node.visible;
+    }
+}
+
+/// Fills the bags and equipment of characters from the updates of the server
+pub struct InventorySystem;
+
+impl<'a> System<'a> for InventorySystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, Inventory>,
+        WriteStorage<'a, Equipment>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, players, mut inventories, mut equipment, server_updates) = data;
+        for server_update in server_updates.iter() {
+            let update = match server_update {
+                ServerUpdate::Inventory(update) => update,
+                _ => continue,
+            };
+            let character = match (&entities, &players)
+                .join()
+                .find(|(_, player)| player.id == update.id())
+            {
+                Some((entity, _)) => entity,
+                None => continue,
+            };
+
+            match update {
+                InventoryUpdate::Bag { slot, stack, .. } => {
+                    if let Ok(entry) = inventories.entry(character) {
+                        entry
+                            .or_insert_with(Inventory::default)
+                            .set(*slot, stack.clone());
+                    }
+                }
+                InventoryUpdate::Equipment { slot, item, .. } => {
+                    if let Ok(entry) = equipment.entry(character) {
+                        let worn = &mut entry.or_insert_with(Equipment::default).0;
+                        match item {
+                            Some(item) => worn.insert(*slot, item.clone()),
+                            None => worn.remove(slot),
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub type ActionData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, Player>,
+    WriteStorage<'a, Inventory>,
+    ReadStorage<'a, Equipment>,
+    Read<'a, AssetIndex>,
+    Write<'a, ChatLog>,
+);
+
+/// The request for clicking a slot: equipping or using what is in a bag slot, or taking
+/// off what is in an equipment slot
+pub fn click(action: &str, slot: usize, data: ActionData) -> Option<ItemRequest> {
+    let (entities, players, inventories, equipment, index, _) = data;
+    let player = local_player(&entities, &players)?;
+    match action {
+        BAG_ACTION => {
+            let stack = inventories.get(player)?.get(slot)?;
+            match index.items.get(&stack.item)?.slot {
+                Some(_) => Some(ItemRequest::Equip(slot)),
+                None => Some(ItemRequest::Use(slot)),
+            }
+        }
+        EQUIPMENT_ACTION => {
+            let slot = *EquipSlot::ALL.get(slot)?;
+            equipment
+                .get(player)?
+                .0
+                .contains_key(&slot)
+                .then_some(ItemRequest::Unequip(slot))
+        }
+        _ => None,
+    }
+}
+
+/// The request for dragging an item from one slot onto another. Moves within the bags
+/// are shown right away, the server sends everything else back once it happened.
+pub fn drag(
+    (from_action, from): (&str, usize),
+    (to_action, to): (&str, usize),
+    data: ActionData,
+) -> Option<ItemRequest> {
+    let (entities, players, mut inventories, equipment, index, mut log) = data;
+    let player = local_player(&entities, &players)?;
+    match (from_action, to_action) {
+        (BAG_ACTION, BAG_ACTION) => {
+            let inventory = inventories.get_mut(player)?;
+            let stack_size = index.items.get(&inventory.get(from)?.item)?.stack;
+            inventory.move_stack(from, to, stack_size);
+            Some(ItemRequest::Move { from, to })
+        }
+        (BAG_ACTION, EQUIPMENT_ACTION) => {
+            let stack = inventories.get(player)?.get(from)?;
+            let fits = index
+                .items
+                .get(&stack.item)
+                .is_some_and(|item| item.slot == EquipSlot::ALL.get(to).copied());
+            if !fits {
+                log.system("That item doesn't go there");
+                return None;
+            }
+            Some(ItemRequest::Equip(from))
+        }
+        (EQUIPMENT_ACTION, BAG_ACTION) => {
+            let slot = *EquipSlot::ALL.get(from)?;
+            equipment
+                .get(player)?
+                .0
+                .contains_key(&slot)
+                .then_some(ItemRequest::Unequip(slot))
+        }
+        _ => None,
+    }
+}
+
+/// The request for dragging an item out of the bags and letting go outside of the UI.
+/// Without `confirmed` the item stays and the player is told how to drop it.
+pub fn discard(
+    action: &str,
+    slot: usize,
+    confirmed: bool,
+    data: ActionData,
+) -> Option<ItemRequest> {
+    let (entities, players, inventories, _, _, mut log) = data;
+    let player = local_player(&entities, &players)?;
+    if action != BAG_ACTION {
+        return None;
+    }
+    inventories.get(player)?.get(slot)?;
+    if !confirmed {
+        log.system("Hold Shift while letting go to drop the item");
+        return None;
+    }
+    Some(ItemRequest::Drop(slot))
+}
+
+/// Sum of the stats of everything the character wears
+pub fn equipment_stats(equipment: &Equipment, index: &AssetIndex) -> Stats {
+    let mut stats = Stats::default();
+    for item in equipment.0.values().filter_map(|id| index.items.get(id)) {
+        stats.add(&item.stats);
+    }
+    stats
+}
+
+/// Everything item slots can show
+struct Holdings<'a> {
+    inventory: Option<&'a Inventory>,
+    equipment: Option<&'a Equipment>,
+    loot: Option<&'a Loot>,
+}
+
+impl<'a> Holdings<'a> {
+    /// The item and how many of it are in the slot a widget shows
+    fn slot_contents(&self, widget: &Widget) -> Option<(&'a str, u32)> {
+        let stack = match widget {
+            Widget::ItemSlot { slot, action } if action == BAG_ACTION => {
+                self.inventory?.get(*slot)?
+            }
+            Widget::ItemSlot { slot, action } if action == LOOT_ACTION => {
+                self.loot?.items.get(*slot)?
+            }
+            Widget::ItemSlot { slot, action } if action == EQUIPMENT_ACTION => {
+                let slot = EquipSlot::ALL.get(*slot)?;
+                return self.equipment?.0.get(slot).map(|item| (item.as_str(), 1));
+            }
+            _ => return None,
+        };
+        Some((stack.item.as_str(), stack.count))
+    }
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, UiComponent>,
+    Read<'a, UiState>,
+    ReadStorage<'a, Player>,
+    ReadStorage<'a, Inventory>,
+    ReadStorage<'a, Equipment>,
+    ReadStorage<'a, CharacterStats>,
+    ReadStorage<'a, Loot>,
+    Read<'a, OpenLoot>,
+    Option<ReadExpect<'a, InventoryWindows>>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the items in the bag, equipment and loot slots, the character sheet, the item
+/// being dragged under the mouse and the tooltip of the hovered slot
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (
+        entities,
+        nodes,
+        state,
+        players,
+        inventories,
+        equipment,
+        progress,
+        loots,
+        open_loot,
+        windows,
+        index,
+    ) = data;
+    let player = local_player(&entities, &players);
+    let holdings = Holdings {
+        inventory: player.and_then(|player| inventories.get(player)),
+        equipment: player.and_then(|player| equipment.get(player)),
+        loot: open_loot.0.and_then(|loot| loots.get(loot)),
+    };
+
+    for (entity, node) in (&entities, &nodes).join() {
+        if !node.shown || !matches!(node.widget, Widget::ItemSlot { .. }) {
+            continue;
+        }
+        let bounds = node.bounds;
+        let z = node.depth as i32;
+        queue.fill_rect(Layer::Ui, z, ui::SLOT_BACKGROUND, bounds);
+        let item = holdings
+            .slot_contents(&node.widget)
+            .and_then(|(id, count)| Some((index.items.get(id)?, count)));
+        if let Some((item, count)) = item {
+            let (texture, region) = ui::icon_region(&index, ICON_TEXTURE, item.icon);
+            let icon = Rect::from_center(bounds.center(), ICON_SIZE, ICON_SIZE);
+            queue.copy(Layer::Ui, z, texture, region, icon);
+            if count > 1 {
+                let count = count.to_string();
+                let (width, height) = font.text_size(&count)?;
+                let corner = bounds
+                    .bottom_right()
+                    .offset(-(width as i32) - 2, -(height as i32));
+                ui::draw_text(queue, z, font, &count, Color::WHITE, corner)?;
+            }
+        }
+        let border = if state.hovered == Some(entity) {
+            ui::HOVERED_BORDER
+        } else {
+            ui::SLOT_BORDER
+        };
+        queue.draw_rect(Layer::Ui, z, border, bounds);
+    }
+
+    if let Some(stats) = windows.and_then(|windows| nodes.get(windows.stats)) {
+        if stats.shown {
+            let progress = player.and_then(|player| progress.get(player));
+            let lines = character_sheet(progress, holdings.equipment, &index);
+            for (line, (text, color)) in lines.iter().enumerate() {
+                let position = stats
+                    .bounds
+                    .top_left()
+                    .offset(0, line as i32 * font.line_height());
+                ui::draw_text(queue, stats.depth as i32, font, text, *color, position)?;
+            }
+        }
+    }
+
+    let pointer = Point::new(state.pointer.0, state.pointer.1);
+    // The icon follows the mouse while a slot is dragged off itself
+    let dragged = state
+        .pressed
+        .and_then(|pressed| nodes.get(pressed))
+        .filter(|node| !node.bounds.contains_point(pointer))
+        .and_then(|node| holdings.slot_contents(&node.widget))
+        .and_then(|(id, _)| index.items.get(id));
+    if let Some(item) = dragged {
+        let (texture, region) = ui::icon_region(&index, ICON_TEXTURE, item.icon);
+        queue.copy(
+            Layer::Ui,
+            i32::MAX,
+            texture,
+            region,
+            Rect::from_center(pointer, ICON_SIZE, ICON_SIZE),
+        );
+        return Ok(());
+    }
+
+    let hovered = state.hovered.and_then(|hovered| nodes.get(hovered));
+    if let Some(node) = hovered {
+        let lines = tooltip(&node.widget, &holdings, &index, font)?;
+        draw_tooltip(queue, font, &lines, pointer)?;
+    }
+
+    Ok(())
+}
+
+/// The lines of the character window: level, experience and the stats with the equipment
+/// added, in the bonus color where the equipment raises them
+fn character_sheet(
+    progress: Option<&CharacterStats>,
+    equipment: Option<&Equipment>,
+    index: &AssetIndex,
+) -> Vec<(String, Color)> {
+    let mut lines = Vec::new();
+    let mut total = Stats::default();
+    if let Some(progress) = progress {
+        lines.push((format!("Level {}", progress.level), Color::WHITE));
+        let experience = if progress.next_level > 0 {
+            format!("XP {}/{}", progress.experience, progress.next_level)
+        } else {
+            "Highest level".to_string()
+        };
+        lines.push((experience, SLOT_NAME_COLOR));
+        total = progress.stats;
+    }
+    let bonus = equipment.map_or_else(Stats::default, |equipment| {
+        equipment_stats(equipment, index)
+    });
+    total.add(&bonus);
+    for ((name, value), (_, added)) in total.named().iter().zip(bonus.named()) {
+        let color = if added > 0 { BONUS_COLOR } else { Color::WHITE };
+        lines.push((format!("{:<10}{:>4}", name, value), color));
+    }
+    lines
+}
+
+/// The lines describing what is in a slot, or which slot it is for empty equipment slots
+fn tooltip(
+    widget: &Widget,
+    holdings: &Holdings,
+    index: &AssetIndex,
+    font: &dyn TextMetrics,
+) -> Result<Vec<(String, Color)>, String> {
+    let mut lines = Vec::new();
+    let item = holdings
+        .slot_contents(widget)
+        .and_then(|(id, _)| index.items.get(id));
+    match (item, widget) {
+        (Some(item), _) => {
+            lines.push((item.name.clone(), Color::WHITE));
+            if let Some(slot) = item.slot {
+                lines.push((slot.name().to_string(), SLOT_NAME_COLOR));
+            }
+            for (name, value) in item.stats.named() {
+                if value != 0 {
+                    lines.push((format!("{:+} {}", value, name), BONUS_COLOR));
+                }
+            }
+            if let Some(description) = &item.description {
+                for line in ui::wrap_text(font, description, TOOLTIP_WIDTH)? {
+                    lines.push((line, DESCRIPTION_COLOR));
+                }
+            }
+        }
+        (None, Widget::ItemSlot { slot, action }) if action == EQUIPMENT_ACTION => {
+            if let Some(slot) = EquipSlot::ALL.get(*slot) {
+                lines.push((slot.name().to_string(), SLOT_NAME_COLOR));
+            }
+        }
+        _ => {}
+    }
+    Ok(lines)
+}
+
+/// Queues a box with the lines next to the mouse, kept inside the window
+fn draw_tooltip(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    lines: &[(String, Color)],
+    pointer: Point,
+) -> Result<(), String> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let mut width = 0;
+    for (line, _) in lines {
+        width = width.max(font.text_size(line)?.0);
+    }
+    let width = width + 2 * TOOLTIP_PADDING as u32;
+    let height = lines.len() as u32 * font.line_height() as u32 + 2 * TOOLTIP_PADDING as u32;
+    let (screen_width, _) = queue.size();
+    let x = if pointer.x() + 16 + width as i32 > screen_width as i32 {
+        pointer.x() - width as i32 - 4
+    } else {
+        pointer.x() + 16
+    };
+    let bounds = Rect::new(x, (pointer.y() - height as i32).max(0), width, height);
+    queue.fill_rect(Layer::Ui, i32::MAX, TOOLTIP_BACKGROUND, bounds);
+    queue.draw_rect(Layer::Ui, i32::MAX, ui::SLOT_BORDER, bounds);
+    for (row, (line, color)) in lines.iter().enumerate() {
+        let position = bounds.top_left().offset(
+            TOOLTIP_PADDING,
+            TOOLTIP_PADDING + row as i32 * font.line_height(),
+        );
+        ui::draw_text(queue, i32::MAX, font, line, *color, position)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use std::ops::Deref;
+
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use specs::prelude::*;
+use specs::storage::MaskedStorage;
+
+use crate::{
+    abilities,
+    assets::AssetIndex,
+    chat::ChatLog,
+    components::*,
+    render::{Layer, RenderQueue},
+    ui,
+    viewport::Viewport,
+    ServerUpdate,
+};
+
+/// Action of the slots in the loot window
+pub const LOOT_ACTION: &str = "loot";
+/// Action of the button that takes everything
+pub const LOOT_ALL_ACTION: &str = "loot_all";
+/// Spritesheet the clips of loot are taken from
+const LOOT_SPRITESHEET: &str = "loot";
+/// Particle emitter that marks loot as lootable
+const SPARKLE_EMITTER: &str = "loot_sparkle";
+/// Farthest the player may stand from loot to open it, in world pixels
+const LOOT_RANGE: f32 = 80.0;
+/// Most stacks a loot window shows
+const MAX_ROWS: usize = 6;
+
+const WINDOW_WIDTH: u32 = 220;
+const WINDOW_PADDING: i32 = 12;
+const ROW_SPACING: i32 = 4;
+const SLOT_SIZE: u32 = 36;
+const TITLE_HEIGHT: u32 = 20;
+const BUTTON_HEIGHT: u32 = 26;
+
+/// The UI elements of a row of the loot window
+pub struct LootRow {
+    pub row: Entity,
+    pub name: Entity,
+}
+
+/// The window listing what is in the open loot
+pub struct LootWindow {
+    pub panel: Entity,
+    pub title: Entity,
+    pub rows: Vec<LootRow>,
+}
+
+pub fn create_loot_window(world: &mut World) -> LootWindow {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (WINDOW_WIDTH, window_height(0)),
+            )
+            .anchored(Anchor::Center, Point::new(-220, -40))
+            .with_layout(Layout::Vertical, WINDOW_PADDING, ROW_SPACING)
+            .hidden(),
+        )
+        .build();
+    let title = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: String::new(),
+                    color: Color::WHITE,
+                },
+                (0, TITLE_HEIGHT),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    let rows = (0..MAX_ROWS)
+        .map(|index| {
+            let row = world
+                .create_entity()
+                .with(
+                    UiComponent::new(Widget::Container, (0, SLOT_SIZE))
+                        .with_parent(panel, index as i32 + 1)
+                        .with_layout(Layout::Horizontal, 0, 8)
+                        .hidden(),
+                )
+                .build();
+            world
+                .create_entity()
+                .with(
+                    UiComponent::new(
+                        Widget::ItemSlot {
+                            slot: index,
+                            action: LOOT_ACTION.to_string(),
+                        },
+                        (SLOT_SIZE, SLOT_SIZE),
+                    )
+                    .with_parent(row, 0),
+                )
+                .build();
+            let name = world
+                .create_entity()
+                .with(
+                    UiComponent::new(
+                        Widget::Label {
+                            text: String::new(),
+                            color: Color::WHITE,
+                        },
+                        (0, 16),
+                    )
+                    .with_parent(row, 1)
+                    .anchored(Anchor::Left, Point::new(0, 0)),
+                )
+                .build();
+            LootRow { row, name }
+        })
+        .collect();
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Button {
+                    label: "Take All".to_string(),
+                    action: LOOT_ALL_ACTION.to_string(),
+                },
+                (0, BUTTON_HEIGHT),
+            )
+            .with_parent(panel, MAX_ROWS as i32 + 1),
+        )
+        .build();
+
+    LootWindow { panel, title, rows }
+}
+
+/// Height of the loot window with `rows` rows shown
+fn window_height(rows: usize) -> u32 {
+    let spacing = ROW_SPACING as u32;
+    2 * WINDOW_PADDING as u32
+        + TITLE_HEIGHT
+        + rows as u32 * (SLOT_SIZE + spacing)
+        + spacing
+        + BUTTON_HEIGHT
+}
+
+/// Puts the loot the server announces into the world and keeps the loot window in sync
+/// with the open loot. The window closes when the loot is gone or the player walks away.
+pub struct LootSystem;
+
+impl<'a> System<'a> for LootSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Loot>,
+        WriteStorage<'a, ParticleEmitter>,
+        Write<'a, OpenLoot>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        ReadExpect<'a, LootWindow>,
+        WriteStorage<'a, UiComponent>,
+        Read<'a, AssetIndex>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            players,
+            mut positions,
+            mut loots,
+            mut emitters,
+            mut open,
+            server_updates,
+            window,
+            mut nodes,
+            index,
+        ) = data;
+
+        for server_update in server_updates.iter() {
+            match server_update {
+                ServerUpdate::Loot(LootUpdate::Drop { loot, position }) => {
+                    let existing = (&entities, &loots)
+                        .join()
+                        .find(|(_, existing)| existing.id == loot.id)
+                        .map(|(entity, _)| entity);
+                    match existing {
+                        Some(entity) => {
+                            let _ = loots.insert(entity, loot.clone());
+                            let _ = positions.insert(entity, Position(*position));
+                        }
+                        None => {
+                            let entity = entities
+                                .build_entity()
+                                .with(loot.clone(), &mut loots)
+                                .with(Position(*position), &mut positions)
+                                .build();
+                            entities
+                                .build_entity()
+                                .with(
+                                    ParticleEmitter::attached_to(SPARKLE_EMITTER, entity),
+                                    &mut emitters,
+                                )
+                                .build();
+                        }
+                    }
+                }
+                ServerUpdate::Loot(LootUpdate::Remove { id }) => {
+                    for (entity, loot) in (&entities, &loots).join() {
+                        if &loot.id == id {
+                            let _ = entities.delete(entity);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let player = local_position(&entities, &players, &positions);
+        let still_open = open.0.is_some_and(|entity| {
+            let in_range = match (player, positions.get(entity)) {
+                (Some(player), Some(loot)) => abilities::distance(player, loot.0) <= LOOT_RANGE,
+                _ => false,
+            };
+            let filled = loots.get(entity).is_some_and(|loot| !loot.items.is_empty());
+            entities.is_alive(entity) && in_range && filled
+        });
+        if !still_open {
+            open.0 = None;
+        }
+
+        let loot = open.0.and_then(|entity| loots.get(entity));
+        let items = loot.map_or(&[][..], |loot| &loot.items[..]);
+        if let Some(panel) = nodes.get_mut(window.panel) {
+            panel.visible = loot.is_some();
+            panel.size.1 = window_height(items.len().min(MAX_ROWS));
+        }
+        if let Some(Widget::Label { text, .. }) =
+            nodes.get_mut(window.title).map(|node| &mut node.widget)
+        {
+            *text = match loot {
+                Some(loot) if !loot.name.is_empty() => loot.name.clone(),
+                _ => "Loot".to_string(),
+            };
+        }
+        for (row, ui_row) in window.rows.iter().enumerate() {
+            let stack = items.get(row);
+            if let Some(node) = nodes.get_mut(ui_row.row) {
+                node.visible = stack.is_some();
+            }
+            if let Some(Widget::Label { text, .. }) =
+                nodes.get_mut(ui_row.name).map(|node| &mut node.widget)
+            {
+                *text = stack.map_or_else(String::new, |stack| {
+                    index
+                        .items
+                        .get(&stack.item)
+                        .map_or_else(|| stack.item.clone(), |item| item.name.clone())
+                });
+            }
+        }
+    }
+}
+
+fn local_position<D>(
+    entities: &Entities,
+    players: &ReadStorage<Player>,
+    positions: &Storage<Position, D>,
+) -> Option<Point>
+where
+    D: Deref<Target = MaskedStorage<Position>>,
+{
+    local_player(entities, players)
+        .and_then(|player| positions.get(player))
+        .map(|position| position.0)
+}
+
+/// Where loot is drawn on a screen of `size`
+fn screen_rect(index: &AssetIndex, loot: &Loot, position: Point, size: (u32, u32)) -> Option<Rect> {
+    let sprite = index.sprite(LOOT_SPRITESHEET, &loot.clip, 0)?;
+    let screen_position = ui::world_to_screen(position, size);
+    Some(ui::sprite_rect(&sprite, screen_position))
+}
+
+pub type OpenData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, Player>,
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Loot>,
+    Write<'a, OpenLoot>,
+    Read<'a, Viewport>,
+    Read<'a, AssetIndex>,
+    Write<'a, ChatLog>,
+);
+
+/// Opens the loot under a click at `point` in window coordinates. Returns whether there
+/// was loot, so the click isn't used for anything else.
+pub fn open_at(point: Point, data: OpenData) -> bool {
+    let (entities, players, positions, loots, mut open, viewport, index, mut log) = data;
+    let size = (viewport.width, viewport.height);
+    // The loot in front, further down the screen, gets the click
+    let clicked = (&entities, &loots, &positions)
+        .join()
+        .filter(|(_, loot, position)| {
+            screen_rect(&index, loot, position.0, size)
+                .is_some_and(|rect| rect.contains_point(point))
+        })
+        .max_by_key(|(_, _, position)| position.0.y());
+    let (entity, _, position) = match clicked {
+        Some(clicked) => clicked,
+        None => return false,
+    };
+    let player = local_position(&entities, &players, &positions);
+    if player.is_some_and(|player| abilities::distance(player, position.0) <= LOOT_RANGE) {
+        open.0 = Some(entity);
+    } else {
+        log.system("You are too far away");
+    }
+    true
+}
+
+/// Opens the closest loot within range of the player
+pub fn open_nearest(data: OpenData) {
+    let (entities, players, positions, loots, mut open, _, _, mut log) = data;
+    let player = match local_position(&entities, &players, &positions) {
+        Some(player) => player,
+        None => return,
+    };
+    let nearest = (&entities, &loots, &positions)
+        .join()
+        .map(|(entity, _, position)| (entity, abilities::distance(player, position.0)))
+        .filter(|(_, distance)| *distance <= LOOT_RANGE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    match nearest {
+        Some((entity, _)) => open.0 = Some(entity),
+        None => log.system("There is nothing to loot nearby"),
+    }
+}
+
+pub type RequestData<'a> = (ReadStorage<'a, Loot>, Read<'a, OpenLoot>);
+
+/// The request for taking the stack at `index` of the open loot
+pub fn take(index: usize, data: RequestData) -> Option<LootRequest> {
+    let (loots, open) = data;
+    let loot = loots.get(open.0?)?;
+    loot.items.get(index)?;
+    Some(LootRequest::Take {
+        loot: loot.id.clone(),
+        index,
+    })
+}
+
+/// The request for taking everything in the open loot
+pub fn take_all(data: RequestData) -> Option<LootRequest> {
+    let (loots, open) = data;
+    let loot = loots.get(open.0?)?;
+    Some(LootRequest::TakeAll {
+        loot: loot.id.clone(),
+    })
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    ReadStorage<'a, Loot>,
+    ReadStorage<'a, Position>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the loot lying in the world on the object layer, below the characters
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let (loots, positions, index) = data;
+    let size = queue.size();
+
+    for (loot, position) in (&loots, &positions).join() {
+        let sprite = match index.sprite(LOOT_SPRITESHEET, &loot.clip, 0) {
+            Some(sprite) => sprite,
+            None => continue,
+        };
+        if let Some(target) = screen_rect(&index, loot, position.0, size) {
+            queue.copy(
+                Layer::Objects,
+                target.bottom(),
+                sprite.spritesheet,
+                sprite.region,
+                target,
+            );
+        }
+    }
+}
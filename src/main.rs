@@ -1,99 +1,84 @@
+mod abilities;
+mod action_bar;
 mod animator;
+mod assets;
+mod chat;
 mod client_listener;
+mod combat_text;
 mod components;
+mod dialog;
+mod effects;
+#[cfg(test)]
+mod golden_tests;
 mod health_checker;
+mod inventory;
 mod keyboard;
-mod physics;
+mod loot;
+mod map;
+mod minimap;
+mod particles;
+mod progression;
+mod projectiles;
+mod quests;
+mod regen;
+mod render;
+mod sdl_renderer;
+#[cfg(test)]
+mod software_renderer;
 mod sprites;
 mod status;
+mod time;
 mod ui;
+mod unit_frames;
+mod viewport;
 
 use log::{debug, error, trace};
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::mouse::MouseButton;
 use sdl2::rect::{Point, Rect};
-use sdl2::render::{Texture, WindowCanvas};
+use sdl2::render::WindowCanvas;
 use sdl2::EventPump;
 use std::net::{SocketAddr, UdpSocket};
-use std::{env, str};
+use std::path::PathBuf;
+use std::{env, io, str};
 // "self" imports the "image" module itself as well as everything else we listed
-use sdl2::image::{self, InitFlag, LoadTexture};
+use sdl2::image::{self, InitFlag};
 use std::collections::{HashMap, VecDeque};
 
 use specs::prelude::*;
 
 use rand::Rng;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::assets::AssetManager;
 use crate::components::*;
+use crate::render::{RenderQueue, Renderer, TextMetrics, BACKGROUND};
+use crate::sdl_renderer::{SdlRenderer, TextCache};
+use crate::time::{FixedTimestep, FrameConfig, GameTime, TICK};
+use crate::viewport::{DisplayConfig, Viewport};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-/// Returns the row of the spritesheet corresponding to the given direction
-fn direction_spritesheet_row(direction: Direction) -> i32 {
-    match direction {
-        Direction::Up => 3,
-        Direction::Down => 0,
-        Direction::Left => 1,
-        Direction::Right => 2,
-        Direction::Stationary => 4,
-    }
-}
-
-/// Create animation frames for the standard character spritesheet
-fn character_animation_frames(
-    spritesheet: usize,
-    top_left_frame: Rect,
-    direction: Direction,
-) -> Vec<Sprite> {
-    let (frame_width, frame_height) = top_left_frame.size();
-    let y_offset = top_left_frame.y() + frame_height as i32 * direction_spritesheet_row(direction);
-
-    let mut frames = Vec::new();
-    for i in 0..3 {
-        frames.push(Sprite {
-            spritesheet,
-            region: Rect::new(
-                top_left_frame.x() + frame_width as i32 * i,
-                y_offset,
-                frame_width,
-                frame_height,
-            ),
-        })
-    }
-
-    frames
-}
-
-fn initialize_player(world: &mut World, player_id: String, player_spritesheet: usize) -> Entity {
-    let player_top_left_frame = Rect::new(0, 0, 26, 36);
-
-    let player_animation = MovementAnimation {
-        current_frame: 0,
-        up_frames: character_animation_frames(
-            player_spritesheet,
-            player_top_left_frame,
-            Direction::Up,
-        ),
-        down_frames: character_animation_frames(
-            player_spritesheet,
-            player_top_left_frame,
-            Direction::Down,
-        ),
-        left_frames: character_animation_frames(
-            player_spritesheet,
-            player_top_left_frame,
-            Direction::Left,
-        ),
-        right_frames: character_animation_frames(
-            player_spritesheet,
-            player_top_left_frame,
-            Direction::Right,
-        ),
+fn initialize_player(world: &mut World, player_id: String, spritesheet: &str) -> Entity {
+    let abilities = if player_id == PLAYER_ID {
+        let known = world
+            .read_resource::<assets::AssetIndex>()
+            .starting_abilities
+            .clone();
+        world.insert(action_bar::ActionBar::load_or_default(&player_id, &known));
+        Some(Abilities::new(known))
+    } else {
+        None
     };
+    let controller = AnimationController::new(spritesheet);
+    let sprite = animator::current_sprite(&world.read_resource(), &controller).unwrap_or(Sprite {
+        spritesheet: assets::MISSING_TEXTURE,
+        region: Rect::new(0, 0, 1, 1),
+        origin: Point::new(0, 0),
+    });
 
-    world
+    let mut player = world
         .create_entity()
         .with(KeyboardControlled)
         .with(ExternalControlled)
@@ -107,13 +92,13 @@ fn initialize_player(world: &mut World, player_id: String, player_spritesheet: u
             1,
         ))
         .with(Position(Point::new(0, 0)))
-        .with(Status {
-            alive: true,
-            health: 100,
-        })
-        .with(player_animation.right_frames[0].clone())
-        .with(player_animation)
-        .build()
+        .with(Status::new(100, Some(Power::new(PowerKind::Mana, 100))))
+        .with(sprite)
+        .with(controller);
+    if let Some(abilities) = abilities {
+        player = player.with(abilities);
+    }
+    player.build()
 }
 
 fn main() -> Result<()> {
@@ -121,8 +106,8 @@ fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     let server_addr_parts: &Vec<u8> = &args[1]
         .split(".")
-        .map(|part| u8::from_str_radix(part, 10).unwrap())
-        .collect::<Vec<u8>>();
+        .map(|part| part.parse::<u8>())
+        .collect::<std::result::Result<Vec<u8>, _>>()?;
     let server_addr = [
         server_addr_parts[0],
         server_addr_parts[1],
@@ -139,64 +124,143 @@ fn main() -> Result<()> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     let _image_context = image::init(InitFlag::PNG | InitFlag::JPG)?;
+    let ttf_context = sdl2::ttf::init()?;
 
-    let window = video_subsystem
-        .window("game tutorial", DIMENSION.width, DIMENSION.height)
+    let display_config = DisplayConfig::from_env();
+    let mut window_builder =
+        video_subsystem.window("game tutorial", DIMENSION.width, DIMENSION.height);
+    window_builder
         .position_centered()
+        .resizable()
+        .allow_highdpi();
+    if display_config.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder
         .build()
         .expect("could not initialize video subsystem");
 
-    let canvas = window
-        .into_canvas()
-        .build()
-        .expect("could not make a canvas");
+    let frame_config = FrameConfig::from_env();
+    let mut canvas_builder = window.into_canvas();
+    if frame_config.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder.build().expect("could not make a canvas");
+    let viewport = viewport::fit(&mut canvas)?;
 
     let texture_creator = canvas.texture_creator();
 
     let mut dispatcher = DispatcherBuilder::new()
         .with(client_listener::ClientListener, "ClientListener", &[])
-        .with(health_checker::HealthChecker, "HealthChecker", &[])
+        .with(effects::EffectSystem, "EffectSystem", &[])
+        .with(abilities::AbilitySystem, "AbilitySystem", &["EffectSystem"])
+        .with(
+            projectiles::ProjectileSystem,
+            "ProjectileSystem",
+            &["AbilitySystem"],
+        )
+        .with(
+            health_checker::HealthChecker,
+            "HealthChecker",
+            &["ProjectileSystem"],
+        )
+        .with(regen::RegenSystem, "RegenSystem", &["HealthChecker"])
+        .with(
+            particles::ParticleSystem,
+            "ParticleSystem",
+            &["HealthChecker"],
+        )
+        .with(
+            combat_text::CombatTextSystem,
+            "CombatTextSystem",
+            &["HealthChecker"],
+        )
+        .with(inventory::InventorySystem, "InventorySystem", &[])
+        .with(loot::LootSystem, "LootSystem", &[])
+        .with(progression::ProgressionSystem, "ProgressionSystem", &[])
+        .with(quests::QuestSystem, "QuestSystem", &[])
+        .with(chat::ChatListener, "ChatListener", &[])
+        .with(dialog::DialogSystem, "DialogSystem", &[])
         .with(keyboard::Keyboard, "Keyboard", &[])
-        // .with(physics::Physics, "Physics", &["Keyboard"])
         // .with(animator::Animator, "Animator", &["Keyboard"])
-        // .with(physics::Physics, "Physics", &[])
-        .with(animator::Animator, "Animator", &[])
+        .with(animator::Animator, "Animator", &["AbilitySystem"])
+        .with(
+            abilities::CastBarSystem,
+            "CastBarSystem",
+            &["AbilitySystem"],
+        )
+        .with(
+            unit_frames::UnitFrameSystem,
+            "UnitFrameSystem",
+            &["RegenSystem"],
+        )
+        .with(minimap::MinimapSystem, "MinimapSystem", &[])
+        .with(
+            ui::UiLayout,
+            "UiLayout",
+            &[
+                "UnitFrameSystem",
+                "DialogSystem",
+                "CastBarSystem",
+                "LootSystem",
+                "ProgressionSystem",
+                "QuestSystem",
+            ],
+        )
         .build();
 
     let mut world = World::new();
     dispatcher.setup(&mut world);
-    status::SystemData::setup(&mut world);
-    sprites::SystemData::setup(&mut world);
-    ui::SystemData::setup(&mut world);
+    setup_draw_data(&mut world);
 
     // Initialize resource
-    let server_update: Option<ServerUpdate> = None;
+    let server_updates: Vec<ServerUpdate> = Vec::new();
     let movement_command: Option<MovementCommand> = None;
     let shoot_command: Option<AttackCommand> = None;
     world.insert(movement_command);
-    world.insert(server_update);
+    world.insert(server_updates);
     world.insert(shoot_command);
+    world.insert(frame_config);
+    world.insert(viewport);
 
-    let bardo = include_bytes!("../assets/bardo.png");
-    let reaper = include_bytes!("../assets/reaper.png");
-    let textures = [
-        texture_creator.load_texture_bytes(bardo)?,
-        texture_creator.load_texture_bytes(reaper)?,
-    ];
-    // First texture in textures array
-    let _player_spritesheet = 0;
-    // Second texture in the textures array
-    let _enemy_spritesheet = 1;
+    let assets_dir = PathBuf::from(
+        env::var("GAME_ASSETS_DIR").unwrap_or_else(|_| assets::ASSETS_DIR.to_string()),
+    );
+    let mut assets = AssetManager::load(&assets_dir, &texture_creator, &ttf_context)?;
+    world.insert(assets.index().clone());
+    world.insert(map::CurrentMap(assets.index().start_map.clone()));
 
     // initialize_enemy(&mut world, enemy_spritesheet, Point::new(-150, -150));
     // initialize_enemy(&mut world, enemy_spritesheet, Point::new(150, -190));
     // initialize_enemy(&mut world, enemy_spritesheet, Point::new(-150, 170));
 
     // Create UI
-    world.create_entity().with(UiComponent {}).build();
+    let bottom_panel = ui::create_bottom_panel(&mut world);
+    action_bar::create_action_bar(&mut world, bottom_panel);
+    let experience_bar = progression::create_experience_bar(&mut world);
+    world.insert(experience_bar);
+    let inventory_windows = inventory::create_inventory_windows(&mut world);
+    world.insert(inventory_windows);
+    let loot_window = loot::create_loot_window(&mut world);
+    world.insert(loot_window);
+    let quest_window = quests::create_quest_window(&mut world);
+    world.insert(quest_window);
+    let quest_tracker = quests::create_quest_tracker(&mut world);
+    world.insert(quest_tracker);
+    let game_menu = ui::create_game_menu(&mut world);
+    let dialog_window = dialog::create_dialog_window(&mut world);
+    world.insert(dialog_window);
+    world.insert(dialog::DialogLibrary::load(
+        &assets_dir.join(dialog::DIALOG_DIR),
+    ));
+    let unit_frames = unit_frames::create_unit_frames(&mut world);
+    world.insert(unit_frames);
+    minimap::create_minimap(&mut world);
+    let cast_bar = abilities::create_cast_bar(&mut world);
+    world.insert(cast_bar);
 
-    send_socket.set_read_timeout(Some(Duration::new(0, 1_000)))?;
-    send_socket.set_write_timeout(Some(Duration::new(0, 1_000)))?;
+    // Only ever read from, and drained every tick until nothing is left
+    send_socket.set_nonblocking(true)?;
     recv_socket.set_read_timeout(Some(Duration::new(0, 1_000)))?;
     recv_socket.set_write_timeout(Some(Duration::new(0, 1_000)))?;
     send_socket.connect(SocketAddr::from((server_addr, SEND_SERVER_PORT)))?;
@@ -215,7 +279,7 @@ fn main() -> Result<()> {
             error!("send sync: {}", error)
         }
     }
-    match recv_socket.send(b"L1;blub_id") {
+    match recv_socket.send(&format!("L1;{}", PLAYER_ID).into_bytes()) {
         Ok(number_of_bytes) => {
             trace!("sent {} bytes to login", number_of_bytes);
             match recv_socket.recv_from(&mut []) {
@@ -230,54 +294,197 @@ fn main() -> Result<()> {
         }
     }
 
-    game_loop(
-        world,
+    let context = GameContext {
         canvas,
-        &textures,
-        dispatcher,
-        sdl_context.event_pump()?,
+        event_pump: sdl_context.event_pump()?,
+        assets: &mut assets,
+        game_menu: &game_menu,
+        text_cache: TextCache::new(&texture_creator),
         send_socket,
         recv_socket,
-        VecDeque::new(),
-        VecDeque::new(),
-    )?;
+    };
+    game_loop(world, dispatcher, context)?;
     Ok(())
 }
 
+/// The window, input, assets and connection the game loop runs with
+struct GameContext<'a, 't> {
+    canvas: WindowCanvas,
+    event_pump: EventPump,
+    assets: &'a mut AssetManager<'t>,
+    game_menu: &'a ui::GameMenu,
+    text_cache: TextCache<'t>,
+    send_socket: &'a UdpSocket,
+    recv_socket: &'a UdpSocket,
+}
+
 fn game_loop<'a>(
     mut world: World,
-    mut canvas: WindowCanvas,
-    textures: &[Texture<'a>],
     mut dispatcher: Dispatcher<'a, 'a>,
-    mut event_pump: EventPump,
-    send_socket: &UdpSocket,
-    recv_socket: &UdpSocket,
-    mut movements: VecDeque<MovementCommand>,
-    mut attacks: VecDeque<AttackCommand>,
+    context: GameContext,
 ) -> Result<()> {
+    let GameContext {
+        mut canvas,
+        mut event_pump,
+        assets,
+        game_menu,
+        mut text_cache,
+        send_socket,
+        recv_socket,
+    } = context;
+    let mut movements: VecDeque<MovementCommand> = VecDeque::new();
+    let mut attacks: VecDeque<AttackCommand> = VecDeque::new();
     let mut entities: HashMap<String, Entity> = HashMap::new();
+    let frame_config = *world.read_resource::<FrameConfig>();
+    let mut timestep = FixedTimestep::start();
+    let mut queue = RenderQueue::default();
     // let mut sync_trigger
     'running: loop {
+        let frame_start = Instant::now();
         // Handle events
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    *world.write_resource::<Viewport>() = viewport::fit(&mut canvas)?;
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => {
+                    viewport::toggle_fullscreen(&mut canvas)?;
+                    continue;
+                }
+                _ => {}
+            }
+            if world.read_resource::<chat::ChatInput>().active {
+                let message = chat::handle_input(
+                    &mut world.write_resource(),
+                    &mut world.write_resource(),
+                    &event,
+                );
+                if let Some(message) = message {
+                    send_command(recv_socket, &message.to_command(), "chat message");
+                }
+                continue;
+            }
+            if world.read_resource::<dialog::ActiveDialog>().0.is_some()
+                && matches!(event, Event::KeyDown { .. })
+            {
+                let reply = dialog::handle_input(&mut world.write_resource(), &event);
+                if let Some(reply) = reply {
+                    send_command(recv_socket, &reply.to_command(), "dialog reply");
+                }
+                continue;
+            }
+            if ui::handle_event(&event, world.system_data()) {
+                continue;
+            }
+            // A click on loot in the world is used up by opening it
+            if let Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } = event
+            {
+                if loot::open_at(Point::new(x, y), world.system_data()) {
+                    continue;
+                }
+            }
+            match event {
+                Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
-                    break 'running;
+                    let mut nodes = world.write_storage::<UiComponent>();
+                    if let Some(menu) = nodes.get_mut(game_menu.panel) {
+                        menu.visible = !menu.visible;
+                        if menu.visible {
+                            world.write_resource::<ui::UiState>().focused = Some(game_menu.resume);
+                        }
+                    }
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Tab),
                     repeat: false,
                     ..
-                } => {} //cycle through enemies
+                } => unit_frames::cycle_target(world.system_data()),
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    repeat: false,
+                    ..
+                } => {
+                    let window = world.read_resource::<inventory::InventoryWindows>().bags;
+                    inventory::toggle(&mut world.write_storage(), window);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    repeat: false,
+                    ..
+                } => loot::open_nearest(world.system_data()),
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    repeat: false,
+                    ..
+                } => {
+                    let window = world
+                        .read_resource::<inventory::InventoryWindows>()
+                        .character;
+                    inventory::toggle(&mut world.write_storage(), window);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::L),
+                    repeat: false,
+                    ..
+                } => {
+                    let window = world.read_resource::<quests::QuestWindow>().panel;
+                    inventory::toggle(&mut world.write_storage(), window);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    repeat: false,
+                    ..
+                } => {
+                    // Stop walking while typing, the key ups go to the chat
+                    movements.clear();
+                    world.write_resource::<chat::ChatInput>().open();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageUp),
+                    ..
+                } => world.write_resource::<chat::ChatLog>().scroll_by(3),
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageDown),
+                    ..
+                } => world.write_resource::<chat::ChatLog>().scroll_by(-3),
                 Event::KeyDown {
                     keycode: Some(Keycode::Space),
                     repeat: false,
                     ..
-                } => attacks.push_front(AttackCommand::Cast()),
+                } => {
+                    if let Some(ability) = world.read_resource::<action_bar::ActionBar>().ability(0)
+                    {
+                        attacks.push_front(AttackCommand::Cast(ability.to_string()));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } if action_bar::slot_for_key(keycode).is_some() => {
+                    let slot = action_bar::slot_for_key(keycode).unwrap_or_default();
+                    if let Some(ability) =
+                        world.read_resource::<action_bar::ActionBar>().ability(slot)
+                    {
+                        attacks.push_front(AttackCommand::Cast(ability.to_string()));
+                    }
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::Left),
                     repeat: false,
@@ -394,67 +601,261 @@ fn game_loop<'a>(
             }
         }
 
-        let movement_command: MovementCommand =
-            *movements.front().unwrap_or(&MovementCommand::Stop);
-        match movement_command {
-            MovementCommand::Move(direction) => {
-                let msg = format!("M0;blub_id;{}", direction);
-                match recv_socket.send(&msg.into_bytes()) {
-                    Ok(_) => {
-                        trace!("send successful");
-                        match recv_socket.recv(&mut []) {
-                            Ok(_) => {}
-                            Err(error) => {
-                                error!("ack Move command: {}", error)
-                            }
+        let ui_events: Vec<ui::UiEvent> = world
+            .write_resource::<ui::UiState>()
+            .events
+            .drain(..)
+            .collect();
+        for ui_event in ui_events {
+            match ui_event {
+                ui::UiEvent::Clicked { action } => match action.as_str() {
+                    ui::MENU_RESUME_ACTION => {
+                        if let Some(menu) = world
+                            .write_storage::<UiComponent>()
+                            .get_mut(game_menu.panel)
+                        {
+                            menu.visible = false;
                         }
                     }
-                    Err(error) => {
-                        error!("sending Move command: {}", error)
+                    ui::MENU_QUIT_ACTION => break 'running,
+                    minimap::ZOOM_IN_ACTION => {
+                        world.write_resource::<minimap::MinimapState>().zoom_by(1)
+                    }
+                    minimap::ZOOM_OUT_ACTION => {
+                        world.write_resource::<minimap::MinimapState>().zoom_by(-1)
+                    }
+                    loot::LOOT_ALL_ACTION => {
+                        if let Some(request) = loot::take_all(world.system_data()) {
+                            send_command(recv_socket, &request.to_command(), "loot request");
+                        }
+                    }
+                    quests::QUEST_ABANDON_ACTION => {
+                        let request = quests::abandon(&world.read_resource());
+                        if let Some(request) = request {
+                            send_command(recv_socket, &request.to_command(), "quest request");
+                        }
+                    }
+                    _ => {}
+                },
+                ui::UiEvent::Selected { action, index } => match action.as_str() {
+                    action_bar::ACTION_BAR_ACTION => {
+                        if let Some(ability) = world
+                            .read_resource::<action_bar::ActionBar>()
+                            .ability(index)
+                        {
+                            attacks.push_front(AttackCommand::Cast(ability.to_string()));
+                        }
+                    }
+                    inventory::BAG_ACTION | inventory::EQUIPMENT_ACTION => {
+                        let request = inventory::click(&action, index, world.system_data());
+                        if let Some(request) = request {
+                            send_command(recv_socket, &request.to_command(), "item request");
+                        }
+                    }
+                    loot::LOOT_ACTION => {
+                        if let Some(request) = loot::take(index, world.system_data()) {
+                            send_command(recv_socket, &request.to_command(), "loot request");
+                        }
+                    }
+                    quests::QUEST_LOG_ACTION => quests::select(index, &mut world.write_resource()),
+                    dialog::DIALOG_CHOICE_ACTION => {
+                        let reply = dialog::choose(&mut world.write_resource(), index);
+                        if let Some(reply) = reply {
+                            send_command(recv_socket, &reply.to_command(), "dialog reply");
+                        }
+                    }
+                    _ => {}
+                },
+                ui::UiEvent::Moved { action, from, to } => match action.as_str() {
+                    action_bar::ACTION_BAR_ACTION => {
+                        let mut bar = world.write_resource::<action_bar::ActionBar>();
+                        bar.swap(from, to);
+                        bar.save();
+                    }
+                    inventory::BAG_ACTION => {
+                        let request =
+                            inventory::drag((&action, from), (&action, to), world.system_data());
+                        if let Some(request) = request {
+                            send_command(recv_socket, &request.to_command(), "item request");
+                        }
+                    }
+                    _ => {}
+                },
+                ui::UiEvent::Transferred {
+                    from_action, from, ..
+                } if from_action == loot::LOOT_ACTION => {
+                    if let Some(request) = loot::take(from, world.system_data()) {
+                        send_command(recv_socket, &request.to_command(), "loot request");
+                    }
+                }
+                ui::UiEvent::Transferred {
+                    from_action,
+                    from,
+                    to_action,
+                    to,
+                } => {
+                    let request = inventory::drag(
+                        (&from_action, from),
+                        (&to_action, to),
+                        world.system_data(),
+                    );
+                    if let Some(request) = request {
+                        send_command(recv_socket, &request.to_command(), "item request");
+                    }
+                }
+                ui::UiEvent::Discarded {
+                    action,
+                    slot,
+                    confirmed,
+                } => {
+                    let request = inventory::discard(&action, slot, confirmed, world.system_data());
+                    if let Some(request) = request {
+                        send_command(recv_socket, &request.to_command(), "item request");
+                    }
+                }
+                ui::UiEvent::Pointed { action, offset } => {
+                    if action == minimap::MINIMAP_ACTION {
+                        let ping = minimap::ping_at(offset, world.system_data());
+                        send_command(recv_socket, &ping.to_command(), "minimap ping");
+                    }
+                }
+                ui::UiEvent::Scrolled { action, amount } => {
+                    if action == minimap::MINIMAP_ACTION {
+                        world
+                            .write_resource::<minimap::MinimapState>()
+                            .zoom_by(amount.signum());
                     }
                 }
             }
-            MovementCommand::Stop => send_player_stationary(recv_socket),
         }
 
-        let shoot_command: Option<AttackCommand> =
-            Some(attacks.pop_front().unwrap_or(AttackCommand::Stop));
-        *world.write_resource() = shoot_command;
-
-        match update_from_server(&send_socket) {
-            Ok(server_update) => {
-                match &server_update {
-                    ServerUpdate::Update(player_update) => {
-                        if !entities.contains_key(&player_update.id) {
-                            let new_player =
-                                initialize_player(&mut world, player_update.id.clone(), 0);
-                            entities.insert(player_update.id.to_string(), new_player);
+        // Simulate in fixed steps, however long the last frame took
+        let steps = timestep.advance();
+        for _ in 0..steps {
+            let movement_command = if effects::input_locked(world.system_data()) {
+                MovementCommand::Stop
+            } else {
+                *movements.front().unwrap_or(&MovementCommand::Stop)
+            };
+            match movement_command {
+                MovementCommand::Move(direction) => {
+                    let msg = format!("M0;{};{}", PLAYER_ID, direction);
+                    match recv_socket.send(&msg.into_bytes()) {
+                        Ok(_) => {
+                            trace!("send successful");
+                            match recv_socket.recv(&mut []) {
+                                Ok(_) => {}
+                                Err(error) => {
+                                    error!("ack Move command: {}", error)
+                                }
+                            }
+                        }
+                        Err(error) => {
+                            error!("sending Move command: {}", error)
                         }
                     }
-                    _ => {}
                 }
-                *world.write_resource() = Some(server_update);
+                MovementCommand::Stop => send_player_stationary(recv_socket),
+            }
+            *world.write_resource() = Some(movement_command);
+
+            let shoot_command: Option<AttackCommand> =
+                Some(attacks.pop_front().unwrap_or(AttackCommand::Stop));
+            *world.write_resource() = shoot_command;
+
+            let server_updates = updates_from_server(send_socket);
+            for server_update in &server_updates {
+                if let ServerUpdate::Update(player_update) = server_update {
+                    if !entities.contains_key(&player_update.id) {
+                        let skin = assets.index().skin(player_update.skin).to_string();
+                        let new_player =
+                            initialize_player(&mut world, player_update.id.clone(), &skin);
+                        entities.insert(player_update.id.to_string(), new_player);
+                    }
+                }
+            }
+            *world.write_resource() = server_updates;
+
+            {
+                let mut time = world.write_resource::<GameTime>();
+                time.delta = TICK;
+                time.total += TICK;
+                time.ticks += 1;
+            }
+            dispatcher.dispatch(&world);
+            world.maintain();
+
+            let requests = std::mem::take(&mut world.write_resource::<CastRequests>().0);
+            for request in requests {
+                send_command(recv_socket, &request.to_command(), "cast request");
             }
-            _ => {}
         }
+        world.write_resource::<GameTime>().alpha = timestep.alpha();
 
-        // Update
-        dispatcher.dispatch(&mut world);
-        world.maintain();
+        // Pick up assets changed on disk, texture handles stay the same
+        if assets.reload_changed() {
+            *world.write_resource() = assets.index().clone();
+        }
 
         // Render
-        canvas.set_draw_color(Color::RGB(65, 64, 255));
-        canvas.clear();
+        let mut renderer = SdlRenderer::new(&mut canvas, assets, "ui", &mut text_cache);
+        queue.begin(renderer.output_size()?);
+        queue_frame(&mut queue, &world, renderer.font()?)?;
+        renderer.clear(BACKGROUND);
+        queue.flush(&mut renderer)?;
+        renderer.present();
 
-        status::draw_to_canvas(&mut canvas, world.system_data())?;
-        sprites::draw_to_canvas(&mut canvas, &textures, world.system_data())?;
-        ui::draw_to_canvas(&mut canvas, Color::RGB(65, 255, 255), world.system_data())?;
+        // Sleep away the rest of the frame
+        if let Some(frame_time) = frame_config.frame_time() {
+            if let Some(remaining) = frame_time.checked_sub(frame_start.elapsed()) {
+                ::std::thread::sleep(remaining);
+            }
+        }
+    }
+    Ok(())
+}
 
-        canvas.present();
+/// Registers the resources and components read by the draw modules
+fn setup_draw_data(world: &mut World) {
+    status::SystemData::setup(world);
+    sprites::SystemData::setup(world);
+    ui::SystemData::setup(world);
+    chat::SystemData::setup(world);
+    combat_text::SystemData::setup(world);
+    map::SystemData::setup(world);
+    minimap::SystemData::setup(world);
+    action_bar::SystemData::setup(world);
+    effects::SystemData::setup(world);
+    projectiles::SystemData::setup(world);
+    particles::SystemData::setup(world);
+    inventory::SystemData::setup(world);
+    loot::SystemData::setup(world);
+    progression::SystemData::setup(world);
+    quests::SystemData::setup(world);
+}
 
-        // Time management!
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 20));
-    }
+/// Queues everything drawn in a frame. The queue sorts the draws into layers, so the order
+/// of the modules here doesn't matter.
+fn queue_frame(
+    queue: &mut RenderQueue,
+    world: &World,
+    font: &dyn TextMetrics,
+) -> std::result::Result<(), String> {
+    map::draw(queue, world.system_data());
+    sprites::draw(queue, world.system_data());
+    loot::draw(queue, world.system_data());
+    projectiles::draw(queue, world.system_data());
+    particles::draw(queue, world.system_data());
+    status::draw(queue, world.system_data());
+    combat_text::draw(queue, font, world.system_data())?;
+    progression::draw(queue, font, world.system_data())?;
+    ui::draw(queue, font, world.system_data())?;
+    minimap::draw(queue, world.system_data());
+    action_bar::draw(queue, font, world.system_data())?;
+    effects::draw(queue, font, world.system_data())?;
+    inventory::draw(queue, font, world.system_data())?;
+    quests::draw(queue, font, world.system_data())?;
+    chat::draw(queue, font, world.system_data())?;
     Ok(())
 }
 
@@ -462,8 +863,26 @@ fn _remove_entities(world: &mut World, entity: Entity) {
     world.entities().delete(entity).unwrap();
 }
 
+/// Sends a command to the server and waits for the acknowledgement
+fn send_command(socket: &UdpSocket, command: &str, description: &str) {
+    match socket.send(command.as_bytes()) {
+        Ok(_) => {
+            trace!("Send {} successful.", description);
+            match socket.recv(&mut []) {
+                Ok(_) => {}
+                Err(error) => {
+                    error!("ack {}: {}", description, error)
+                }
+            }
+        }
+        Err(error) => {
+            error!("sending {}: {}", description, error)
+        }
+    }
+}
+
 fn send_player_stationary(socket: &UdpSocket) {
-    let msg = format!("M0;blub_id;{}", Direction::Stationary);
+    let msg = format!("M0;{};{}", PLAYER_ID, Direction::Stationary);
     match socket.send(&msg.into_bytes()) {
         Ok(_) => {
             trace!("Send Stationary successful.");
@@ -480,40 +899,168 @@ fn send_player_stationary(socket: &UdpSocket) {
     }
 }
 
-fn update_from_server(socket: &UdpSocket) -> Result<ServerUpdate> {
-    let mut buf = [0; 200];
-    match socket.recv(&mut buf) {
-        Ok(number_of_bytes) => {
-            trace!("update from server; {}", number_of_bytes);
-            if number_of_bytes == 1 {
-                return Ok(ServerUpdate::Nothing);
-            } else {
-                match get_operation_from(&buf) {
-                    "L1;" => {
-                        let player_id: &str = get_context_from(&buf, number_of_bytes);
-                        debug!("get op L1; {}", player_id);
-                        return Ok(ServerUpdate::Login(player_id.to_string()));
-                    }
-                    "P0;" => {
-                        let player = Player::from_str(get_context_from(&buf, number_of_bytes));
-                        debug!("update from server: P0; {:?}", player);
-                        return Ok(ServerUpdate::Update(player));
-                    }
-                    _ => Ok(ServerUpdate::Nothing),
+/// Reads every update the server sent since the last tick. Broken ones are logged and
+/// dropped.
+fn updates_from_server(socket: &UdpSocket) -> Vec<ServerUpdate> {
+    let mut updates = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match socket.recv(&mut buf) {
+            Ok(number_of_bytes) => {
+                trace!("update from server; {}", number_of_bytes);
+                match parse_update(&buf[..number_of_bytes]) {
+                    Ok(Some(update)) => updates.push(update),
+                    Ok(None) => {}
+                    Err(error) => error!("dropped a broken server update: {}", error),
                 }
             }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) => {
+                error!("update from server: {}", error);
+                break;
+            }
+        }
+    }
+    updates
+}
+
+/// The update in a datagram of the server, `None` for keep-alives and unknown operations
+fn parse_update(buf: &[u8]) -> Result<Option<ServerUpdate>> {
+    if buf.len() == 1 {
+        return Ok(None);
+    }
+    match get_operation_from(buf)? {
+        "L1;" => {
+            let player_id: &str = get_context_from(buf)?;
+            debug!("get op L1; {}", player_id);
+            Ok(Some(ServerUpdate::Login))
+        }
+        "P0;" => {
+            let player = Player::from_str(get_context_from(buf)?);
+            debug!("update from server: P0; {:?}", player);
+            Ok(Some(ServerUpdate::Update(player)))
         }
-        _ => {
-            trace!("update from server, nothing to do");
-            Ok(ServerUpdate::Nothing)
+        operation @ ("C0;" | "C1;" | "C2;" | "C3;") => {
+            let channel = operation[1..2].parse::<ChatChannel>()?;
+            let message = ChatMessage::from_str(channel, get_context_from(buf)?);
+            debug!("update from server: {} {:?}", operation, message);
+            Ok(Some(ServerUpdate::Chat(message)))
         }
+        "D0;" => {
+            let mut parts = get_context_from(buf)?.split(';');
+            let update = DialogUpdate::Open {
+                npc: parts.next().unwrap_or_default().to_string(),
+                dialog: parts.next().unwrap_or_default().to_string(),
+                node: parts.next().map(|node| node.to_string()),
+            };
+            debug!("update from server: D0; {:?}", update);
+            Ok(Some(ServerUpdate::Dialog(update)))
+        }
+        "D1;" => {
+            let mut parts = get_context_from(buf)?.splitn(2, ';');
+            let npc = parts.next().unwrap_or_default().to_string();
+            let tree = dialog::parse_tree(parts.next().unwrap_or_default())?;
+            debug!("update from server: D1; {} {}", npc, tree.id);
+            Ok(Some(ServerUpdate::Dialog(DialogUpdate::Tree { npc, tree })))
+        }
+        "D2;" => Ok(Some(ServerUpdate::Dialog(DialogUpdate::Close))),
+        "N0;" => {
+            let ping = MinimapPing::from_str(get_context_from(buf)?)?;
+            debug!("update from server: N0; {:?}", ping);
+            Ok(Some(ServerUpdate::Ping(ping)))
+        }
+        "H0;" => {
+            let update = CombatUpdate::from_str(get_context_from(buf)?)?;
+            debug!("update from server: H0; {:?}", update);
+            Ok(Some(ServerUpdate::Combat(update)))
+        }
+        "E0;" => {
+            let update = EffectUpdate::apply_from_str(get_context_from(buf)?)?;
+            debug!("update from server: E0; {:?}", update);
+            Ok(Some(ServerUpdate::Effect(update)))
+        }
+        "E1;" => {
+            let update = EffectUpdate::remove_from_str(get_context_from(buf)?);
+            debug!("update from server: E1; {:?}", update);
+            Ok(Some(ServerUpdate::Effect(update)))
+        }
+        "I0;" => {
+            let update = InventoryUpdate::bag_from_str(get_context_from(buf)?)?;
+            debug!("update from server: I0; {:?}", update);
+            Ok(Some(ServerUpdate::Inventory(update)))
+        }
+        "I1;" => {
+            let update = InventoryUpdate::equipment_from_str(get_context_from(buf)?)?;
+            debug!("update from server: I1; {:?}", update);
+            Ok(Some(ServerUpdate::Inventory(update)))
+        }
+        "O0;" => {
+            let update = LootUpdate::drop_from_str(get_context_from(buf)?)?;
+            debug!("update from server: O0; {:?}", update);
+            Ok(Some(ServerUpdate::Loot(update)))
+        }
+        "O1;" => {
+            let update = LootUpdate::remove_from_str(get_context_from(buf)?);
+            debug!("update from server: O1; {:?}", update);
+            Ok(Some(ServerUpdate::Loot(update)))
+        }
+        "Q0;" => {
+            let update = QuestUpdate::state_from_str(get_context_from(buf)?)?;
+            debug!("update from server: Q0; {:?}", update);
+            Ok(Some(ServerUpdate::Quest(update)))
+        }
+        "Q1;" => {
+            let update = QuestUpdate::remove_from_str(get_context_from(buf)?);
+            debug!("update from server: Q1; {:?}", update);
+            Ok(Some(ServerUpdate::Quest(update)))
+        }
+        "X0;" => {
+            let update = ProgressUpdate::from_str(get_context_from(buf)?)?;
+            debug!("update from server: X0; {:?}", update);
+            Ok(Some(ServerUpdate::Progress(update)))
+        }
+        "R0;" => {
+            let update = StatusUpdate::from_str(get_context_from(buf)?)?;
+            debug!("update from server: R0; {:?}", update);
+            Ok(Some(ServerUpdate::Status(update)))
+        }
+        _ => Ok(None),
     }
 }
 
-fn get_operation_from(buffer: &[u8]) -> &str {
-    str::from_utf8(&buffer[0..3]).unwrap()
+fn get_operation_from(buffer: &[u8]) -> Result<&str> {
+    let operation = buffer.get(0..3).ok_or("server update is too short")?;
+    Ok(str::from_utf8(operation)?)
+}
+
+fn get_context_from(buffer: &[u8]) -> Result<&str> {
+    let context = buffer.get(3..).ok_or("server update is too short")?;
+    Ok(str::from_utf8(context)?)
 }
 
-fn get_context_from(buffer: &[u8], size: usize) -> &str {
-    str::from_utf8(&buffer[3..size]).unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_or_garbled_datagrams_are_errors() {
+        assert!(get_operation_from(b"C0").is_err());
+        assert!(get_context_from(b"C0").is_err());
+        assert!(get_operation_from(b"C\xff;").is_err());
+        assert!(get_context_from(b"C0;reaper;\xff\xfe").is_err());
+
+        assert_eq!(get_operation_from(b"C0;reaper;hi").unwrap(), "C0;");
+        assert_eq!(get_context_from(b"C0;reaper;hi").unwrap(), "reaper;hi");
+        assert_eq!(get_context_from(b"D2;").unwrap(), "");
+    }
+
+    #[test]
+    fn keep_alives_and_unknown_operations_are_no_update() {
+        assert!(parse_update(b"\0").unwrap().is_none());
+        assert!(parse_update(b"Z9;whatever").unwrap().is_none());
+        assert!(matches!(
+            parse_update(b"D2;").unwrap(),
+            Some(ServerUpdate::Dialog(DialogUpdate::Close))
+        ));
+    }
 }
@@ -0,0 +1,55 @@
+use sdl2::rect::{Point, Rect};
+use specs::prelude::*;
+
+use crate::assets::AssetIndex;
+use crate::render::{Layer, RenderQueue};
+use crate::ui;
+
+/// Tiles are drawn at twice their size, like the sprites
+pub const SCALE: u32 = 2;
+
+/// Id of the map the player is currently on
+#[derive(Default)]
+pub struct CurrentMap(pub Option<String>);
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (Read<'a, CurrentMap>, Read<'a, AssetIndex>);
+
+/// Queues the tiles of the current map that are on screen on the ground layer
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let (current, index) = data;
+    let map = match current.0.as_ref().and_then(|id| index.map_data(id)) {
+        Some(map) => map,
+        None => return,
+    };
+    let (width, height) = queue.size();
+    let screen = Rect::new(0, 0, width, height);
+    // Treat the center of the screen as the (0, 0) coordinate
+    let screen_origin =
+        ui::world_to_screen(Point::new(map.origin[0], map.origin[1]), (width, height));
+
+    let tileset = index.texture(&map.tileset);
+    let tileset_columns = (index.texture_size(tileset).0 / map.tile_size).max(1);
+    let tile_size = (map.tile_size * SCALE) as i32;
+    for row in 0..map.rows {
+        for column in 0..map.columns {
+            let target = Rect::new(
+                screen_origin.x() + column as i32 * tile_size,
+                screen_origin.y() + row as i32 * tile_size,
+                tile_size as u32,
+                tile_size as u32,
+            );
+            if !target.has_intersection(screen) {
+                continue;
+            }
+            let tile = map.tile(column, row);
+            let source = Rect::new(
+                ((tile % tileset_columns) * map.tile_size) as i32,
+                ((tile / tileset_columns) * map.tile_size) as i32,
+                map.tile_size,
+                map.tile_size,
+            );
+            queue.copy(Layer::Ground, 0, tileset, source, target);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use specs::prelude::*;
+
+use crate::{
+    assets::AssetIndex,
+    components::*,
+    map::{self, CurrentMap},
+    render::{Layer, RenderQueue},
+    time::GameTime,
+    ui, ServerUpdate,
+};
+
+/// Action of the map area, clicks ping and the wheel zooms
+pub const MINIMAP_ACTION: &str = "minimap";
+/// Actions of the zoom buttons
+pub const ZOOM_IN_ACTION: &str = "minimap_zoom_in";
+pub const ZOOM_OUT_ACTION: &str = "minimap_zoom_out";
+
+/// Size of the map area, without the frame
+const SIZE: u32 = 160;
+/// Minimap pixels per world pixel, from the widest view to the closest
+const ZOOM_LEVELS: [f32; 4] = [0.05, 0.1, 0.2, 0.4];
+const DEFAULT_ZOOM: usize = 1;
+/// How long a ping stays on the minimap
+const PING_DURATION_MS: u32 = 4000;
+const DOT_SIZE: u32 = 4;
+/// Team of the characters nobody plays
+const NPC_TEAM: u8 = 0;
+
+const BACKGROUND: Color = Color::RGB(10, 10, 20);
+const SELF_COLOR: Color = Color::WHITE;
+const PARTY_COLOR: Color = Color::RGB(60, 220, 60);
+const ENEMY_COLOR: Color = Color::RGB(230, 40, 40);
+const NPC_COLOR: Color = Color::RGB(240, 210, 40);
+const PING_COLOR: Color = Color::RGB(255, 150, 0);
+
+/// A ping and how long it has been shown
+pub struct ActivePing {
+    pub ping: MinimapPing,
+    pub age: u32,
+}
+
+pub struct MinimapState {
+    /// Index into `ZOOM_LEVELS`
+    pub zoom: usize,
+    pub pings: Vec<ActivePing>,
+}
+
+impl Default for MinimapState {
+    fn default() -> Self {
+        Self {
+            zoom: DEFAULT_ZOOM,
+            pings: Vec::new(),
+        }
+    }
+}
+
+impl MinimapState {
+    /// Zooms in for positive steps and out for negative ones
+    pub fn zoom_by(&mut self, steps: i32) {
+        self.zoom = (self.zoom as i32 + steps).clamp(0, ZOOM_LEVELS.len() as i32 - 1) as usize;
+    }
+
+    pub fn scale(&self) -> f32 {
+        ZOOM_LEVELS[self.zoom]
+    }
+
+    pub fn ping(&mut self, ping: MinimapPing) {
+        self.pings.push(ActivePing { ping, age: 0 });
+    }
+}
+
+/// Creates the minimap in the top right corner, with zoom buttons in its bottom corners
+pub fn create_minimap(world: &mut World) -> Entity {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (SIZE + 20, SIZE + 20),
+            )
+            .anchored(Anchor::TopRight, Point::new(-10, 10))
+            .with_layout(Layout::Free, 10, 0),
+        )
+        .build();
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Minimap {
+                    action: MINIMAP_ACTION.to_string(),
+                },
+                (0, 0),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    for (order, (label, action, anchor)) in [
+        ("-", ZOOM_OUT_ACTION, Anchor::BottomLeft),
+        ("+", ZOOM_IN_ACTION, Anchor::BottomRight),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        world
+            .create_entity()
+            .with(
+                UiComponent::new(
+                    Widget::Button {
+                        label: label.to_string(),
+                        action: action.to_string(),
+                    },
+                    (24, 20),
+                )
+                .with_parent(panel, order as i32 + 1)
+                .anchored(anchor, Point::new(0, 0)),
+            )
+            .build();
+    }
+
+    panel
+}
+
+/// Collects the pings of the party and forgets old ones
+pub struct MinimapSystem;
+
+impl<'a> System<'a> for MinimapSystem {
+    type SystemData = (
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        Read<'a, GameTime>,
+        Write<'a, MinimapState>,
+    );
+
+    fn run(&mut self, (server_updates, time, mut state): Self::SystemData) {
+        for ping in state.pings.iter_mut() {
+            ping.age += time.delta_ms();
+        }
+        state.pings.retain(|ping| ping.age < PING_DURATION_MS);
+
+        // Our own pings are shown when they are sent
+        for server_update in server_updates.iter() {
+            if let ServerUpdate::Ping(ping) = server_update {
+                if ping.sender != PLAYER_ID {
+                    state.ping(ping.clone());
+                }
+            }
+        }
+    }
+}
+
+pub type PingData<'a> = (ReadStorage<'a, Player>, Write<'a, MinimapState>);
+
+/// Pings the world position under a click `offset` from the center of the minimap.
+/// Returns the ping to send to the server.
+pub fn ping_at(offset: Point, data: PingData) -> MinimapPing {
+    let (players, mut state) = data;
+    let center = own_position(&players);
+    let scale = state.scale();
+    let ping = MinimapPing {
+        sender: PLAYER_ID.to_string(),
+        position: center
+            + Point::new(
+                (offset.x() as f32 / scale) as i32,
+                (offset.y() as f32 / scale) as i32,
+            ),
+    };
+    state.ping(ping.clone());
+    ping
+}
+
+fn own_position(players: &ReadStorage<Player>) -> Point {
+    players
+        .join()
+        .find(|player| player.is_local())
+        .map(|player| player.world_pos)
+        .unwrap_or_else(|| Point::new(0, 0))
+}
+
+fn dot_color(player: &Player, own_team: Option<u8>) -> Color {
+    if player.is_local() {
+        SELF_COLOR
+    } else if player.team == NPC_TEAM {
+        NPC_COLOR
+    } else if Some(player.team) == own_team {
+        PARTY_COLOR
+    } else {
+        ENEMY_COLOR
+    }
+}
+
+/// Queues `source` of a texture stretched over `target`, cut to `clip`
+fn copy_clipped(
+    queue: &mut RenderQueue,
+    z: i32,
+    texture: TextureHandle,
+    source: Rect,
+    target: Rect,
+    clip: Rect,
+) {
+    let visible = match target.intersection(clip) {
+        Some(visible) => visible,
+        None => return,
+    };
+    let scale_x = source.width() as f32 / target.width() as f32;
+    let scale_y = source.height() as f32 / target.height() as f32;
+    let source = Rect::new(
+        source.x() + ((visible.x() - target.x()) as f32 * scale_x) as i32,
+        source.y() + ((visible.y() - target.y()) as f32 * scale_y) as i32,
+        ((visible.width() as f32 * scale_x).round() as u32).max(1),
+        ((visible.height() as f32 * scale_y).round() as u32).max(1),
+    );
+    queue.copy(Layer::Ui, z, texture, source, visible);
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    ReadStorage<'a, UiComponent>,
+    ReadStorage<'a, Player>,
+    Read<'a, MinimapState>,
+    Read<'a, CurrentMap>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the map, the players and the pings into every shown minimap widget
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let (nodes, players, state, current, index) = data;
+    let center = own_position(&players);
+    let own_team = players
+        .join()
+        .find(|player| player.is_local())
+        .map(|player| player.team);
+    let scale = state.scale();
+
+    for node in nodes.join() {
+        if !node.shown || !matches!(node.widget, Widget::Minimap { .. }) {
+            continue;
+        }
+        let bounds = node.bounds;
+        let z = node.depth as i32;
+        let to_minimap = |world: Point| {
+            bounds.center()
+                + Point::new(
+                    ((world.x() - center.x()) as f32 * scale).floor() as i32,
+                    ((world.y() - center.y()) as f32 * scale).floor() as i32,
+                )
+        };
+        queue.fill_rect(Layer::Ui, z, BACKGROUND, bounds);
+
+        if let Some(map) = current.0.as_ref().and_then(|id| index.map_data(id)) {
+            let tileset = index.texture(&map.tileset);
+            let tileset_columns = (index.texture_size(tileset).0 / map.tile_size).max(1);
+            let tile_size = (map.tile_size * map::SCALE) as i32;
+            let origin = Point::new(map.origin[0], map.origin[1]);
+            for row in 0..map.rows {
+                for column in 0..map.columns {
+                    // From the corners of the tile so neighbouring tiles leave no gaps
+                    let top_left = to_minimap(
+                        origin + Point::new(column as i32 * tile_size, row as i32 * tile_size),
+                    );
+                    let bottom_right = to_minimap(
+                        origin
+                            + Point::new(
+                                (column as i32 + 1) * tile_size,
+                                (row as i32 + 1) * tile_size,
+                            ),
+                    );
+                    if bottom_right.x() <= top_left.x() || bottom_right.y() <= top_left.y() {
+                        continue;
+                    }
+                    let target = Rect::new(
+                        top_left.x(),
+                        top_left.y(),
+                        (bottom_right.x() - top_left.x()) as u32,
+                        (bottom_right.y() - top_left.y()) as u32,
+                    );
+                    let tile = map.tile(column, row);
+                    let source = Rect::new(
+                        ((tile % tileset_columns) * map.tile_size) as i32,
+                        ((tile / tileset_columns) * map.tile_size) as i32,
+                        map.tile_size,
+                        map.tile_size,
+                    );
+                    copy_clipped(queue, z, tileset, source, target, bounds);
+                }
+            }
+        }
+
+        // The player last so it is never hidden by someone standing close
+        let mut dots: Vec<&Player> = players.join().collect();
+        dots.sort_by_key(|player| player.is_local());
+        for player in dots {
+            let dot = Rect::from_center(to_minimap(player.world_pos), DOT_SIZE, DOT_SIZE);
+            if bounds.contains_rect(dot) {
+                queue.fill_rect(Layer::Ui, z, dot_color(player, own_team), dot);
+            }
+        }
+
+        // Pings pulse, and stay at the edge when they are out of view
+        for ping in &state.pings {
+            let position = to_minimap(ping.ping.position);
+            let position = Point::new(
+                position.x().clamp(bounds.left() + 4, bounds.right() - 5),
+                position.y().clamp(bounds.top() + 4, bounds.bottom() - 5),
+            );
+            let size = 6 + (ping.age % 1000) / 100;
+            queue.draw_rect(
+                Layer::Ui,
+                z,
+                PING_COLOR,
+                Rect::from_center(position, size, size),
+            );
+            queue.fill_rect(Layer::Ui, z, PING_COLOR, Rect::from_center(position, 2, 2));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use rand::Rng;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use specs::prelude::*;
+
+use crate::{
+    assets::{AssetIndex, EmitterData},
+    components::*,
+    map::CurrentMap,
+    render::{Layer, RenderQueue},
+    time::GameTime,
+    ui,
+};
+
+/// Emitters started on characters by what happens to them in combat
+const HIT_EMITTER: &str = "hit";
+const CRIT_EMITTER: &str = "crit";
+
+/// Starts emitters for hits and for the current map, spawns and moves particles and
+/// removes emitters once they and their particles are done
+pub struct ParticleSystem;
+
+impl<'a> System<'a> for ParticleSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Projectile>,
+        WriteStorage<'a, ParticleEmitter>,
+        Read<'a, CombatEvents>,
+        Read<'a, CurrentMap>,
+        Read<'a, AssetIndex>,
+        Read<'a, GameTime>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, positions, projectiles, mut emitters, combat, current, index, time) = data;
+        let delta = time.delta_ms();
+        let mut rng = rand::thread_rng();
+
+        // The emitters of a map come and go with it
+        let mut map_started = false;
+        for (entity, emitter) in (&entities, &emitters).join() {
+            match &emitter.map {
+                Some(map) if Some(map) == current.0.as_ref() => map_started = true,
+                Some(_) => {
+                    let _ = entities.delete(entity);
+                }
+                None => {}
+            }
+        }
+        let map = current.0.as_ref().and_then(|id| index.map_data(id));
+        if let (false, Some(map)) = (map_started, map) {
+            for placed in &map.emitters {
+                let position = Point::new(placed.position[0], placed.position[1]);
+                let emitter = ParticleEmitter {
+                    map: current.0.clone(),
+                    ..ParticleEmitter::new(&placed.effect, position)
+                };
+                entities.build_entity().with(emitter, &mut emitters).build();
+            }
+        }
+
+        for event in &combat.0 {
+            let effect = match event.kind {
+                CombatKind::Damage => HIT_EMITTER,
+                CombatKind::Crit => CRIT_EMITTER,
+                CombatKind::Heal | CombatKind::Miss => continue,
+            };
+            entities
+                .build_entity()
+                .with(
+                    ParticleEmitter::attached_to(effect, event.entity),
+                    &mut emitters,
+                )
+                .build();
+        }
+
+        for (entity, emitter) in (&entities, &mut emitters).join() {
+            let data = match index.particles.get(&emitter.effect) {
+                Some(data) => data,
+                None => {
+                    let _ = entities.delete(entity);
+                    continue;
+                }
+            };
+            let anchor = match emitter.attached {
+                Some(attached) if entities.is_alive(attached) => projectiles
+                    .get(attached)
+                    .map(|projectile| projectile.position)
+                    .or_else(|| positions.get(attached).map(|position| position.0)),
+                Some(_) => None,
+                None => Some(emitter.position),
+            };
+            let running = data.duration == 0 || emitter.elapsed < data.duration;
+
+            if let (Some(anchor), true) = (anchor, running) {
+                let mut count = 0;
+                if emitter.elapsed == 0 {
+                    count += data.burst;
+                }
+                emitter.carry += data.rate * delta as f32 / 1000.0;
+                count += emitter.carry as u32;
+                emitter.carry = emitter.carry.fract();
+                for _ in 0..count {
+                    emitter.particles.push(spawn(data, anchor, &mut rng));
+                }
+            }
+            emitter.elapsed = emitter.elapsed.saturating_add(delta);
+
+            let seconds = delta as f32 / 1000.0;
+            for particle in emitter.particles.iter_mut() {
+                particle.age += delta;
+                particle.velocity.1 += data.gravity * seconds;
+                particle.position.0 += particle.velocity.0 * seconds;
+                particle.position.1 += particle.velocity.1 * seconds;
+            }
+            emitter
+                .particles
+                .retain(|particle| particle.age < particle.lifetime);
+
+            let spawning = anchor.is_some() && running;
+            if !spawning && emitter.particles.is_empty() {
+                let _ = entities.delete(entity);
+            }
+        }
+    }
+}
+
+fn spawn(data: &EmitterData, anchor: Point, rng: &mut impl Rng) -> Particle {
+    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+    let distance = data.radius * rng.gen::<f32>().sqrt();
+    let direction = (data.direction + data.spread * rng.gen_range(-1.0..=1.0)).to_radians();
+    let speed = between(data.speed[0], data.speed[1], rng.gen());
+    let lifetime = if data.lifetime[0] < data.lifetime[1] {
+        rng.gen_range(data.lifetime[0]..=data.lifetime[1])
+    } else {
+        data.lifetime[0]
+    };
+    Particle {
+        position: (
+            anchor.x() as f32 + angle.cos() * distance,
+            anchor.y() as f32 + angle.sin() * distance,
+        ),
+        velocity: (direction.cos() * speed, direction.sin() * speed),
+        age: 0,
+        lifetime: lifetime.max(1),
+    }
+}
+
+fn between(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+/// Color of a particle `t` of the way through its life
+fn color_at(colors: &[[u8; 4]], t: f32) -> Color {
+    let last = match colors.len() {
+        0 => return Color::WHITE,
+        len => len - 1,
+    };
+    let position = t.clamp(0.0, 1.0) * last as f32;
+    let from = colors[(position as usize).min(last)];
+    let to = colors[(position as usize + 1).min(last)];
+    let t = position.fract();
+    let channel = |i: usize| between(from[i] as f32, to[i] as f32, t).round() as u8;
+    Color::RGBA(channel(0), channel(1), channel(2), channel(3))
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (ReadStorage<'a, ParticleEmitter>, Read<'a, AssetIndex>);
+
+/// Queues every particle as a square on the effect layer, in front of the characters
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let (emitters, index) = data;
+    let screen_size = queue.size();
+
+    for emitter in emitters.join() {
+        let data = match index.particles.get(&emitter.effect) {
+            Some(data) => data,
+            None => continue,
+        };
+        for particle in &emitter.particles {
+            let t = particle.age as f32 / particle.lifetime as f32;
+            let color = color_at(&data.colors, t);
+            let size = between(data.size[0], data.size[1], t).round().max(1.0) as u32;
+            let position = ui::world_to_screen(
+                Point::new(
+                    particle.position.0.round() as i32,
+                    particle.position.1.round() as i32,
+                ),
+                screen_size,
+            );
+            let rect = Rect::from_center(position, size, size);
+            if data.additive {
+                queue.glow_rect(Layer::Effects, position.y(), color, rect);
+            } else {
+                queue.fill_rect(Layer::Effects, position.y(), color, rect);
+            }
+        }
+    }
+}
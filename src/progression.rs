@@ -0,0 +1,181 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::{
+    chat::ChatLog,
+    components::*,
+    render::{Layer, RenderQueue, TextMetrics},
+    time::GameTime,
+    ui, ServerUpdate,
+};
+
+/// Particle emitter started on characters that reach a new level
+const LEVEL_UP_EMITTER: &str = "level_up";
+/// Milliseconds the level up banner stays on screen
+const BANNER_LIFETIME: u32 = 3000;
+const BANNER_SCALE: f32 = 2.0;
+const BANNER_COLOR: Color = Color::RGB(255, 220, 90);
+const EXPERIENCE_BAR_HEIGHT: u32 = 22;
+const EXPERIENCE_COLOR: Color = Color::RGB(150, 60, 200);
+
+/// The experience bar along the top of the bottom panel
+pub struct ExperienceBar(pub Entity);
+
+/// Creates the experience bar, hidden until the server sends the progress of the player
+pub fn create_experience_bar(world: &mut World) -> ExperienceBar {
+    let bar = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Bar {
+                    value: 0,
+                    max: 0,
+                    color: Some(EXPERIENCE_COLOR),
+                },
+                (0, EXPERIENCE_BAR_HEIGHT),
+            )
+            .anchored(
+                Anchor::Bottom,
+                Point::new(0, -(ui::BOTTOM_PANEL_HEIGHT as i32)),
+            )
+            .hidden(),
+        )
+        .build();
+    ExperienceBar(bar)
+}
+
+/// The level the player just reached, shown in the middle of the screen
+#[derive(Default)]
+pub struct LevelUpBanner {
+    /// `None` while no banner is shown
+    pub level: Option<u32>,
+    /// Milliseconds the banner has been shown
+    pub age: u32,
+}
+
+/// Applies the progress sent by the server, celebrates new levels and fills the experience
+/// bar of the player
+pub struct ProgressionSystem;
+
+impl<'a> System<'a> for ProgressionSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, CharacterStats>,
+        WriteStorage<'a, ParticleEmitter>,
+        Write<'a, LevelUpBanner>,
+        Write<'a, ChatLog>,
+        Read<'a, GameTime>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        ReadExpect<'a, ExperienceBar>,
+        WriteStorage<'a, UiComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            players,
+            mut progress,
+            mut emitters,
+            mut banner,
+            mut log,
+            time,
+            server_updates,
+            bar,
+            mut nodes,
+        ) = data;
+
+        if banner.level.is_some() {
+            banner.age += time.delta_ms();
+            if banner.age >= BANNER_LIFETIME {
+                banner.level = None;
+            }
+        }
+
+        for server_update in server_updates.iter() {
+            if let ServerUpdate::Progress(update) = server_update {
+                let character = (&entities, &players)
+                    .join()
+                    .find(|(_, player)| player.id == update.id);
+                if let Some((entity, player)) = character {
+                    let local = player.is_local();
+                    let previous = progress.get(entity).cloned();
+                    let _ = progress.insert(entity, update.progress.clone());
+                    // The first update only tells where the character stands
+                    match previous {
+                        Some(previous) if update.progress.level > previous.level => {
+                            entities
+                                .build_entity()
+                                .with(
+                                    ParticleEmitter::attached_to(LEVEL_UP_EMITTER, entity),
+                                    &mut emitters,
+                                )
+                                .build();
+                            if local {
+                                let level = update.progress.level;
+                                log.system(&format!("You have reached level {}", level));
+                                banner.level = Some(level);
+                                banner.age = 0;
+                            }
+                        }
+                        Some(previous)
+                            if local
+                                && update.progress.level == previous.level
+                                && update.progress.experience > previous.experience =>
+                        {
+                            let gained = update.progress.experience - previous.experience;
+                            log.system(&format!("You gain {} experience", gained));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let player = (&players, &progress)
+            .join()
+            .find(|(player, _)| player.is_local())
+            .map(|(_, progress)| progress);
+        if let Some(node) = nodes.get_mut(bar.0) {
+            // Nothing is left to earn at the highest level
+            node.visible = player.is_some_and(|progress| progress.next_level > 0);
+            if let (Widget::Bar { value, max, .. }, Some(progress)) = (&mut node.widget, player) {
+                *value = progress.experience;
+                *max = progress.next_level;
+            }
+        }
+    }
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (Read<'a, LevelUpBanner>, Read<'a, GameTime>);
+
+/// Queues the level up banner
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (banner, time) = data;
+    let level = match banner.level {
+        Some(level) => level,
+        None => return Ok(()),
+    };
+    let (width, height) = queue.size();
+
+    // Fade out over the last third of the lifetime
+    let age = banner.age as f32 + time.alpha * time.delta_ms() as f32;
+    let remaining = (BANNER_LIFETIME as f32 - age).max(0.0) / (BANNER_LIFETIME / 3) as f32;
+    let color = Color::RGBA(
+        BANNER_COLOR.r,
+        BANNER_COLOR.g,
+        BANNER_COLOR.b,
+        (remaining.min(1.0) * 255.0) as u8,
+    );
+    let text = format!("Level {}!", level);
+    let center = Point::new(width as i32 / 2, height as i32 / 4);
+    let target = ui::scaled_text_rect(font, &text, center, BANNER_SCALE)?;
+    queue.text(Layer::Overlays, 0, &text, color, target);
+    Ok(())
+}
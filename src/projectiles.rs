@@ -0,0 +1,246 @@
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::{
+    abilities,
+    assets::{AssetIndex, SpellVisual},
+    components::*,
+    render::{Layer, RenderQueue},
+    time::GameTime,
+    ui, ServerUpdate,
+};
+
+fn spell_visual<'a>(index: &'a AssetIndex, ability: &str) -> Option<&'a SpellVisual> {
+    index.abilities.get(ability)?.visual.as_ref()
+}
+
+/// Spawns projectiles, impact animations and their particles for finished casts and
+/// moves projectiles towards their targets. Projectiles are only visuals, the damage comes
+/// with the server's hit. A projectile lands when it arrives, or right away when the server
+/// reports the hit of its ability first, so the impact matches the server's timing.
+pub struct ProjectileSystem;
+
+impl<'a> System<'a> for ProjectileSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Projectile>,
+        WriteStorage<'a, SpellEffect>,
+        WriteStorage<'a, ParticleEmitter>,
+        Read<'a, CastEvents>,
+        Read<'a, AssetIndex>,
+        Read<'a, GameTime>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            players,
+            positions,
+            mut projectiles,
+            mut spell_effects,
+            mut emitters,
+            casts,
+            index,
+            time,
+            server_updates,
+        ) = data;
+        let delta = time.delta_ms();
+
+        for effect in (&mut spell_effects).join() {
+            effect.elapsed += delta;
+        }
+        for (entity, effect) in (&entities, &spell_effects).join() {
+            let finished = index
+                .spritesheets
+                .get(&effect.spritesheet)
+                .and_then(|sheet| sheet.animations.get(&effect.clip))
+                .is_none_or(|clip| clip.finished(effect.elapsed));
+            if finished || !entities.is_alive(effect.target) {
+                let _ = entities.delete(entity);
+            }
+        }
+
+        let mut impacts = Vec::new();
+        for cast in &casts.0 {
+            let visual = match spell_visual(&index, &cast.ability) {
+                Some(visual) => visual,
+                None => continue,
+            };
+            let flies = visual.projectile.is_some()
+                && cast.target.is_some_and(|target| target != cast.caster);
+            let start = positions.get(cast.caster).map(|position| position.0);
+            match (flies, start) {
+                (true, Some(position)) => {
+                    let projectile = entities
+                        .build_entity()
+                        .with(
+                            Projectile {
+                                cast: cast.clone(),
+                                position,
+                                elapsed: 0,
+                            },
+                            &mut projectiles,
+                        )
+                        .build();
+                    if let Some(trail) = &visual.trail {
+                        entities
+                            .build_entity()
+                            .with(
+                                ParticleEmitter::attached_to(trail, projectile),
+                                &mut emitters,
+                            )
+                            .build();
+                    }
+                }
+                _ => impacts.push(cast.clone()),
+            }
+        }
+
+        // The server hit the target first. Each hit lands the oldest projectile of the same
+        // ability flying to the target that no other hit landed.
+        let mut confirmed = Vec::new();
+        for server_update in server_updates.iter() {
+            if let ServerUpdate::Combat(update) = server_update {
+                let target = (&entities, &players)
+                    .join()
+                    .find(|(_, player)| player.id == update.target)
+                    .map(|(entity, _)| entity);
+                let projectile = (&entities, &projectiles)
+                    .join()
+                    .filter(|(entity, projectile)| {
+                        target.is_some()
+                            && !confirmed.contains(entity)
+                            && projectile.cast.target == target
+                            && update.ability.as_ref() == Some(&projectile.cast.ability)
+                    })
+                    .max_by_key(|(_, projectile)| projectile.elapsed)
+                    .map(|(entity, _)| entity);
+                confirmed.extend(projectile);
+            }
+        }
+        for (entity, projectile) in (&entities, &mut projectiles).join() {
+            let target = match projectile.cast.target {
+                Some(target) if entities.is_alive(target) => target,
+                _ => {
+                    let _ = entities.delete(entity);
+                    continue;
+                }
+            };
+            projectile.elapsed += delta;
+            if confirmed.contains(&entity) {
+                impacts.push(projectile.cast.clone());
+                let _ = entities.delete(entity);
+                continue;
+            }
+
+            let destination = match positions.get(target) {
+                Some(position) => position.0,
+                None => continue,
+            };
+            let speed =
+                spell_visual(&index, &projectile.cast.ability).map_or(0, |visual| visual.speed);
+            let step = speed as f32 * delta as f32 / 1000.0;
+            let distance = abilities::distance(projectile.position, destination);
+            if distance <= step {
+                impacts.push(projectile.cast.clone());
+                let _ = entities.delete(entity);
+            } else {
+                let offset = destination - projectile.position;
+                projectile.position += Point::new(
+                    (offset.x() as f32 * step / distance).round() as i32,
+                    (offset.y() as f32 * step / distance).round() as i32,
+                );
+            }
+        }
+
+        for cast in impacts {
+            let visual = match spell_visual(&index, &cast.ability) {
+                Some(visual) => visual,
+                None => continue,
+            };
+            let target = cast.target.unwrap_or(cast.caster);
+            if let Some(clip) = &visual.impact {
+                entities
+                    .build_entity()
+                    .with(
+                        SpellEffect {
+                            spritesheet: visual.spritesheet.clone(),
+                            clip: clip.clone(),
+                            target,
+                            elapsed: 0,
+                        },
+                        &mut spell_effects,
+                    )
+                    .build();
+            }
+            if let Some(burst) = &visual.burst {
+                entities
+                    .build_entity()
+                    .with(ParticleEmitter::attached_to(burst, target), &mut emitters)
+                    .build();
+            }
+        }
+    }
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    ReadStorage<'a, Projectile>,
+    ReadStorage<'a, SpellEffect>,
+    ReadStorage<'a, Position>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the projectiles in flight and the impact animations on the effect layer
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let (projectiles, spell_effects, positions, index) = data;
+    let size = queue.size();
+
+    let mut queue_clip = |spritesheet: &str, clip: &str, elapsed: u32, position: Point| {
+        let frame = index
+            .spritesheets
+            .get(spritesheet)
+            .and_then(|sheet| sheet.animations.get(clip))
+            .map_or(0, |data| data.frame_at(elapsed));
+        let sprite = match index.sprite(spritesheet, clip, frame) {
+            Some(sprite) => sprite,
+            None => return,
+        };
+        let screen_position = ui::world_to_screen(position, size);
+        let target = ui::sprite_rect(&sprite, screen_position);
+        queue.copy(
+            Layer::Effects,
+            screen_position.y(),
+            sprite.spritesheet,
+            sprite.region,
+            target,
+        );
+    };
+
+    for projectile in projectiles.join() {
+        let visual = spell_visual(&index, &projectile.cast.ability);
+        if let Some((visual, clip)) =
+            visual.and_then(|visual| Some((visual, visual.projectile.as_ref()?)))
+        {
+            queue_clip(
+                &visual.spritesheet,
+                clip,
+                projectile.elapsed,
+                projectile.position,
+            );
+        }
+    }
+    for effect in spell_effects.join() {
+        if let Some(position) = positions.get(effect.target) {
+            queue_clip(
+                &effect.spritesheet,
+                &effect.clip,
+                effect.elapsed,
+                position.0,
+            );
+        }
+    }
+}
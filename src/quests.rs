@@ -0,0 +1,434 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::{
+    assets::{AssetIndex, QuestData},
+    chat::ChatLog,
+    components::*,
+    render::{Layer, RenderQueue, TextMetrics},
+    ui, ServerUpdate,
+};
+
+/// Action of the list of quests in the quest log window
+pub const QUEST_LOG_ACTION: &str = "quest_log";
+/// Action of the button that abandons the selected quest
+pub const QUEST_ABANDON_ACTION: &str = "quest_abandon";
+
+const WINDOW_WIDTH: u32 = 360;
+const WINDOW_PADDING: i32 = 14;
+const SPACING: i32 = 6;
+const TITLE_HEIGHT: u32 = 20;
+/// Room for five quests in the list
+const LIST_HEIGHT: u32 = 90;
+const DESCRIPTION_HEIGHT: u32 = 106;
+const OBJECTIVES_HEIGHT: u32 = 84;
+const BUTTON_HEIGHT: u32 = 26;
+const TRACKER_WIDTH: u32 = 240;
+const TRACKER_HEIGHT: u32 = 300;
+/// Quests listed in the tracker at most, the oldest first
+const MAX_TRACKED: usize = 5;
+const OBJECTIVE_INDENT: i32 = 10;
+const MARKER_SCALE: f32 = 2.0;
+/// Gap between the head of a quest giver and its marker
+const MARKER_MARGIN: i32 = 14;
+
+const QUEST_TITLE_COLOR: Color = Color::RGB(255, 210, 60);
+const OBJECTIVE_COLOR: Color = Color::RGB(200, 200, 200);
+const DONE_COLOR: Color = Color::RGB(120, 120, 120);
+const UNFINISHED_MARKER_COLOR: Color = Color::RGB(160, 160, 160);
+
+/// The quest log window toggled with L
+pub struct QuestWindow {
+    pub panel: Entity,
+    pub list: Entity,
+    pub title: Entity,
+    pub description: Entity,
+    /// Where the objectives of the selected quest are listed
+    pub objectives: Entity,
+    pub abandon: Entity,
+}
+
+/// Where the quests in progress are listed, under the minimap
+pub struct QuestTracker(pub Entity);
+
+/// Creates the hidden quest log window
+pub fn create_quest_window(world: &mut World) -> QuestWindow {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let spacing = SPACING as u32;
+    let height = 2 * WINDOW_PADDING as u32
+        + 2 * TITLE_HEIGHT
+        + LIST_HEIGHT
+        + DESCRIPTION_HEIGHT
+        + OBJECTIVES_HEIGHT
+        + BUTTON_HEIGHT
+        + 5 * spacing;
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (WINDOW_WIDTH, height),
+            )
+            .anchored(Anchor::Center, Point::new(-120, -40))
+            .with_layout(Layout::Vertical, WINDOW_PADDING, SPACING)
+            .hidden(),
+        )
+        .build();
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: "Quest Log".to_string(),
+                    color: Color::WHITE,
+                },
+                (0, TITLE_HEIGHT),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    let list = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::List {
+                    items: Vec::new(),
+                    selected: None,
+                    action: QUEST_LOG_ACTION.to_string(),
+                },
+                (0, LIST_HEIGHT),
+            )
+            .with_parent(panel, 1),
+        )
+        .build();
+    let title = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: String::new(),
+                    color: QUEST_TITLE_COLOR,
+                },
+                (0, TITLE_HEIGHT),
+            )
+            .with_parent(panel, 2),
+        )
+        .build();
+    let description = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Paragraph {
+                    text: String::new(),
+                    color: OBJECTIVE_COLOR,
+                    revealed: 0,
+                },
+                (0, DESCRIPTION_HEIGHT),
+            )
+            .with_parent(panel, 3),
+        )
+        .build();
+    let objectives = world
+        .create_entity()
+        .with(UiComponent::new(Widget::Container, (0, OBJECTIVES_HEIGHT)).with_parent(panel, 4))
+        .build();
+    let abandon = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Button {
+                    label: "Abandon".to_string(),
+                    action: QUEST_ABANDON_ACTION.to_string(),
+                },
+                (0, BUTTON_HEIGHT),
+            )
+            .with_parent(panel, 5),
+        )
+        .build();
+
+    QuestWindow {
+        panel,
+        list,
+        title,
+        description,
+        objectives,
+        abandon,
+    }
+}
+
+/// Creates the quest tracker on the right of the screen
+pub fn create_quest_tracker(world: &mut World) -> QuestTracker {
+    let tracker = world
+        .create_entity()
+        .with(
+            UiComponent::new(Widget::Container, (TRACKER_WIDTH, TRACKER_HEIGHT))
+                .anchored(Anchor::TopRight, Point::new(-10, 200)),
+        )
+        .build();
+    QuestTracker(tracker)
+}
+
+fn title<'a>(index: &'a AssetIndex, id: &'a str) -> &'a str {
+    index
+        .quests
+        .get(id)
+        .map_or(id, |quest| quest.title.as_str())
+}
+
+/// Applies the quest states sent by the server to the quest log, tells the player about
+/// their progress in the chat and keeps the quest log window in sync
+pub struct QuestSystem;
+
+impl<'a> System<'a> for QuestSystem {
+    type SystemData = (
+        Write<'a, QuestLog>,
+        Write<'a, ChatLog>,
+        ReadExpect<'a, Vec<ServerUpdate>>,
+        Read<'a, AssetIndex>,
+        ReadExpect<'a, QuestWindow>,
+        WriteStorage<'a, UiComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut log, mut chat, server_updates, index, window, mut nodes) = data;
+
+        for server_update in server_updates.iter() {
+            match server_update {
+                ServerUpdate::Quest(QuestUpdate::State(quest)) => {
+                    let previous = log.get(&quest.id).cloned();
+                    announce(previous.as_ref(), quest, &index, &mut chat);
+                    match log.quests.iter_mut().find(|known| known.id == quest.id) {
+                        Some(known) => *known = quest.clone(),
+                        None => log.quests.push(quest.clone()),
+                    }
+                }
+                ServerUpdate::Quest(QuestUpdate::Remove { id }) => {
+                    log.quests.retain(|quest| &quest.id != id);
+                }
+                _ => {}
+            }
+        }
+
+        let selected_accepted = log
+            .selected
+            .as_ref()
+            .is_some_and(|id| log.accepted().any(|quest| &quest.id == id));
+        if !selected_accepted {
+            let first = log.accepted().next().map(|quest| quest.id.clone());
+            log.selected = first;
+        }
+
+        let selected = log.selected.as_ref().and_then(|id| log.get(id));
+        if let Some(Widget::List {
+            items,
+            selected: row,
+            ..
+        }) = nodes.get_mut(window.list).map(|node| &mut node.widget)
+        {
+            *items = log
+                .accepted()
+                .map(|quest| match quest.state {
+                    QuestState::Complete => format!("{} (Complete)", title(&index, &quest.id)),
+                    _ => title(&index, &quest.id).to_string(),
+                })
+                .collect();
+            *row = selected
+                .and_then(|selected| log.accepted().position(|quest| quest.id == selected.id));
+        }
+        if let Some(Widget::Label { text, .. }) =
+            nodes.get_mut(window.title).map(|node| &mut node.widget)
+        {
+            *text = selected.map_or_else(String::new, |quest| title(&index, &quest.id).to_string());
+        }
+        if let Some(Widget::Paragraph { text, revealed, .. }) = nodes
+            .get_mut(window.description)
+            .map(|node| &mut node.widget)
+        {
+            *text = selected
+                .and_then(|quest| index.quests.get(&quest.id))
+                .map_or_else(String::new, |quest| quest.description.clone());
+            *revealed = text.chars().count();
+        }
+        if let Some(node) = nodes.get_mut(window.abandon) {
+            node.visible = selected.is_some();
+        }
+    }
+}
+
+/// Chat lines for taking a quest, finishing it and every step of its objectives
+fn announce(previous: Option<&Quest>, quest: &Quest, index: &AssetIndex, chat: &mut ChatLog) {
+    let title = title(index, &quest.id);
+    let accepted = previous.is_some_and(|previous| previous.state != QuestState::Available);
+    if quest.state == QuestState::Available {
+        return;
+    }
+    if !accepted {
+        chat.system(&format!("Quest accepted: {}", title));
+    }
+    if let Some(data) = index.quests.get(&quest.id) {
+        for (objective, (done, data)) in quest.progress.iter().zip(&data.objectives).enumerate() {
+            let before = previous
+                .and_then(|previous| previous.progress.get(objective))
+                .copied()
+                .unwrap_or(0);
+            if accepted && *done > before {
+                chat.system(&format!("{}: {}/{}", data.text, done, data.count));
+            }
+        }
+    }
+    let was_complete = previous.is_some_and(|previous| previous.state == QuestState::Complete);
+    if quest.state == QuestState::Complete && !was_complete {
+        chat.system(&format!("{} completed", title));
+    }
+}
+
+/// Shows the quest at `row` of the quest log list
+pub fn select(row: usize, log: &mut QuestLog) {
+    let quest = log.accepted().nth(row).map(|quest| quest.id.clone());
+    if quest.is_some() {
+        log.selected = quest;
+    }
+}
+
+/// The request for dropping the quest shown in the quest log
+pub fn abandon(log: &QuestLog) -> Option<QuestRequest> {
+    let id = log.selected.as_ref()?;
+    log.accepted()
+        .any(|quest| &quest.id == id)
+        .then(|| QuestRequest::Abandon(id.clone()))
+}
+
+/// Lines listing the objectives of a quest with their progress
+fn objective_lines(quest: &Quest, data: &QuestData) -> Vec<(String, Color)> {
+    data.objectives
+        .iter()
+        .enumerate()
+        .map(|(objective, data)| {
+            let done = quest.progress.get(objective).copied().unwrap_or(0);
+            let color = if done >= data.count || quest.state == QuestState::Complete {
+                DONE_COLOR
+            } else {
+                OBJECTIVE_COLOR
+            };
+            if data.count > 1 {
+                (
+                    format!("{}: {}/{}", data.text, done.min(data.count), data.count),
+                    color,
+                )
+            } else {
+                (data.text.clone(), color)
+            }
+        })
+        .collect()
+}
+
+// Type alias for the data needed by the renderer
+pub type SystemData<'a> = (
+    ReadStorage<'a, UiComponent>,
+    Read<'a, QuestLog>,
+    Option<ReadExpect<'a, QuestWindow>>,
+    Option<ReadExpect<'a, QuestTracker>>,
+    ReadStorage<'a, Player>,
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Sprite>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the objectives in the quest log window, the quest tracker and the markers over
+/// quest givers
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (nodes, log, window, tracker, players, positions, sprites, index) = data;
+    let line_height = font.line_height();
+
+    let objectives = window.and_then(|window| nodes.get(window.objectives));
+    let selected = log
+        .selected
+        .as_ref()
+        .and_then(|id| log.get(id))
+        .and_then(|quest| Some((quest, index.quests.get(&quest.id)?)));
+    if let (Some(node), Some((quest, data))) = (objectives.filter(|node| node.shown), selected) {
+        for (line, (text, color)) in objective_lines(quest, data).iter().enumerate() {
+            let position = node.bounds.top_left().offset(0, line as i32 * line_height);
+            ui::draw_text(queue, node.depth as i32, font, text, *color, position)?;
+        }
+    }
+
+    let tracker = tracker.and_then(|tracker| nodes.get(tracker.0));
+    if let Some(node) = tracker.filter(|node| node.shown) {
+        let mut lines = Vec::new();
+        for quest in log.accepted().take(MAX_TRACKED) {
+            let title = title(&index, &quest.id).to_string();
+            lines.push((title, QUEST_TITLE_COLOR, 0));
+            // A finished quest only needs turning in
+            if quest.state == QuestState::Complete {
+                let text = "Ready to turn in".to_string();
+                lines.push((text, OBJECTIVE_COLOR, OBJECTIVE_INDENT));
+                continue;
+            }
+            if let Some(data) = index.quests.get(&quest.id) {
+                for (text, color) in objective_lines(quest, data) {
+                    lines.push((text, color, OBJECTIVE_INDENT));
+                }
+            }
+        }
+        let fits = (node.bounds.height() as i32 / line_height).max(0) as usize;
+        for (line, (text, color, indent)) in lines.iter().take(fits).enumerate() {
+            let position = node
+                .bounds
+                .top_left()
+                .offset(*indent, line as i32 * line_height);
+            ui::draw_text(queue, node.depth as i32, font, text, *color, position)?;
+        }
+    }
+
+    let size = queue.size();
+    for (player, position, sprite) in (&players, &positions, &sprites).join() {
+        let marker = match giver_state(&log, &index, &player.id) {
+            Some(QuestState::Complete) => ("?", QUEST_TITLE_COLOR),
+            Some(QuestState::Available) => ("!", QUEST_TITLE_COLOR),
+            Some(QuestState::Active) => ("?", UNFINISHED_MARKER_COLOR),
+            None => continue,
+        };
+        let screen_position = ui::world_to_screen(position.0, size);
+        let sprite_top = ui::sprite_rect(sprite, screen_position).top();
+        let center = Point::new(screen_position.x(), sprite_top - MARKER_MARGIN);
+        let target = ui::scaled_text_rect(font, marker.0, center, MARKER_SCALE)?;
+        queue.text(
+            Layer::Overlays,
+            screen_position.y(),
+            marker.0,
+            marker.1,
+            target,
+        );
+    }
+
+    Ok(())
+}
+
+/// What the marker over a character shows: a quest to turn in before a quest to take before
+/// a quest still in progress
+fn giver_state(log: &QuestLog, index: &AssetIndex, giver: &str) -> Option<QuestState> {
+    let states = log
+        .quests
+        .iter()
+        .filter(|quest| {
+            index
+                .quests
+                .get(&quest.id)
+                .is_some_and(|data| data.giver.as_deref() == Some(giver))
+        })
+        .map(|quest| quest.state);
+    states.min_by_key(|state| match state {
+        QuestState::Complete => 0,
+        QuestState::Available => 1,
+        QuestState::Active => 2,
+    })
+}
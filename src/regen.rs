@@ -0,0 +1,29 @@
+use specs::prelude::*;
+
+use crate::{components::*, time::GameTime};
+
+/// Regenerates the power of living characters once per second of game time, between
+/// the status updates of the server
+pub struct RegenSystem;
+
+impl<'a> System<'a> for RegenSystem {
+    type SystemData = (Read<'a, GameTime>, WriteStorage<'a, Status>);
+
+    fn run(&mut self, (time, mut statuses): Self::SystemData) {
+        // On every whole second since the client started, the status updates of the server
+        // correct whatever this is off by
+        let previous = time.total.saturating_sub(time.delta);
+        if previous.as_secs() == time.total.as_secs() {
+            return;
+        }
+
+        for status in (&mut statuses).join() {
+            if !status.alive {
+                continue;
+            }
+            if let Some(power) = &mut status.power {
+                power.add(power.regen);
+            }
+        }
+    }
+}
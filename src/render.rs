@@ -0,0 +1,222 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::ttf::Font;
+
+use crate::components::TextureHandle;
+
+/// Color a frame is cleared with before drawing
+pub const BACKGROUND: Color = Color::RGB(65, 64, 255);
+
+/// Parts of a frame, drawn from back to front
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    /// The map
+    Ground,
+    /// Things lying in the world that characters walk in front of
+    Objects,
+    /// Characters, ordered by how far down the screen they stand
+    Characters,
+    /// Combat text and other short-lived effects
+    Effects,
+    /// Health bars and other information attached to characters
+    Overlays,
+    /// Windows, frames and the chat
+    Ui,
+}
+
+#[derive(Debug, Clone)]
+pub enum DrawCommand {
+    Fill {
+        color: Color,
+        rect: Rect,
+    },
+    Outline {
+        color: Color,
+        rect: Rect,
+    },
+    /// A fill added onto what is below, for light, fire and other glowing things
+    Glow {
+        color: Color,
+        rect: Rect,
+    },
+    /// A part of a texture with every pixel multiplied by `tint`
+    Texture {
+        texture: TextureHandle,
+        source: Rect,
+        target: Rect,
+        tint: Color,
+    },
+    /// A line of text stretched over `target` and blended with the alpha of `color`
+    Text {
+        text: String,
+        color: Color,
+        target: Rect,
+    },
+}
+
+#[derive(Debug)]
+struct QueuedDraw {
+    layer: Layer,
+    z: i32,
+    command: DrawCommand,
+}
+
+/// Collects the draw calls of a frame from every draw module and draws them sorted by
+/// layer, then by z within a layer. Calls with the same layer and z are drawn in the
+/// order they were queued.
+#[derive(Debug, Default)]
+pub struct RenderQueue {
+    size: (u32, u32),
+    draws: Vec<QueuedDraw>,
+}
+
+impl RenderQueue {
+    /// Starts a frame of the given output size
+    pub fn begin(&mut self, size: (u32, u32)) {
+        self.size = size;
+        self.draws.clear();
+    }
+
+    /// Size of the output the frame is drawn to
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn push(&mut self, layer: Layer, z: i32, command: DrawCommand) {
+        self.draws.push(QueuedDraw { layer, z, command });
+    }
+
+    pub fn fill_rect(&mut self, layer: Layer, z: i32, color: Color, rect: Rect) {
+        self.push(layer, z, DrawCommand::Fill { color, rect });
+    }
+
+    pub fn draw_rect(&mut self, layer: Layer, z: i32, color: Color, rect: Rect) {
+        self.push(layer, z, DrawCommand::Outline { color, rect });
+    }
+
+    pub fn glow_rect(&mut self, layer: Layer, z: i32, color: Color, rect: Rect) {
+        self.push(layer, z, DrawCommand::Glow { color, rect });
+    }
+
+    pub fn copy(
+        &mut self,
+        layer: Layer,
+        z: i32,
+        texture: TextureHandle,
+        source: Rect,
+        target: Rect,
+    ) {
+        self.copy_tinted(layer, z, texture, source, target, Color::WHITE);
+    }
+
+    pub fn copy_tinted(
+        &mut self,
+        layer: Layer,
+        z: i32,
+        texture: TextureHandle,
+        source: Rect,
+        target: Rect,
+        tint: Color,
+    ) {
+        self.push(
+            layer,
+            z,
+            DrawCommand::Texture {
+                texture,
+                source,
+                target,
+                tint,
+            },
+        );
+    }
+
+    pub fn text(&mut self, layer: Layer, z: i32, text: &str, color: Color, target: Rect) {
+        if text.is_empty() {
+            return;
+        }
+        self.push(
+            layer,
+            z,
+            DrawCommand::Text {
+                text: text.to_string(),
+                color,
+                target,
+            },
+        );
+    }
+
+    /// Draws everything queued this frame and empties the queue
+    pub fn flush(&mut self, renderer: &mut dyn Renderer) -> Result<(), String> {
+        // Stable, so draws with the same layer and z keep their order
+        self.draws.sort_by_key(|draw| (draw.layer, draw.z));
+
+        for draw in self.draws.drain(..) {
+            match draw.command {
+                DrawCommand::Fill { color, rect } => renderer.fill_rect(color, rect)?,
+                DrawCommand::Outline { color, rect } => renderer.draw_rect(color, rect)?,
+                DrawCommand::Glow { color, rect } => renderer.glow_rect(color, rect)?,
+                DrawCommand::Texture {
+                    texture,
+                    source,
+                    target,
+                    tint,
+                } => renderer.copy(texture, source, target, tint)?,
+                DrawCommand::Text {
+                    text,
+                    color,
+                    target,
+                } => renderer.draw_text(&text, color, target)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A backend frames are drawn with. Draw modules only queue draws, the queue is the only
+/// thing talking to a renderer.
+pub trait Renderer {
+    fn output_size(&self) -> Result<(u32, u32), String>;
+    fn clear(&mut self, color: Color);
+    /// Fills `rect`, blended with the alpha of `color`
+    fn fill_rect(&mut self, color: Color, rect: Rect) -> Result<(), String>;
+    fn draw_rect(&mut self, color: Color, rect: Rect) -> Result<(), String>;
+    /// Adds `color`, scaled by its alpha, to the colors in `rect`
+    fn glow_rect(&mut self, color: Color, rect: Rect) -> Result<(), String>;
+    /// Copies `source` of a texture stretched over `target`, multiplied by `tint`
+    fn copy(
+        &mut self,
+        texture: TextureHandle,
+        source: Rect,
+        target: Rect,
+        tint: Color,
+    ) -> Result<(), String>;
+    /// Draws a line of text in the UI font stretched over `target`, blended with the alpha
+    /// of `color`
+    fn draw_text(&mut self, text: &str, color: Color, target: Rect) -> Result<(), String>;
+    fn present(&mut self);
+}
+
+/// Measures text for layout. Implemented by the font of every backend.
+pub trait TextMetrics {
+    /// Width and height of a line of text
+    fn text_size(&self, text: &str) -> Result<(u32, u32), String>;
+    /// Distance between the tops of two lines
+    fn line_height(&self) -> i32;
+    /// Height of the tallest glyph
+    fn height(&self) -> i32;
+}
+
+impl TextMetrics for Font<'_, '_> {
+    fn text_size(&self, text: &str) -> Result<(u32, u32), String> {
+        self.size_of(text).map_err(|e| e.to_string())
+    }
+
+    fn line_height(&self) -> i32 {
+        self.recommended_line_spacing()
+    }
+
+    fn height(&self) -> i32 {
+        Font::height(self)
+    }
+}
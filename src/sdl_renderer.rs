@@ -0,0 +1,163 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::ttf::Font;
+use sdl2::video::WindowContext;
+
+use crate::assets::AssetManager;
+use crate::components::TextureHandle;
+use crate::render::Renderer;
+
+/// Draws to the window through an SDL canvas, with the textures of the asset manager
+pub struct SdlRenderer<'r, 'a> {
+    canvas: &'r mut WindowCanvas,
+    assets: &'r mut AssetManager<'a>,
+    /// Id of the font text is drawn with
+    font: &'r str,
+    text: &'r mut TextCache<'a>,
+}
+
+impl<'r, 'a> SdlRenderer<'r, 'a> {
+    pub fn new(
+        canvas: &'r mut WindowCanvas,
+        assets: &'r mut AssetManager<'a>,
+        font: &'r str,
+        text: &'r mut TextCache<'a>,
+    ) -> Self {
+        // Lets translucent fills darken or tint what is below them
+        canvas.set_blend_mode(BlendMode::Blend);
+        Self {
+            canvas,
+            assets,
+            font,
+            text,
+        }
+    }
+
+    /// The font text is drawn with, for measuring it
+    pub fn font(&self) -> Result<&Font<'a, 'static>, String> {
+        self.assets.font(self.font)
+    }
+}
+
+/// The textures of the text drawn in the last two frames, so text that stays on screen is
+/// rendered once instead of every frame. Keyed by the opaque color, the alpha is a mod.
+pub struct TextCache<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    drawn: HashMap<(String, Color), Texture<'a>>,
+    stale: HashMap<(String, Color), Texture<'a>>,
+}
+
+impl<'a> TextCache<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+        Self {
+            texture_creator,
+            drawn: HashMap::new(),
+            stale: HashMap::new(),
+        }
+    }
+
+    fn texture(
+        &mut self,
+        font: &Font<'_, 'static>,
+        text: &str,
+        color: Color,
+    ) -> Result<&mut Texture<'a>, String> {
+        match self.drawn.entry((text.to_string(), color)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let texture = match self.stale.remove(entry.key()) {
+                    Some(texture) => texture,
+                    None => {
+                        let surface = font
+                            .render(text)
+                            .blended(color)
+                            .map_err(|e| e.to_string())?;
+                        self.texture_creator
+                            .create_texture_from_surface(&surface)
+                            .map_err(|e| e.to_string())?
+                    }
+                };
+                Ok(entry.insert(texture))
+            }
+        }
+    }
+
+    /// Drops the textures of the text that wasn't drawn since the last call
+    fn end_frame(&mut self) {
+        self.stale = std::mem::take(&mut self.drawn);
+    }
+}
+
+impl Renderer for SdlRenderer<'_, '_> {
+    /// The logical size the canvas is scaled from, when it has one
+    fn output_size(&self) -> Result<(u32, u32), String> {
+        match self.canvas.logical_size() {
+            (0, 0) => self.canvas.output_size(),
+            size => Ok(size),
+        }
+    }
+
+    /// Clears the letterbox bars to black and the logical area to `color`
+    fn clear(&mut self, color: Color) {
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        if let Ok((width, height)) = self.output_size() {
+            self.canvas.set_draw_color(color);
+            let _ = self.canvas.fill_rect(Rect::new(0, 0, width, height));
+        }
+    }
+
+    fn fill_rect(&mut self, color: Color, rect: Rect) -> Result<(), String> {
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rect(rect)
+    }
+
+    fn draw_rect(&mut self, color: Color, rect: Rect) -> Result<(), String> {
+        self.canvas.set_draw_color(color);
+        self.canvas.draw_rect(rect)
+    }
+
+    fn glow_rect(&mut self, color: Color, rect: Rect) -> Result<(), String> {
+        self.canvas.set_blend_mode(BlendMode::Add);
+        self.canvas.set_draw_color(color);
+        let filled = self.canvas.fill_rect(rect);
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        filled
+    }
+
+    fn copy(
+        &mut self,
+        texture: TextureHandle,
+        source: Rect,
+        target: Rect,
+        tint: Color,
+    ) -> Result<(), String> {
+        let texture = self.assets.texture_mut(texture);
+        if tint == Color::WHITE {
+            return self.canvas.copy(texture, source, target);
+        }
+        // The texture is shared by every sprite cut from it, so the tint is reset right away
+        texture.set_color_mod(tint.r, tint.g, tint.b);
+        let copied = self.canvas.copy(texture, source, target);
+        texture.set_color_mod(255, 255, 255);
+        copied
+    }
+
+    fn draw_text(&mut self, text: &str, color: Color, target: Rect) -> Result<(), String> {
+        let font = self.assets.font(self.font)?;
+        let texture = self
+            .text
+            .texture(font, text, Color::RGB(color.r, color.g, color.b))?;
+        texture.set_alpha_mod(color.a);
+        self.canvas.copy(texture, None, target)
+    }
+
+    fn present(&mut self) {
+        self.canvas.present();
+        self.text.end_frame();
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use ab_glyph::{Font, FontVec, PxScale, PxScaleFont, ScaleFont};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::assets::{self, AssetIndex, MISSING_TEXTURE};
+use crate::components::TextureHandle;
+use crate::render::{Renderer, TextMetrics};
+
+/// An RGBA image in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Four bytes per pixel, row by row
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, color: Color) -> Self {
+        let pixels = [color.r, color.g, color.b, color.a].repeat((width * height) as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn load_png(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("opening {:?}: {}", path, e))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let size = reader
+            .output_buffer_size()
+            .ok_or_else(|| format!("{:?} is too large", path))?;
+        let mut buffer = vec![0; size];
+        let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+        let data = &buffer[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data.to_vec(),
+            png::ColorType::Rgb => data
+                .chunks(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks(2)
+                .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                .collect(),
+            png::ColorType::Grayscale => data
+                .iter()
+                .flat_map(|gray| [*gray, *gray, *gray, 255])
+                .collect(),
+            png::ColorType::Indexed => return Err(format!("{:?} has an unexpanded palette", path)),
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        }
+        let file = File::create(path).map_err(|e| format!("creating {:?}: {}", path, e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let index = ((y * self.width + x) * 4) as usize;
+        [
+            self.pixels[index],
+            self.pixels[index + 1],
+            self.pixels[index + 2],
+            self.pixels[index + 3],
+        ]
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    fn set(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        let index = ((y as u32 * self.width + x as u32) * 4) as usize;
+        self.pixels[index..index + 4].copy_from_slice(&color);
+    }
+
+    /// Alpha blends `color` over the pixel, like SDL's blend mode
+    fn blend(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        let index = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let alpha = color[3] as u32;
+        for (destination, source) in self.pixels[index..index + 3].iter_mut().zip(color) {
+            *destination =
+                ((source as u32 * alpha + *destination as u32 * (255 - alpha)) / 255) as u8;
+        }
+        let destination_alpha = self.pixels[index + 3] as u32;
+        self.pixels[index + 3] = (alpha + destination_alpha * (255 - alpha) / 255) as u8;
+    }
+
+    /// Adds `color` scaled by its alpha to the pixel, like SDL's additive blend mode
+    fn add(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        let index = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let alpha = color[3] as u32;
+        for (destination, source) in self.pixels[index..index + 3].iter_mut().zip(color) {
+            *destination = (*destination as u32 + source as u32 * alpha / 255).min(255) as u8;
+        }
+    }
+
+    /// Copies `source` stretched over `target` with nearest neighbour sampling, like SDL
+    /// does by default. Every pixel is multiplied by `tint` and then blended.
+    fn blit(&mut self, image: &Image, source: Rect, target: Rect, tint: Color) {
+        let source = match source.intersection(image.bounds()) {
+            Some(source) => source,
+            None => return,
+        };
+        let visible = match target.intersection(self.bounds()) {
+            Some(visible) => visible,
+            None => return,
+        };
+        for y in visible.top()..visible.bottom() {
+            let source_y = source.y()
+                + ((y - target.y()) as i64 * source.height() as i64 / target.height() as i64)
+                    as i32;
+            for x in visible.left()..visible.right() {
+                let source_x = source.x()
+                    + ((x - target.x()) as i64 * source.width() as i64 / target.width() as i64)
+                        as i32;
+                let [r, g, b, a] = image.pixel(source_x as u32, source_y as u32);
+                let alpha = (a as u32 * tint.a as u32 / 255) as u8;
+                if alpha > 0 {
+                    self.blend(
+                        x,
+                        y,
+                        [
+                            (r as u32 * tint.r as u32 / 255) as u8,
+                            (g as u32 * tint.g as u32 / 255) as u8,
+                            (b as u32 * tint.b as u32 / 255) as u8,
+                            alpha,
+                        ],
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// A TrueType font rasterized in software
+pub struct SoftwareFont {
+    font: FontVec,
+    scale: PxScale,
+}
+
+impl SoftwareFont {
+    /// Loads a font at a point size, matching the sizes given to SDL_ttf
+    pub fn load(path: &Path, size: u16) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("reading {:?}: {}", path, e))?;
+        let font = FontVec::try_from_vec(data).map_err(|e| e.to_string())?;
+        let scale = font
+            .pt_to_px_scale(size as f32)
+            .unwrap_or(PxScale::from(size as f32));
+        Ok(Self { font, scale })
+    }
+
+    fn scaled(&self) -> PxScaleFont<&FontVec> {
+        self.font.as_scaled(self.scale)
+    }
+
+    /// Renders a line of text in white, with the coverage of the glyphs as alpha
+    fn render(&self, text: &str) -> Result<Image, String> {
+        let (width, height) = self.text_size(text)?;
+        let mut image = Image::new(width.max(1), height.max(1), Color::RGBA(255, 255, 255, 0));
+        let font = self.scaled();
+        let mut caret = 0.0;
+        let mut previous = None;
+        for character in text.chars() {
+            let id = font.glyph_id(character);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            let glyph =
+                id.with_scale_and_position(self.scale, ab_glyph::point(caret, font.ascent()));
+            caret += font.h_advance(id);
+            previous = Some(id);
+
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|x, y, coverage| {
+                    let x = bounds.min.x as i32 + x as i32;
+                    let y = bounds.min.y as i32 + y as i32;
+                    if x >= 0 && y >= 0 && (x as u32) < image.width && (y as u32) < image.height {
+                        let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                        image.set(x, y, [255, 255, 255, alpha]);
+                    }
+                });
+            }
+        }
+        Ok(image)
+    }
+}
+
+impl TextMetrics for SoftwareFont {
+    fn text_size(&self, text: &str) -> Result<(u32, u32), String> {
+        let font = self.scaled();
+        let mut width = 0.0;
+        let mut previous = None;
+        for character in text.chars() {
+            let id = font.glyph_id(character);
+            if let Some(previous) = previous {
+                width += font.kern(previous, id);
+            }
+            width += font.h_advance(id);
+            previous = Some(id);
+        }
+        Ok((width.ceil() as u32, self.height() as u32))
+    }
+
+    fn line_height(&self) -> i32 {
+        let font = self.scaled();
+        (font.height() + font.line_gap()).ceil() as i32
+    }
+
+    fn height(&self) -> i32 {
+        self.scaled().height().ceil() as i32
+    }
+}
+
+/// Draws into an image in memory instead of a window, for running without a display
+pub struct SoftwareRenderer {
+    frame: Image,
+    textures: Vec<Image>,
+    font: SoftwareFont,
+}
+
+impl SoftwareRenderer {
+    /// Loads the textures and UI font of the assets directory `root`, along with an index
+    /// of everything in its manifest
+    pub fn load(root: &Path, width: u32, height: u32) -> Result<(Self, AssetIndex), String> {
+        let manifest = assets::read_manifest(root)?;
+        let mut index = assets::build_index(&manifest, root);
+
+        let mut texture_ids: Vec<(&String, TextureHandle)> = index
+            .textures
+            .iter()
+            .map(|(id, handle)| (id, *handle))
+            .collect();
+        texture_ids.sort_by_key(|(_, handle)| handle.0);
+        let mut textures = vec![Image::new(1, 1, Color::MAGENTA)];
+        let mut texture_sizes = Vec::new();
+        for (id, handle) in texture_ids {
+            let image = Image::load_png(&root.join(&manifest.textures[id]))?;
+            texture_sizes.push((handle, (image.width, image.height)));
+            textures.push(image);
+        }
+        index.texture_sizes.extend(texture_sizes);
+
+        let font = manifest
+            .fonts
+            .get("ui")
+            .ok_or_else(|| "the manifest has no ui font".to_string())?;
+        let font = SoftwareFont::load(&root.join(&font.path), font.size)?;
+
+        let renderer = Self {
+            frame: Image::new(width, height, Color::BLACK),
+            textures,
+            font,
+        };
+        Ok((renderer, index))
+    }
+
+    pub fn font(&self) -> &SoftwareFont {
+        &self.font
+    }
+
+    pub fn frame(&self) -> &Image {
+        &self.frame
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn output_size(&self) -> Result<(u32, u32), String> {
+        Ok((self.frame.width, self.frame.height))
+    }
+
+    fn clear(&mut self, color: Color) {
+        self.frame = Image::new(self.frame.width, self.frame.height, color);
+    }
+
+    fn fill_rect(&mut self, color: Color, rect: Rect) -> Result<(), String> {
+        if let Some(rect) = rect.intersection(self.frame.bounds()) {
+            for y in rect.top()..rect.bottom() {
+                for x in rect.left()..rect.right() {
+                    self.frame.blend(x, y, [color.r, color.g, color.b, color.a]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn glow_rect(&mut self, color: Color, rect: Rect) -> Result<(), String> {
+        if let Some(rect) = rect.intersection(self.frame.bounds()) {
+            for y in rect.top()..rect.bottom() {
+                for x in rect.left()..rect.right() {
+                    self.frame.add(x, y, [color.r, color.g, color.b, color.a]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, color: Color, rect: Rect) -> Result<(), String> {
+        let edges = [
+            Rect::new(rect.x(), rect.y(), rect.width(), 1),
+            Rect::new(rect.x(), rect.bottom() - 1, rect.width(), 1),
+            Rect::new(rect.x(), rect.y(), 1, rect.height()),
+            Rect::new(rect.right() - 1, rect.y(), 1, rect.height()),
+        ];
+        for edge in edges {
+            self.fill_rect(color, edge)?;
+        }
+        Ok(())
+    }
+
+    fn copy(
+        &mut self,
+        texture: TextureHandle,
+        source: Rect,
+        target: Rect,
+        tint: Color,
+    ) -> Result<(), String> {
+        let image = self
+            .textures
+            .get(texture.0)
+            .unwrap_or(&self.textures[MISSING_TEXTURE.0]);
+        self.frame.blit(image, source, target, tint);
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, color: Color, target: Rect) -> Result<(), String> {
+        let image = self.font.render(text)?;
+        self.frame.blit(&image, image.bounds(), target, color);
+        Ok(())
+    }
+
+    fn present(&mut self) {}
+}
@@ -1,37 +1,40 @@
-use log::debug;
-use sdl2::rect::{Point, Rect};
-use sdl2::render::{Texture, WindowCanvas};
+use sdl2::pixels::Color;
 use specs::prelude::*;
 
-use crate::{components::*, ServerUpdate};
+use crate::{
+    assets::AssetIndex,
+    components::*,
+    effects,
+    render::{Layer, RenderQueue},
+    ui,
+};
 
 // Type alias for the data needed by the renderer
 pub type SystemData<'a> = (
-    ReadExpect<'a, Option<ServerUpdate>>,
     ReadStorage<'a, Position>,
-    ReadStorage<'a, Player>,
-    WriteStorage<'a, Sprite>,
+    ReadStorage<'a, Sprite>,
+    ReadStorage<'a, Effects>,
+    Read<'a, AssetIndex>,
 );
 
-pub fn draw_to_canvas(
-    canvas: &mut WindowCanvas,
-    textures: &[Texture],
-    mut data: SystemData,
-) -> Result<(), String> {
-    let (width, height) = canvas.output_size()?;
+/// Queues every character on the character layer. Characters further down the screen
+/// stand in front of the ones above them, characters under an effect are tinted by it.
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let size = queue.size();
 
-    for (pos, player, sprite) in (&data.1, &data.2, &mut data.3).join() {
-        debug!("rendering: {:?}", player);
+    for (pos, sprite, effects) in (&data.0, &data.1, data.2.maybe()).join() {
         let current_frame = sprite.region;
         // Treat the center of the screen as the (0, 0) coordinate
-        let screen_position = player.pos + Point::new(width as i32 / 2, (height as i32 - 100) / 2);
-        let screen_rect = Rect::from_center(
-            screen_position,
-            current_frame.width() * 2,
-            current_frame.height() * 2,
+        let screen_position = ui::world_to_screen(pos.0, size);
+        let screen_rect = ui::sprite_rect(sprite, screen_position);
+        let tint = effects.map_or(Color::WHITE, |effects| effects::tint(effects, &data.3));
+        queue.copy_tinted(
+            Layer::Characters,
+            screen_position.y(),
+            sprite.spritesheet,
+            current_frame,
+            screen_rect,
+            tint,
         );
-        canvas.copy(&textures[sprite.spritesheet], current_frame, screen_rect)?;
     }
-
-    Ok(())
 }
@@ -1,29 +1,71 @@
 use sdl2::pixels::Color;
-use sdl2::rect::{Point, Rect};
-use sdl2::render::WindowCanvas;
+use sdl2::rect::Rect;
 use specs::prelude::*;
 
 use crate::components::*;
+use crate::render::{Layer, RenderQueue};
+use crate::ui;
+
+/// Height of the health bar drawn under characters
+const BAR_HEIGHT: u32 = 6;
+/// Gap between the feet of a character and its health bar
+const BAR_MARGIN: i32 = 4;
+
+/// Color of a health bar filled to `fraction`, going from green through yellow to red
+pub fn health_color(fraction: f32) -> Color {
+    let fraction = fraction.clamp(0.0, 1.0);
+    if fraction > 0.5 {
+        // Green to yellow
+        let red = ((1.0 - fraction) * 2.0 * 255.0) as u8;
+        Color::RGB(red, 200, 0)
+    } else {
+        // Yellow to red
+        let green = (fraction * 2.0 * 200.0) as u8;
+        Color::RGB(255, green, 0)
+    }
+}
 
 // Type alias for the data needed by the renderer
-pub type SystemData<'a> = (ReadStorage<'a, Position>, ReadStorage<'a, Status>);
+pub type SystemData<'a> = (
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Status>,
+    ReadStorage<'a, Sprite>,
+);
 
-pub fn draw_to_canvas(canvas: &mut WindowCanvas, data: SystemData) -> Result<(), String> {
-    let (width, height) = canvas.output_size()?;
+/// Queues a health bar under every character, as wide as the character is drawn. The bars
+/// go on the overlay layer so characters standing below never cover them.
+pub fn draw(queue: &mut RenderQueue, data: SystemData) {
+    let size = queue.size();
 
-    for (pos, status) in (&data.0, &data.1).join() {
-        let screen_position = pos.0 + Point::new(width as i32 / 2, (height as i32 - 100) / 2);
-        canvas.set_draw_color(Color::RGB(255, 100, 100));
-        canvas.fill_rect(Rect::from_center(screen_position.offset(0, 34), 304, 24))?;
-        canvas.set_draw_color(Color::GRAY);
-        canvas.fill_rect(Rect::from_center(screen_position.offset(0, 34), 300, 20))?;
-        canvas.set_draw_color(Color::GREEN);
-        canvas.fill_rect(Rect::from_center(
-            screen_position.offset(0, 34),
-            status.health * 3,
-            18,
-        ))?;
-    }
+    for (pos, status, sprite) in (&data.0, &data.1, &data.2).join() {
+        let screen_position = ui::world_to_screen(pos.0, size);
+        let sprite_rect = ui::sprite_rect(sprite, screen_position);
+        let bar_width = sprite_rect.width();
+        let bar_top = sprite_rect.bottom() + BAR_MARGIN;
+        let outline = Rect::new(
+            sprite_rect.left() - 1,
+            bar_top - 1,
+            bar_width + 2,
+            BAR_HEIGHT + 2,
+        );
+        let z = screen_position.y();
+        queue.fill_rect(Layer::Overlays, z, Color::BLACK, outline);
+        queue.fill_rect(
+            Layer::Overlays,
+            z,
+            Color::GRAY,
+            Rect::new(outline.x() + 1, bar_top, bar_width, BAR_HEIGHT),
+        );
 
-    Ok(())
+        let health = fraction(status.health, status.max_health);
+        let filled = (bar_width as f32 * health).round() as u32;
+        if filled > 0 {
+            queue.fill_rect(
+                Layer::Overlays,
+                z,
+                health_color(health),
+                Rect::new(outline.x() + 1, bar_top, filled, BAR_HEIGHT),
+            );
+        }
+    }
 }
@@ -0,0 +1,123 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+/// Length of one simulation step. The server expects a movement update per step.
+pub const TICK: Duration = Duration::from_millis(50);
+/// Steps simulated at most per frame, so a long stall doesn't make the client spend the
+/// next frames catching up
+const MAX_STEPS_PER_FRAME: u32 = 5;
+const DEFAULT_TARGET_FPS: u32 = 60;
+
+/// Simulation time, read by the systems instead of counting dispatches
+#[derive(Debug, Default)]
+pub struct GameTime {
+    /// Time simulated by the current step
+    pub delta: Duration,
+    /// Time simulated since the start
+    pub total: Duration,
+    /// Steps simulated since the start
+    pub ticks: u64,
+    /// How far rendering is between the last step and the next one, from 0 to 1
+    pub alpha: f32,
+}
+
+impl GameTime {
+    pub fn delta_ms(&self) -> u32 {
+        self.delta.as_millis() as u32
+    }
+}
+
+/// How often frames are drawn, read from `GAME_TARGET_FPS` and `GAME_VSYNC`
+#[derive(Debug, Clone, Copy)]
+pub struct FrameConfig {
+    /// Frames per second the loop sleeps down to, `None` draws as fast as possible
+    pub target_fps: Option<u32>,
+    /// Wait for the display to refresh before presenting a frame
+    pub vsync: bool,
+}
+
+impl FrameConfig {
+    /// `GAME_TARGET_FPS=0` turns the frame limiter off, `GAME_VSYNC=1` turns vsync on
+    pub fn from_env() -> Self {
+        let target_fps = match env::var("GAME_TARGET_FPS").map(|fps| fps.parse::<u32>()) {
+            Ok(Ok(0)) => None,
+            Ok(Ok(fps)) => Some(fps),
+            _ => Some(DEFAULT_TARGET_FPS),
+        };
+        let vsync = matches!(
+            env::var("GAME_VSYNC").as_deref(),
+            Ok("1") | Ok("true") | Ok("on")
+        );
+        Self { target_fps, vsync }
+    }
+
+    pub fn frame_time(&self) -> Option<Duration> {
+        self.target_fps
+            .map(|fps| Duration::from_secs(1) / fps.max(1))
+    }
+}
+
+/// Splits the time that passed between frames into fixed simulation steps
+pub struct FixedTimestep {
+    last: Instant,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn start() -> Self {
+        Self {
+            last: Instant::now(),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// Number of steps to simulate for the time passed since the last call
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+        self.accumulator += now - self.last;
+        self.last = now;
+
+        let mut steps = 0;
+        while self.accumulator >= TICK {
+            self.accumulator -= TICK;
+            steps += 1;
+        }
+        if steps > MAX_STEPS_PER_FRAME {
+            steps = MAX_STEPS_PER_FRAME;
+            self.accumulator = Duration::ZERO;
+        }
+        steps
+    }
+
+    /// Fraction of the next step that has already passed
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / TICK.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started_ago(millis: u64) -> FixedTimestep {
+        FixedTimestep {
+            last: Instant::now() - Duration::from_millis(millis),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn advance_keeps_the_rest_of_a_step() {
+        let mut timestep = started_ago(125);
+        assert_eq!(timestep.advance(), 2);
+        assert!(timestep.accumulator >= Duration::from_millis(25));
+        assert!(timestep.alpha() >= 0.5);
+    }
+
+    #[test]
+    fn advance_drops_time_after_a_long_frame() {
+        let mut timestep = started_ago(2000);
+        assert_eq!(timestep.advance(), MAX_STEPS_PER_FRAME);
+        assert_eq!(timestep.accumulator, Duration::ZERO);
+    }
+}
@@ -1,25 +1,1040 @@
+use std::collections::{HashMap, VecDeque};
+
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
+use sdl2::rect::{Point, Rect};
 use specs::prelude::*;
 
-use crate::{components::*, DIMENSION};
+use crate::{
+    assets::AssetIndex,
+    components::*,
+    map,
+    render::{Layer, RenderQueue, TextMetrics},
+    status::health_color,
+    viewport::Viewport,
+};
+
+/// Actions of the buttons of the game menu
+pub const MENU_RESUME_ACTION: &str = "menu_resume";
+pub const MENU_QUIT_ACTION: &str = "menu_quit";
+
+/// Height of the panel along the bottom of the window, the world is centred above it
+pub const BOTTOM_PANEL_HEIGHT: u32 = 100;
+const LIST_ITEM_HEIGHT: i32 = 18;
+const TEXT_PADDING: i32 = 4;
+/// Size of a cell of the icon atlases, icons are numbered row by row
+pub const ICON_CELL: u32 = 32;
+
+/// Colors of the slots of the action bar, the bags and the equipment
+pub const SLOT_BACKGROUND: Color = Color::RGB(20, 20, 28);
+pub const SLOT_BORDER: Color = Color::RGB(90, 90, 110);
+pub const HOVERED_BORDER: Color = Color::RGB(230, 210, 120);
+
+/// Where the world `position` is on a screen of `size`, the world's (0, 0) being the centre
+/// of the area above the bottom panel
+pub fn world_to_screen(position: Point, (width, height): (u32, u32)) -> Point {
+    position
+        + Point::new(
+            width as i32 / 2,
+            (height as i32 - BOTTOM_PANEL_HEIGHT as i32) / 2,
+        )
+}
+
+/// Where `sprite` is drawn with its origin at `screen_position`. Sprites are drawn at
+/// `map::SCALE` times their size like the tiles, so is the offset of their origin.
+pub fn sprite_rect(sprite: &Sprite, screen_position: Point) -> Rect {
+    let scale = map::SCALE as i32;
+    Rect::new(
+        screen_position.x() - sprite.origin.x() * scale,
+        screen_position.y() - sprite.origin.y() * scale,
+        sprite.region.width() * map::SCALE,
+        sprite.region.height() * map::SCALE,
+    )
+}
+
+/// Source rectangle of `icon` in the icon atlas `texture`
+pub fn icon_region(index: &AssetIndex, texture: &str, icon: u32) -> (TextureHandle, Rect) {
+    let texture = index.texture(texture);
+    let columns = (index.texture_size(texture).0 / ICON_CELL).max(1);
+    let region = Rect::new(
+        ((icon % columns) * ICON_CELL) as i32,
+        ((icon / columns) * ICON_CELL) as i32,
+        ICON_CELL,
+        ICON_CELL,
+    );
+    (texture, region)
+}
+
+/// The double line frame from border.png
+pub fn frame_style(index: &AssetIndex) -> NineSlice {
+    NineSlice {
+        texture: index.texture("border"),
+        source: Rect::new(59, 46, 702, 994),
+        inset: 15,
+        border: 6,
+    }
+}
+
+/// One of the bars from dialog.png, used as button background
+fn button_style(index: &AssetIndex, hovered: bool, pressed: bool) -> NineSlice {
+    let bar_top = match (hovered, pressed) {
+        (_, true) => 130,
+        (true, false) => 1125,
+        (false, false) => 1549,
+    };
+    NineSlice {
+        texture: index.texture("dialog"),
+        source: Rect::new(35, bar_top, 850, 91),
+        inset: 10,
+        border: 4,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UiEvent {
+    Clicked {
+        action: String,
+    },
+    Selected {
+        action: String,
+        index: usize,
+    },
+    /// A click on a widget that cares where it was clicked, `offset` is from its center
+    Pointed {
+        action: String,
+        offset: Point,
+    },
+    /// Something was dragged from one slot onto another
+    Moved {
+        action: String,
+        from: usize,
+        to: usize,
+    },
+    /// An item was dragged from a slot onto a slot of another kind, like from the bags
+    /// onto the equipment
+    Transferred {
+        from_action: String,
+        from: usize,
+        to_action: String,
+        to: usize,
+    },
+    /// An item was dragged out of its slot and let go outside of the UI. Only `confirmed`
+    /// when Shift was held, so a slipped drag doesn't throw the item away.
+    Discarded {
+        action: String,
+        slot: usize,
+        confirmed: bool,
+    },
+    /// The mouse wheel turned over a widget, positive is away from the user
+    Scrolled {
+        action: String,
+        amount: i32,
+    },
+}
+
+/// Interaction state of the UI and the events it produced since they were last drained
+#[derive(Default)]
+pub struct UiState {
+    pub hovered: Option<Entity>,
+    pub pressed: Option<Entity>,
+    /// Activated by Return and moved with the arrow keys, only the keyboard sets it
+    pub focused: Option<Entity>,
+    pub events: VecDeque<UiEvent>,
+    /// Where the mouse was last seen
+    pub pointer: (i32, i32),
+    /// Whether Shift was held at the last key event
+    pub shift: bool,
+}
+
+/// Computes the screen bounds of every UI element from its parent, anchor and size
+pub struct UiLayout;
+
+impl<'a> System<'a> for UiLayout {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, UiComponent>,
+        Write<'a, UiState>,
+        Read<'a, Viewport>,
+    );
+
+    fn run(&mut self, (entities, mut nodes, mut state, viewport): Self::SystemData) {
+        let mut children: HashMap<Option<Entity>, Vec<(i32, Entity)>> = HashMap::new();
+        for (entity, node) in (&entities, &nodes).join() {
+            children
+                .entry(node.parent)
+                .or_default()
+                .push((node.order, entity));
+        }
+        for siblings in children.values_mut() {
+            siblings.sort_by_key(|(order, _)| *order);
+        }
+
+        let mut depth = 0;
+        layout_children(
+            None,
+            viewport.rect(),
+            (Layout::Free, 0),
+            true,
+            &children,
+            &mut nodes,
+            &mut depth,
+        );
+
+        // Hidden elements can't keep the focus or be interacted with
+        let shown = |entity: &Option<Entity>| {
+            entity
+                .and_then(|entity| nodes.get(entity))
+                .map(|node| node.shown)
+                .unwrap_or(false)
+        };
+        if !shown(&state.focused) {
+            state.focused = None;
+        }
+        if !shown(&state.hovered) {
+            state.hovered = None;
+        }
+        if !shown(&state.pressed) {
+            state.pressed = None;
+        }
+    }
+}
+
+fn layout_children(
+    parent: Option<Entity>,
+    content: Rect,
+    (layout, spacing): (Layout, i32),
+    parent_shown: bool,
+    children: &HashMap<Option<Entity>, Vec<(i32, Entity)>>,
+    nodes: &mut WriteStorage<UiComponent>,
+    depth: &mut usize,
+) {
+    let mut cursor = 0;
+    for (_, entity) in children.get(&parent).into_iter().flatten() {
+        let node = match nodes.get_mut(*entity) {
+            Some(node) => node,
+            None => continue,
+        };
+        let width = if node.size.0 == 0 {
+            content.width()
+        } else {
+            node.size.0
+        };
+        let height = if node.size.1 == 0 {
+            content.height()
+        } else {
+            node.size.1
+        };
+        let area = match layout {
+            Layout::Free => content,
+            Layout::Vertical => {
+                Rect::new(content.x(), content.y() + cursor, content.width(), height)
+            }
+            Layout::Horizontal => {
+                Rect::new(content.x() + cursor, content.y(), width, content.height())
+            }
+        };
+        node.bounds = anchored_rect(area, node.anchor, node.offset, width, height);
+        node.shown = parent_shown && node.visible;
+        node.depth = *depth;
+        *depth += 1;
+        if node.visible {
+            match layout {
+                Layout::Free => {}
+                Layout::Vertical => cursor += height as i32 + spacing,
+                Layout::Horizontal => cursor += width as i32 + spacing,
+            }
+        }
+
+        let padding = node.padding;
+        let inner = Rect::new(
+            node.bounds.x() + padding,
+            node.bounds.y() + padding,
+            (node.bounds.width() as i32 - 2 * padding).max(1) as u32,
+            (node.bounds.height() as i32 - 2 * padding).max(1) as u32,
+        );
+        let child_layout = (node.layout, node.spacing);
+        let shown = node.shown;
+        layout_children(
+            Some(*entity),
+            inner,
+            child_layout,
+            shown,
+            children,
+            nodes,
+            depth,
+        );
+    }
+}
+
+fn anchored_rect(area: Rect, anchor: Anchor, offset: Point, width: u32, height: u32) -> Rect {
+    use self::Anchor::*;
+    let (w, h) = (width as i32, height as i32);
+    let x = match anchor {
+        TopLeft | Left | BottomLeft => area.left(),
+        Top | Center | Bottom => area.center().x() - w / 2,
+        TopRight | Right | BottomRight => area.right() - w,
+    };
+    let y = match anchor {
+        TopLeft | Top | TopRight => area.top(),
+        Left | Center | Right => area.center().y() - h / 2,
+        BottomLeft | Bottom | BottomRight => area.bottom() - h,
+    };
+    Rect::new(x + offset.x(), y + offset.y(), width, height)
+}
+
+/// Returns the topmost shown element under `point` that isn't see-through
+pub fn hit_test(
+    point: Point,
+    entities: &Entities,
+    nodes: &WriteStorage<UiComponent>,
+) -> Option<Entity> {
+    (entities, nodes)
+        .join()
+        .filter(|(_, node)| {
+            node.shown
+                && node.bounds.contains_point(point)
+                && !matches!(
+                    node.widget,
+                    Widget::Container
+                        | Widget::Label { .. }
+                        | Widget::Paragraph { .. }
+                        | Widget::Image { .. }
+                        | Widget::Bar { .. }
+                )
+        })
+        .max_by_key(|(_, node)| node.depth)
+        .map(|(entity, _)| entity)
+}
+
+pub type InputData<'a> = (
+    Entities<'a>,
+    WriteStorage<'a, UiComponent>,
+    Write<'a, UiState>,
+);
+
+/// Feeds a window event to the UI. Returns true when the UI consumed it and the game
+/// should ignore it.
+pub fn handle_event(event: &Event, data: InputData) -> bool {
+    let (entities, mut nodes, mut state) = data;
+    if let Event::KeyDown { keymod, .. } | Event::KeyUp { keymod, .. } = *event {
+        state.shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+    }
+    match *event {
+        Event::MouseMotion { x, y, .. } => {
+            state.pointer = (x, y);
+            state.hovered = hit_test(Point::new(x, y), &entities, &nodes);
+            false
+        }
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Left,
+            x,
+            y,
+            ..
+        } => {
+            // Clicking doesn't move the keyboard focus, so Return keeps going to the game
+            let hit = hit_test(Point::new(x, y), &entities, &nodes);
+            state.pressed = hit;
+            hit.is_some()
+        }
+        Event::MouseButtonUp {
+            mouse_btn: MouseButton::Left,
+            x,
+            y,
+            ..
+        } => {
+            let point = Point::new(x, y);
+            let hit = hit_test(point, &entities, &nodes);
+            match (state.pressed.take(), hit) {
+                (Some(pressed), Some(hit)) if pressed == hit => {
+                    activate(hit, Some(point), &mut nodes, &mut state)
+                }
+                (Some(pressed), Some(hit)) => drop_onto(pressed, hit, &nodes, &mut state),
+                (Some(pressed), None) => {
+                    if let Some(Widget::ItemSlot { slot, action }) =
+                        nodes.get(pressed).map(|node| &node.widget)
+                    {
+                        let confirmed = state.shift;
+                        state.events.push_back(UiEvent::Discarded {
+                            action: action.clone(),
+                            slot: *slot,
+                            confirmed,
+                        });
+                    }
+                }
+                (None, _) => {}
+            }
+            hit.is_some()
+        }
+        Event::MouseWheel { y, .. } => {
+            let hovered = state.hovered.and_then(|entity| nodes.get(entity));
+            match hovered {
+                Some(node) => match &node.widget {
+                    Widget::Minimap { action } => {
+                        state.events.push_back(UiEvent::Scrolled {
+                            action: action.clone(),
+                            amount: y,
+                        });
+                        true
+                    }
+                    _ => false,
+                },
+                None => false,
+            }
+        }
+        Event::KeyDown {
+            keycode: Some(Keycode::Return),
+            ..
+        } => match state.focused {
+            Some(focused) => {
+                activate(focused, None, &mut nodes, &mut state);
+                true
+            }
+            None => false,
+        },
+        Event::KeyDown {
+            keycode: Some(keycode @ (Keycode::Up | Keycode::Down)),
+            ..
+        } => match state.focused {
+            Some(focused) => {
+                let forward = keycode == Keycode::Down;
+                state.focused = Some(next_focus(focused, forward, &entities, &nodes));
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// The shown focusable sibling after `focused`, or before it when not `forward`, wrapping
+/// around at the ends
+fn next_focus(
+    focused: Entity,
+    forward: bool,
+    entities: &Entities,
+    nodes: &WriteStorage<UiComponent>,
+) -> Entity {
+    let parent = nodes.get(focused).and_then(|node| node.parent);
+    let mut siblings: Vec<(i32, Entity)> = (entities, nodes)
+        .join()
+        .filter(|(_, node)| node.parent == parent && node.shown && node.focusable)
+        .map(|(entity, node)| (node.order, entity))
+        .collect();
+    siblings.sort();
+    let position = match siblings.iter().position(|(_, entity)| *entity == focused) {
+        Some(position) => position,
+        None => return focused,
+    };
+    let next = if forward {
+        (position + 1) % siblings.len()
+    } else {
+        (position + siblings.len() - 1) % siblings.len()
+    };
+    siblings[next].1
+}
+
+/// Reports dragging a slot onto another slot of the same kind, or an item slot onto an
+/// item slot of another kind
+fn drop_onto(
+    dragged: Entity,
+    target: Entity,
+    nodes: &WriteStorage<UiComponent>,
+    state: &mut UiState,
+) {
+    let widgets = (
+        nodes.get(dragged).map(|node| &node.widget),
+        nodes.get(target).map(|node| &node.widget),
+    );
+    match widgets {
+        (
+            Some(Widget::ActionSlot { slot: from, action }),
+            Some(Widget::ActionSlot {
+                slot: to,
+                action: target_action,
+            }),
+        )
+        | (
+            Some(Widget::ItemSlot { slot: from, action }),
+            Some(Widget::ItemSlot {
+                slot: to,
+                action: target_action,
+            }),
+        ) if action == target_action => state.events.push_back(UiEvent::Moved {
+            action: action.clone(),
+            from: *from,
+            to: *to,
+        }),
+        (
+            Some(Widget::ItemSlot { slot: from, action }),
+            Some(Widget::ItemSlot {
+                slot: to,
+                action: target_action,
+            }),
+        ) => state.events.push_back(UiEvent::Transferred {
+            from_action: action.clone(),
+            from: *from,
+            to_action: target_action.clone(),
+            to: *to,
+        }),
+        _ => {}
+    }
+}
+
+/// Clicks a button or selects the list item at `point` (the current one without a point)
+fn activate(
+    entity: Entity,
+    point: Option<Point>,
+    nodes: &mut WriteStorage<UiComponent>,
+    state: &mut UiState,
+) {
+    let node = match nodes.get_mut(entity) {
+        Some(node) => node,
+        None => return,
+    };
+    let bounds = node.bounds;
+    match &mut node.widget {
+        Widget::Button { action, .. } => state.events.push_back(UiEvent::Clicked {
+            action: action.clone(),
+        }),
+        Widget::List {
+            items,
+            selected,
+            action,
+        } => {
+            let index = match point {
+                Some(point) => ((point.y() - bounds.y()) / LIST_ITEM_HEIGHT) as usize,
+                None => selected.unwrap_or(0),
+            };
+            if index < items.len() {
+                *selected = Some(index);
+                state.events.push_back(UiEvent::Selected {
+                    action: action.clone(),
+                    index,
+                });
+            }
+        }
+        Widget::ActionSlot { slot, action } | Widget::ItemSlot { slot, action } => {
+            state.events.push_back(UiEvent::Selected {
+                action: action.clone(),
+                index: *slot,
+            })
+        }
+        Widget::Minimap { action } => {
+            if let Some(point) = point {
+                state.events.push_back(UiEvent::Pointed {
+                    action: action.clone(),
+                    offset: point - bounds.center(),
+                });
+            }
+        }
+        _ => {}
+    }
+}
 
 // Type alias for the data needed by the renderer
-pub type SystemData<'a> = ReadStorage<'a, UiComponent>;
+pub type SystemData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, UiComponent>,
+    Read<'a, UiState>,
+    Read<'a, AssetIndex>,
+);
+
+/// Queues the shown elements on the UI layer, ordered by their depth in the tree
+pub fn draw(
+    queue: &mut RenderQueue,
+    font: &dyn TextMetrics,
+    data: SystemData,
+) -> Result<(), String> {
+    let (entities, nodes, state, index) = data;
+    let mut shown: Vec<(Entity, &UiComponent)> = (&entities, &nodes)
+        .join()
+        .filter(|(_, node)| node.shown)
+        .collect();
+    shown.sort_by_key(|(_, node)| node.depth);
+
+    for (entity, node) in shown {
+        let bounds = node.bounds;
+        let z = node.depth as i32;
+        match &node.widget {
+            // Drawn by the minimap, action bar and inventory modules, which know the world
+            Widget::Container
+            | Widget::Minimap { .. }
+            | Widget::ActionSlot { .. }
+            | Widget::ItemSlot { .. } => {}
+            Widget::Panel { frame, background } => {
+                if let Some(background) = background {
+                    queue.fill_rect(Layer::Ui, z, *background, bounds);
+                }
+                if let Some(frame) = frame {
+                    draw_nine_slice(queue, Layer::Ui, z, frame, bounds);
+                }
+            }
+            Widget::Label { text, color } => {
+                draw_text(queue, z, font, text, *color, bounds.top_left())?;
+            }
+            Widget::Paragraph {
+                text,
+                color,
+                revealed,
+            } => {
+                let line_height = font.line_height();
+                let mut remaining = *revealed;
+                for (index, line) in wrap_text(font, text, bounds.width())?.iter().enumerate() {
+                    if remaining == 0 {
+                        break;
+                    }
+                    let visible: String = line.chars().take(remaining).collect();
+                    remaining = remaining.saturating_sub(line.chars().count() + 1);
+                    draw_text(
+                        queue,
+                        z,
+                        font,
+                        &visible,
+                        *color,
+                        bounds.top_left().offset(0, index as i32 * line_height),
+                    )?;
+                }
+            }
+            Widget::Image {
+                spritesheet,
+                region,
+            } => {
+                // Keep the aspect ratio of the region
+                let scale = (bounds.width() as f32 / region.width() as f32)
+                    .min(bounds.height() as f32 / region.height() as f32);
+                let target = Rect::from_center(
+                    bounds.center(),
+                    (region.width() as f32 * scale) as u32,
+                    (region.height() as f32 * scale) as u32,
+                );
+                queue.copy(Layer::Ui, z, *spritesheet, *region, target);
+            }
+            Widget::Bar { value, max, color } => {
+                queue.fill_rect(Layer::Ui, z, Color::RGB(20, 20, 20), bounds);
+                let filled_fraction = fraction(*value, *max);
+                let filled = (bounds.width() as f32 * filled_fraction).round() as u32;
+                if filled > 0 {
+                    queue.fill_rect(
+                        Layer::Ui,
+                        z,
+                        color.unwrap_or_else(|| health_color(filled_fraction)),
+                        Rect::new(bounds.x(), bounds.y(), filled, bounds.height()),
+                    );
+                }
+                if bounds.height() as i32 >= font.height() {
+                    let numbers = format!("{} / {}", value, max);
+                    let (text_width, text_height) = font.text_size(&numbers)?;
+                    let text_rect = Rect::from_center(bounds.center(), text_width, text_height);
+                    draw_text(queue, z, font, &numbers, Color::WHITE, text_rect.top_left())?;
+                }
+            }
+            Widget::Button { label, .. } => {
+                let hovered = state.hovered == Some(entity);
+                let pressed = hovered && state.pressed == Some(entity);
+                let style = button_style(&index, hovered, pressed);
+                draw_nine_slice(queue, Layer::Ui, z, &style, bounds);
+                let (text_width, text_height) = font.text_size(label)?;
+                let text_rect = Rect::from_center(bounds.center(), text_width, text_height);
+                draw_text(queue, z, font, label, Color::WHITE, text_rect.top_left())?;
+            }
+            Widget::List {
+                items, selected, ..
+            } => {
+                for (index, item) in items.iter().enumerate() {
+                    let item_rect = Rect::new(
+                        bounds.x(),
+                        bounds.y() + index as i32 * LIST_ITEM_HEIGHT,
+                        bounds.width(),
+                        LIST_ITEM_HEIGHT as u32,
+                    );
+                    if item_rect.bottom() > bounds.bottom() {
+                        break;
+                    }
+                    if *selected == Some(index) {
+                        queue.fill_rect(Layer::Ui, z, Color::RGB(60, 60, 120), item_rect);
+                    }
+                    draw_text(
+                        queue,
+                        z,
+                        font,
+                        item,
+                        Color::WHITE,
+                        item_rect.top_left().offset(TEXT_PADDING, 1),
+                    )?;
+                }
+            }
+        }
+        if state.focused == Some(entity) {
+            queue.draw_rect(Layer::Ui, z, Color::YELLOW, bounds);
+        }
+    }
+
+    Ok(())
+}
 
-pub fn draw_to_canvas(
-    canvas: &mut WindowCanvas,
-    background: Color,
-    _data: SystemData,
+/// Stretches a nine-slice frame over `target`
+pub fn draw_nine_slice(
+    queue: &mut RenderQueue,
+    layer: Layer,
+    z: i32,
+    frame: &NineSlice,
+    target: Rect,
+) {
+    let source = frame.source;
+    let inset = frame.inset as i32;
+    let border = (frame.border as i32)
+        .min(target.width() as i32 / 2)
+        .min(target.height() as i32 / 2);
+    let source_xs = [
+        source.left(),
+        source.left() + inset,
+        source.right() - inset,
+        source.right(),
+    ];
+    let source_ys = [
+        source.top(),
+        source.top() + inset,
+        source.bottom() - inset,
+        source.bottom(),
+    ];
+    let target_xs = [
+        target.left(),
+        target.left() + border,
+        target.right() - border,
+        target.right(),
+    ];
+    let target_ys = [
+        target.top(),
+        target.top() + border,
+        target.bottom() - border,
+        target.bottom(),
+    ];
+
+    for row in 0..3 {
+        for column in 0..3 {
+            let target_width = target_xs[column + 1] - target_xs[column];
+            let target_height = target_ys[row + 1] - target_ys[row];
+            if target_width <= 0 || target_height <= 0 {
+                continue;
+            }
+            queue.copy(
+                layer,
+                z,
+                frame.texture,
+                Rect::new(
+                    source_xs[column],
+                    source_ys[row],
+                    (source_xs[column + 1] - source_xs[column]) as u32,
+                    (source_ys[row + 1] - source_ys[row]) as u32,
+                ),
+                Rect::new(
+                    target_xs[column],
+                    target_ys[row],
+                    target_width as u32,
+                    target_height as u32,
+                ),
+            );
+        }
+    }
+}
+
+/// Splits `text` into lines no wider than `width`, breaking between words. The lines
+/// together contain every character of `text` except the spaces they were split at.
+pub fn wrap_text(font: &dyn TextMetrics, text: &str, width: u32) -> Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        let (candidate_width, _) = font.text_size(&candidate)?;
+        if candidate_width > width && !line.is_empty() {
+            lines.push(line);
+            line = word.to_string();
+        } else {
+            line = candidate;
+        }
+    }
+    lines.push(line);
+
+    Ok(lines)
+}
+
+/// Queues a single line of text on the UI layer with its top left corner at `position`
+pub fn draw_text(
+    queue: &mut RenderQueue,
+    z: i32,
+    font: &dyn TextMetrics,
+    text: &str,
+    color: Color,
+    position: Point,
 ) -> Result<(), String> {
-    canvas.set_draw_color(background);
-    canvas.fill_rect(Rect::new(
-        0,
-        (DIMENSION.height - 100) as i32,
-        DIMENSION.width as u32,
-        100,
-    ))?;
+    if text.is_empty() {
+        return Ok(());
+    }
+    let (width, height) = font.text_size(text)?;
+    queue.text(
+        Layer::Ui,
+        z,
+        text,
+        color,
+        Rect::new(position.x(), position.y(), width, height),
+    );
 
     Ok(())
 }
+
+/// Where a line of text centered on `center` and scaled by `scale` is drawn
+pub fn scaled_text_rect(
+    font: &dyn TextMetrics,
+    text: &str,
+    center: Point,
+    scale: f32,
+) -> Result<Rect, String> {
+    let (width, height) = font.text_size(text)?;
+    Ok(Rect::from_center(
+        center,
+        (width as f32 * scale) as u32,
+        (height as f32 * scale) as u32,
+    ))
+}
+
+/// Creates the panel along the bottom of the window that holds the chat
+pub fn create_bottom_panel(world: &mut World) -> Entity {
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: None,
+                    background: Some(Color::RGB(65, 255, 255)),
+                },
+                (0, BOTTOM_PANEL_HEIGHT),
+            )
+            .anchored(Anchor::Bottom, Point::new(0, 0)),
+        )
+        .build()
+}
+
+/// The menu toggled with Escape
+pub struct GameMenu {
+    pub panel: Entity,
+    pub resume: Entity,
+}
+
+pub fn create_game_menu(world: &mut World) -> GameMenu {
+    let frame = frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (220, 150),
+            )
+            .anchored(Anchor::Center, Point::new(0, -50))
+            .with_layout(Layout::Vertical, 20, 10)
+            .hidden(),
+        )
+        .build();
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: "Menu".to_string(),
+                    color: Color::WHITE,
+                },
+                (40, 20),
+            )
+            .with_parent(panel, 0)
+            .anchored(Anchor::Top, Point::new(0, 0)),
+        )
+        .build();
+    let resume = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Button {
+                    label: "Resume".to_string(),
+                    action: MENU_RESUME_ACTION.to_string(),
+                },
+                (0, 30),
+            )
+            .with_parent(panel, 1),
+        )
+        .build();
+    world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Button {
+                    label: "Quit".to_string(),
+                    action: MENU_QUIT_ACTION.to_string(),
+                },
+                (0, 30),
+            )
+            .with_parent(panel, 2),
+        )
+        .build();
+
+    GameMenu { panel, resume }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_menu() -> (World, GameMenu) {
+        let mut world = World::new();
+        world.register::<UiComponent>();
+        world.insert(AssetIndex::default());
+        let menu = create_game_menu(&mut world);
+        world
+            .write_storage::<UiComponent>()
+            .get_mut(menu.panel)
+            .unwrap()
+            .visible = true;
+        System::setup(&mut UiLayout, &mut world);
+        UiLayout.run_now(&world);
+        (world, menu)
+    }
+
+    fn key(keycode: Keycode) -> Event {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        }
+    }
+
+    fn action(world: &World, entity: Option<Entity>) -> Option<String> {
+        match &world.read_storage::<UiComponent>().get(entity?)?.widget {
+            Widget::Button { action, .. } => Some(action.clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn clicking_a_button_does_not_focus_it() {
+        let (world, menu) = open_menu();
+        let center = world
+            .read_storage::<UiComponent>()
+            .get(menu.resume)
+            .unwrap()
+            .bounds
+            .center();
+        let click = Event::MouseButtonDown {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mouse_btn: MouseButton::Left,
+            clicks: 1,
+            x: center.x(),
+            y: center.y(),
+        };
+        assert!(handle_event(&click, world.system_data()));
+        let state = world.read_resource::<UiState>();
+        assert_eq!(state.pressed, Some(menu.resume));
+        assert_eq!(state.focused, None);
+    }
+
+    #[test]
+    fn arrow_keys_move_the_focus_between_buttons() {
+        let (world, menu) = open_menu();
+        assert!(!handle_event(&key(Keycode::Down), world.system_data()));
+
+        world.write_resource::<UiState>().focused = Some(menu.resume);
+        assert!(handle_event(&key(Keycode::Down), world.system_data()));
+        let focused = world.read_resource::<UiState>().focused;
+        assert_eq!(action(&world, focused).as_deref(), Some(MENU_QUIT_ACTION));
+
+        handle_event(&key(Keycode::Down), world.system_data());
+        assert_eq!(world.read_resource::<UiState>().focused, Some(menu.resume));
+        handle_event(&key(Keycode::Up), world.system_data());
+        handle_event(&key(Keycode::Return), world.system_data());
+        let events: Vec<UiEvent> = world.write_resource::<UiState>().events.drain(..).collect();
+        assert!(matches!(
+            events.as_slice(),
+            [UiEvent::Clicked { action }] if action == MENU_QUIT_ACTION
+        ));
+    }
+
+    #[test]
+    fn dragging_an_item_out_only_confirms_with_shift_held() {
+        let mut world = World::new();
+        System::setup(&mut UiLayout, &mut world);
+        let widget = Widget::ItemSlot {
+            slot: 3,
+            action: crate::inventory::BAG_ACTION.to_string(),
+        };
+        world
+            .create_entity()
+            .with(UiComponent::new(widget, (36, 36)))
+            .build();
+        UiLayout.run_now(&world);
+
+        let drag_out = |world: &World| {
+            for (x, y, down) in [(10, 10, true), (400, 400, false)] {
+                let event = if down {
+                    Event::MouseButtonDown {
+                        timestamp: 0,
+                        window_id: 0,
+                        which: 0,
+                        mouse_btn: MouseButton::Left,
+                        clicks: 1,
+                        x,
+                        y,
+                    }
+                } else {
+                    Event::MouseButtonUp {
+                        timestamp: 0,
+                        window_id: 0,
+                        which: 0,
+                        mouse_btn: MouseButton::Left,
+                        clicks: 1,
+                        x,
+                        y,
+                    }
+                };
+                handle_event(&event, world.system_data());
+            }
+            world.write_resource::<UiState>().events.pop_front()
+        };
+        assert!(matches!(
+            drag_out(&world),
+            Some(UiEvent::Discarded {
+                slot: 3,
+                confirmed: false,
+                ..
+            })
+        ));
+
+        let shift = Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(Keycode::LShift),
+            scancode: None,
+            keymod: Mod::LSHIFTMOD,
+            repeat: false,
+        };
+        assert!(!handle_event(&shift, world.system_data()));
+        assert!(matches!(
+            drag_out(&world),
+            Some(UiEvent::Discarded {
+                slot: 3,
+                confirmed: true,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use specs::prelude::*;
+
+use crate::{assets::AssetIndex, components::*, ui};
+
+const MANA_COLOR: Color = Color::RGB(40, 90, 230);
+const ENERGY_COLOR: Color = Color::RGB(230, 200, 40);
+const RAGE_COLOR: Color = Color::RGB(200, 30, 30);
+
+/// The UI elements of a single unit frame
+pub struct UnitFrame {
+    pub panel: Entity,
+    pub name: Entity,
+    pub health: Entity,
+    pub resource: Entity,
+}
+
+/// The unit frames of the player and its target
+pub struct UnitFrames {
+    pub player: UnitFrame,
+    pub target: UnitFrame,
+}
+
+fn create_unit_frame(world: &mut World, offset: Point) -> UnitFrame {
+    let frame = ui::frame_style(&world.read_resource::<AssetIndex>());
+    let panel = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Panel {
+                    frame: Some(frame),
+                    background: Some(Color::RGB(30, 30, 40)),
+                },
+                (200, 66),
+            )
+            .anchored(Anchor::TopLeft, offset)
+            .with_layout(Layout::Vertical, 10, 3)
+            .hidden(),
+        )
+        .build();
+    let name = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Label {
+                    text: String::new(),
+                    color: Color::WHITE,
+                },
+                (0, 16),
+            )
+            .with_parent(panel, 0),
+        )
+        .build();
+    let health = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Bar {
+                    value: 0,
+                    max: 0,
+                    color: None,
+                },
+                (0, 16),
+            )
+            .with_parent(panel, 1),
+        )
+        .build();
+    let resource = world
+        .create_entity()
+        .with(
+            UiComponent::new(
+                Widget::Bar {
+                    value: 0,
+                    max: 0,
+                    color: Some(MANA_COLOR),
+                },
+                (0, 8),
+            )
+            .with_parent(panel, 2),
+        )
+        .build();
+
+    UnitFrame {
+        panel,
+        name,
+        health,
+        resource,
+    }
+}
+
+pub fn create_unit_frames(world: &mut World) -> UnitFrames {
+    UnitFrames {
+        player: create_unit_frame(world, Point::new(10, 10)),
+        target: create_unit_frame(world, Point::new(220, 10)),
+    }
+}
+
+/// Keeps the unit frames in sync with the status of the player and its target
+pub struct UnitFrameSystem;
+
+impl<'a> System<'a> for UnitFrameSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Status>,
+        Write<'a, Target>,
+        ReadExpect<'a, UnitFrames>,
+        WriteStorage<'a, UiComponent>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, players, statuses, mut target, frames, mut nodes): Self::SystemData,
+    ) {
+        if let Some(entity) = target.0 {
+            if !entities.is_alive(entity) {
+                target.0 = None;
+            }
+        }
+
+        let player = local_player(&entities, &players);
+        sync_frame(&frames.player, player, &players, &statuses, &mut nodes);
+        sync_frame(&frames.target, target.0, &players, &statuses, &mut nodes);
+    }
+}
+
+fn sync_frame(
+    frame: &UnitFrame,
+    unit: Option<Entity>,
+    players: &ReadStorage<Player>,
+    statuses: &ReadStorage<Status>,
+    nodes: &mut WriteStorage<UiComponent>,
+) {
+    let status = unit.and_then(|unit| statuses.get(unit));
+    if let Some(panel) = nodes.get_mut(frame.panel) {
+        panel.visible = status.is_some();
+    }
+    let (unit, status) = match (unit, status) {
+        (Some(unit), Some(status)) => (unit, status),
+        _ => return,
+    };
+
+    if let Some(Widget::Label { text, .. }) = nodes.get_mut(frame.name).map(|n| &mut n.widget) {
+        *text = players
+            .get(unit)
+            .map(|player| {
+                if player.char_name.is_empty() {
+                    player.id.clone()
+                } else {
+                    player.char_name.clone()
+                }
+            })
+            .unwrap_or_default();
+    }
+    if let Some(Widget::Bar { value, max, .. }) = nodes.get_mut(frame.health).map(|n| &mut n.widget)
+    {
+        *value = status.health;
+        *max = status.max_health;
+    }
+    if let Some(node) = nodes.get_mut(frame.resource) {
+        node.visible = status.power.is_some();
+        if let (Widget::Bar { value, max, color }, Some(resource)) =
+            (&mut node.widget, &status.power)
+        {
+            *value = resource.value;
+            *max = resource.max;
+            *color = Some(match resource.kind {
+                PowerKind::Mana => MANA_COLOR,
+                PowerKind::Energy => ENERGY_COLOR,
+                PowerKind::Rage => RAGE_COLOR,
+            });
+        }
+    }
+}
+
+pub type TargetData<'a> = (Entities<'a>, ReadStorage<'a, Player>, Write<'a, Target>);
+
+/// Selects the next other player as target, wrapping around after the last one
+pub fn cycle_target(data: TargetData) {
+    let (entities, players, mut target) = data;
+    let candidates: Vec<Entity> = (&entities, &players)
+        .join()
+        .filter(|(_, player)| !player.is_local())
+        .map(|(entity, _)| entity)
+        .collect();
+    let next = match target
+        .0
+        .and_then(|current| candidates.iter().position(|entity| *entity == current))
+    {
+        Some(index) => candidates.get(index + 1).or_else(|| candidates.first()),
+        None => candidates.first(),
+    };
+    target.0 = next.copied();
+}
@@ -0,0 +1,104 @@
+use std::env;
+
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::video::FullscreenType;
+
+use crate::DIMENSION;
+
+/// Widest aspect ratio shown, wider windows get bars at the sides
+const MAX_ASPECT: (u32, u32) = (21, 9);
+
+/// The area everything is laid out in, in logical pixels. The canvas scales it up to the
+/// window, so the layout is the same on every screen resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            width: DIMENSION.width,
+            height: DIMENSION.height,
+        }
+    }
+}
+
+impl Viewport {
+    /// The viewport for a window of the given size in pixels. The height is always
+    /// `DIMENSION.height`, wider windows see more of the world to the sides. Windows
+    /// narrower than `DIMENSION` or wider than `MAX_ASPECT` are letterboxed.
+    pub fn for_window(width: u32, height: u32) -> Self {
+        let logical_width = (width as u64 * DIMENSION.height as u64 / height.max(1) as u64) as u32;
+        let max_width = DIMENSION.height * MAX_ASPECT.0 / MAX_ASPECT.1;
+        Self {
+            width: logical_width.clamp(DIMENSION.width, max_width),
+            height: DIMENSION.height,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+}
+
+/// How the window is opened, read from `GAME_FULLSCREEN`
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayConfig {
+    /// Start in borderless fullscreen at the resolution of the desktop
+    pub fullscreen: bool,
+}
+
+impl DisplayConfig {
+    /// `GAME_FULLSCREEN=1` starts in fullscreen, F11 toggles it while playing
+    pub fn from_env() -> Self {
+        let fullscreen = matches!(
+            env::var("GAME_FULLSCREEN").as_deref(),
+            Ok("1") | Ok("true") | Ok("on")
+        );
+        Self { fullscreen }
+    }
+}
+
+/// Scales the canvas to the viewport of its current output size. Called whenever the
+/// window changes size.
+pub fn fit(canvas: &mut WindowCanvas) -> Result<Viewport, String> {
+    let (width, height) = canvas.output_size()?;
+    let viewport = Viewport::for_window(width, height);
+    canvas
+        .set_logical_size(viewport.width, viewport.height)
+        .map_err(|e| e.to_string())?;
+    Ok(viewport)
+}
+
+/// Switches between a window and borderless fullscreen
+pub fn toggle_fullscreen(canvas: &mut WindowCanvas) -> Result<(), String> {
+    let window = canvas.window_mut();
+    let mode = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    window.set_fullscreen(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wider_windows_see_more_of_the_world() {
+        let viewport = Viewport::for_window(1920, 1080);
+        assert_eq!((viewport.width, viewport.height), (1066, DIMENSION.height));
+        assert_eq!(Viewport::for_window(1600, 1200), Viewport::default());
+    }
+
+    #[test]
+    fn narrow_and_ultrawide_windows_are_letterboxed() {
+        assert_eq!(Viewport::for_window(600, 1200), Viewport::default());
+        let viewport = Viewport::for_window(5120, 1080);
+        assert_eq!(viewport.width, DIMENSION.height * 21 / 9);
+        assert_eq!(Viewport::for_window(0, 0).height, DIMENSION.height);
+    }
+}